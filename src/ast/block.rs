@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc, sync::atomic::{AtomicU64, Ordering}};
 
-use super::{func::FuncDef, stmt::Stmt};
+use super::{func::FuncDef, span::Span, stmt::Stmt};
 
 pub type BlockId = u64;
 
@@ -11,6 +11,12 @@ pub static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 #[derive(Debug)]
 pub struct Block {
   pub stmts: Vec<Stmt>,
+
+  /// 每条语句在源文件中的位置，与 stmts 一一对应
+  pub spans: Vec<Span>,
+
+  /// Block 在源文件中的位置，包括两侧的花括号
+  pub span: Span,
   
   /// 标识每一个 Block 的唯一 ID，parse 阶段原子自增给出
   pub block_id: BlockId,
@@ -27,9 +33,12 @@ pub struct Block {
 }
 
 impl Block {
-  pub fn new(stmts: Vec<Stmt>) -> Self {
+  pub fn new(stmts: Vec<(Stmt, Span)>, span: Span) -> Self {
+    let (stmts, spans) = stmts.into_iter().unzip();
     Block {
       stmts,
+      spans,
+      span,
       block_id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
       parent_id:Rc::new(RefCell::new(None)),
       while_: Rc::new(RefCell::new(false)),
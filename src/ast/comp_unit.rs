@@ -1,8 +1,10 @@
 use std::rc::Rc;
 
 use crate::ast::score::Score;
+use crate::ast::span::Span;
 
 use super::func::FuncDef;
+use super::instrument::InstrumentDef;
 use super::block::Block;
 use super::stmt::{ConstDecl, VarDecl};
 
@@ -11,10 +13,26 @@ pub enum Def {
   ConstDecl(ConstDecl),
   VarDecl(VarDecl),
   FuncDef(Rc<FuncDef>),
+  InstrumentDef(InstrumentDef),
+  TestDef(TestDef),
+}
+
+/// `test "name" { ... }` 定义的测试，只在执行测试时检查和执行
+#[derive(Debug)]
+pub struct TestDef {
+  pub name: String,
+
+  /// 测试的名称在源文件中的位置
+  pub span: Span,
+
+  pub block: Rc<Block>,
 }
 
 #[derive(Debug)]
 pub struct CompUnit {
   pub block: Rc<Block>,
+  pub instruments: Vec<InstrumentDef>,
+  pub tests: Vec<TestDef>,
+
   pub score: Score
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+use super::{expr::Expr, span::Span};
+
+/// 鼓组网格中一步的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrumStep {
+  /// '.' 或 '-' 休止
+  Rest,
+
+  /// 'x' 以默认力度击打
+  Hit,
+
+  /// 'X' 重音
+  Accent,
+
+  /// 'o' 轻击
+  Ghost,
+}
+
+/// 鼓组网格的一行，例如 `kick: "x...x...|x...x...";`
+#[derive(Debug)]
+pub struct DrumRow {
+  /// 这一行演奏的音高，通常是 kick、snare 等打击乐名称
+  pub note: Expr,
+
+  /// 每个字符为一步，'|' 分隔小节，空格被忽略
+  pub pattern: String,
+
+  /// 这一行在源文件中的位置
+  pub span: Span,
+}
+
+impl DrumRow {
+  /// 按小节解析 pattern，遇到无法识别的字符时返回该字符
+  pub fn measures(&self) -> Result<Vec<Vec<DrumStep>>, char> {
+    let mut measures = vec![];
+    for measure in self.pattern.split('|') {
+      let mut steps = vec![];
+      for c in measure.chars() {
+        steps.push(match c {
+          '.' | '-' => DrumStep::Rest,
+          'x' => DrumStep::Hit,
+          'X' => DrumStep::Accent,
+          'o' => DrumStep::Ghost,
+          ' ' => continue,
+          _ => return Err(c),
+        });
+      }
+      measures.push(steps);
+    }
+    Ok(measures)
+  }
+}
+
+/// 步进音序器形式的鼓组字面量，例如
+/// `drums(4) { kick: "x...x...x...x..."; snare: "....x.......x..."; }`，
+/// 它的值是一个 Phrase，每一行成为每个小节中的一个声部，通常交给 channel 9 演奏。
+#[derive(Debug)]
+pub struct DrumGrid {
+  /// 每拍的步数，必须是 2 的幂，缺省为 4
+  pub steps: Option<Expr>,
+
+  pub rows: Vec<DrumRow>,
+}
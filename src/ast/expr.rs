@@ -42,6 +42,9 @@ pub enum PrimaryExpr {
   LVal(LVal),
   Number(IntConst),
   FuncCall(FuncCall),
+
+  /// 字符串字面量
+  Str(String),
 }
 
 #[derive(Debug)]
@@ -84,4 +87,32 @@ pub struct LOrExpr {
   pub land_exps: Vec<LAndExpr>,
 }
 
-pub type Expr = LOrExpr;
\ No newline at end of file
+pub type Expr = LOrExpr;
+
+impl LOrExpr {
+  /// 若表达式仅由一个 PrimaryExpr 构成(没有任何运算)，返回该 PrimaryExpr
+  pub fn as_primary_expr(&self) -> Option<&PrimaryExpr> {
+    let [land_exp] = self.land_exps.as_slice() else { return None };
+    let [eq_exp] = land_exp.eq_exps.as_slice() else { return None };
+    let [rel_exp] = eq_exp.rel_exps.as_slice() else { return None };
+    let [add_exp] = rel_exp.add_exps.as_slice() else { return None };
+    let [mul_exp] = add_exp.mul_exps.as_slice() else { return None };
+    let [unary_exp] = mul_exp.unary_exps.as_slice() else { return None };
+    unary_exp.unary_ops.is_empty().then_some(&unary_exp.primary_exp)
+  }
+
+  /// 与 as_primary_expr 相同，但取得该 PrimaryExpr 的所有权
+  pub fn into_primary_expr(mut self) -> Option<PrimaryExpr> {
+    self.as_primary_expr()?;
+    let unary_exp = self.land_exps.pop()?.eq_exps.pop()?.rel_exps.pop()?.add_exps.pop()?.mul_exps.pop()?.unary_exps.pop()?;
+    Some(unary_exp.primary_exp)
+  }
+
+  /// 若表达式仅由一个标识符构成(没有任何运算和括号)，返回该左值
+  pub fn as_lval(&self) -> Option<&LVal> {
+    match self.as_primary_expr()? {
+      PrimaryExpr::LVal( lval ) => Some(lval),
+      _ => None,
+    }
+  }
+}
\ No newline at end of file
@@ -1,8 +1,9 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::ast::{stmt::AsgnRVal, val::Value};
+use crate::builtin::Builtin;
 
-use super::{block::Block, val::{BType, RVal}};
+use super::{block::Block, span::Span, val::{BType, RVal}};
 
 #[derive(Debug, Clone, Copy)]
 pub enum FuncType {
@@ -15,15 +16,19 @@ pub enum FuncType {
 pub struct FuncFParam {
   pub ident: String,
   pub rval: Rc<RVal>,
+
+  /// 标识符在源文件中的位置
+  pub span: Span,
 }
 
 impl FuncFParam {
   /// 初始化为类型 BType 默认值
-  pub fn new(btype: BType, ident: String) -> Self {
+  pub fn new(btype: BType, ident: String, span: Span) -> Self {
     let rval = RVal::new_with_btype(btype.clone());
     FuncFParam {
       ident,
       rval: Rc::new(rval),
+      span,
     }
   }
 
@@ -40,6 +45,9 @@ pub struct FuncDef {
   pub ident: String,
   pub func_fparams: Vec<FuncFParam>,
   pub block: Rc<Block>,
+
+  /// 函数名在源文件中的位置
+  pub span: Span,
 }
 
 #[derive(Debug)]
@@ -49,14 +57,22 @@ pub struct FuncCall {
 
   /// 语义检查阶段绑定的函数定义
   pub func_def: Rc<RefCell<Option<Rc<FuncDef>>>>,
+
+  /// 语义检查阶段绑定的内置函数，找不到用户定义的同名函数时才会绑定
+  pub builtin: Rc<RefCell<Option<Builtin>>>,
+
+  /// 函数调用在源文件中的位置
+  pub span: Span,
 }
 
 impl FuncCall {
-  pub fn new(ident: String, func_rparams: Vec<AsgnRVal>) -> Self {
+  pub fn new(ident: String, func_rparams: Vec<AsgnRVal>, span: Span) -> Self {
     FuncCall {
       ident,
       func_rparams,
+      span,
       func_def: Rc::new(RefCell::new(None)),
+      builtin: Rc::new(RefCell::new(None)),
     }
   }
 
@@ -68,4 +84,13 @@ impl FuncCall {
   pub fn get_func_def(&self) -> Rc<FuncDef> {
     self.func_def.borrow().as_ref().unwrap().clone()
   }
+
+  pub fn bind_builtin(&self, builtin: Builtin) {
+    *self.builtin.borrow_mut() = Some(builtin);
+  }
+
+  /// 获取绑定的内置函数
+  pub fn get_builtin(&self) -> Option<Builtin> {
+    *self.builtin.borrow()
+  }
 }
\ No newline at end of file
@@ -0,0 +1,23 @@
+use super::expr::Expr;
+use super::span::Span;
+
+/// 用内置合成器定义的乐器，例如
+/// `instrument pad { wave = saw; attack = 200; release = 800; cutoff = 2000; }`。
+/// channel 可以像 General MIDI 乐器一样用 `@0 -> pad;` 绑定它，
+/// 渲染音频时使用这里定义的音色，输出 midi 时使用 program 给出的 General MIDI 乐器
+#[derive(Debug)]
+pub struct InstrumentDef {
+  pub ident: String,
+
+  /// 乐器名称在源文件中的位置
+  pub span: Span,
+
+  pub params: Vec<InstrumentParam>,
+}
+
+/// 乐器定义中的一个参数，例如 `attack = 200;`
+#[derive(Debug)]
+pub struct InstrumentParam {
+  pub name: String,
+  pub value: Expr,
+}
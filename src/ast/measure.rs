@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::ast::{func::FuncCall, note::{Note, NoteValue}, val::LVal};
 
 
@@ -13,6 +15,9 @@ pub enum MeasureUnit {
   /// '.' 休止符
   Rest,
 
+  /// '&' 声部分隔符,其后的单元作为一个新的声部从小节开头重新计时
+  VoiceSeparator,
+
   /// 单个音符
   Note(Note)
 }
@@ -44,6 +49,9 @@ pub enum MeasureUnitValue {
   /// '.' 休止符
   Rest,
 
+  /// '&' 声部分隔符,其后的单元作为一个新的声部从小节开头重新计时
+  VoiceSeparator,
+
   /// 单个音符
   NoteValue(NoteValue)
 }
@@ -58,4 +66,40 @@ impl Measure {
   pub fn a(&self) {
     // let a =  self.content.insert();
   }
+}
+
+impl MeasureValue {
+  /// 按声部分隔符拆分小节内容,每个声部都从小节开头开始。
+  /// 没有声部分隔符的小节只有一个声部。
+  pub fn voices(&self) -> Vec<&[MeasureUnitValue]> {
+    self.content.split(|unit| *unit == MeasureUnitValue::VoiceSeparator).collect()
+  }
+}
+
+impl fmt::Display for MeasureUnitValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      MeasureUnitValue::TimeDilation => write!(f, "<"),
+      MeasureUnitValue::TimeCompression => write!(f, ">"),
+      MeasureUnitValue::Rest => write!(f, "."),
+      MeasureUnitValue::VoiceSeparator => write!(f, "&"),
+      MeasureUnitValue::NoteValue( note ) => write!(f, "{note}"),
+    }
+  }
+}
+
+/// 以 yam 字面量的形式显示，如 `| 60, 64=2 & 48 |`
+impl fmt::Display for MeasureValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "|")?;
+    for (i, voice) in self.voices().into_iter().enumerate() {
+      if i > 0 {
+        write!(f, " &")?;
+      }
+      for (j, unit) in voice.iter().enumerate() {
+        write!(f, "{} {unit}", if j > 0 { "," } else { "" })?;
+      }
+    }
+    write!(f, " |")
+  }
 }
\ No newline at end of file
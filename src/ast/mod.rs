@@ -5,7 +5,11 @@ pub mod stmt;
 pub mod func;
 pub mod comp_unit;
 pub mod note;
+pub mod drum;
 pub mod measure;
 pub mod phrase;
 pub mod track;
-pub mod score;
\ No newline at end of file
+pub mod score;
+pub mod instrument;
+pub mod span;
+pub mod repl;
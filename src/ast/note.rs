@@ -1,4 +1,7 @@
-use super::expr::Expr;
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::{expr::Expr, span::Span};
 
 /// 代表一个音符(可以是和弦)
 #[derive(Debug)]
@@ -7,11 +10,74 @@ pub struct Note {
 
   // 表示音符的延长(倍数),在特定的 Measure 中才有意义
   pub len: Option<Expr>,
+
+  /// 音高偏移的音分，在特定的 Measure 中才有意义
+  pub cents: Option<Expr>,
+
+  /// 附在音符上的歌词音节，在特定的 Measure 中才有意义
+  pub syllable: Option<String>,
+
+  /// 音符字面量在源文件中的位置
+  pub span: Span,
 }
 
 /// 表达式都被计算好后的 Note 值
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct NoteValue {
   pub notes: Vec<i32>,
   pub len: Option<i32>,
-}
\ No newline at end of file
+
+  /// 音符的力度，为 None 时使用默认力度
+  pub velocity: Option<i32>,
+
+  /// 音高偏移的音分，为 None 时不偏移
+  pub cents: Option<i32>,
+
+  /// 音符开始时输出的歌词音节
+  pub syllable: Option<String>,
+
+  /// 产生该音符的音符字面量或函数调用在源文件中的位置，不参与值的比较
+  pub span: Option<Span>,
+}
+
+impl PartialEq for NoteValue {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for NoteValue {}
+
+impl PartialOrd for NoteValue {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for NoteValue {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.notes.cmp(&other.notes)
+      .then_with(|| self.len.cmp(&other.len))
+      .then_with(|| self.velocity.cmp(&other.velocity))
+      .then_with(|| self.cents.cmp(&other.cents))
+      .then_with(|| self.syllable.cmp(&other.syllable))
+  }
+}
+
+/// 以 yam 字面量的形式显示，如 `60'64 ~ 50=2 "la"`。力度没有对应的字面量，不显示
+impl fmt::Display for NoteValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let notes: Vec<_> = self.notes.iter().map(|note| note.to_string()).collect();
+    write!(f, "{}", notes.join("'"))?;
+    if let Some( cents ) = self.cents {
+      write!(f, " ~ {cents}")?;
+    }
+    if let Some( len ) = self.len {
+      write!(f, "={len}")?;
+    }
+    if let Some( syllable ) = &self.syllable {
+      write!(f, " \"{syllable}\"")?;
+    }
+    Ok(())
+  }
+}
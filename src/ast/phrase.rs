@@ -1,4 +1,6 @@
-use crate::ast::{func::FuncCall, measure::{MeasureRVal, MeasureValue}, val::LVal};
+use std::fmt;
+
+use crate::ast::{drum::DrumGrid, func::FuncCall, measure::{MeasureRVal, MeasureValue}, val::LVal};
 
 
 /// 代表一个乐句，包括可选的小节属性，乐句的小节属性会作为所有包含的小节的默认属性，并被小节的属性覆盖。
@@ -11,6 +13,7 @@ pub struct Phrase {
 #[derive(Debug)]
 pub enum PhraseRVal {
   Phrase(Phrase),
+  DrumGrid(DrumGrid),
   LVal(LVal),
   FuncCall(FuncCall)
 }
@@ -19,4 +22,15 @@ pub enum PhraseRVal {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PhraseValue {
   pub content: Vec<MeasureValue>,
+}
+
+/// 以 yam 字面量的形式显示，如 `[ | 60, 62 | | 64=2 | ]`
+impl fmt::Display for PhraseValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[")?;
+    for measure in &self.content {
+      write!(f, " {measure}")?;
+    }
+    write!(f, " ]")
+  }
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+use super::instrument::InstrumentDef;
+use super::score::Score;
+use super::span::Span;
+use super::stmt::{AsgnRVal, Stmt};
+
+/// 交互式解释器一次输入中的一项：函数定义、乐器定义、常量和变量声明或其他语句
+#[derive(Debug)]
+pub enum ReplItem {
+  Stmt(Stmt),
+  InstrumentDef(InstrumentDef),
+}
+
+/// 交互式解释器的一次输入，作为增量的编译单元在持久的全局作用域中检查和执行
+#[derive(Debug)]
+pub enum ReplUnit {
+  /// 依次检查和执行的若干项及其在输入中的位置
+  Items(Vec<(ReplItem, Span)>),
+
+  /// 求值并显示结果的表达式或音乐字面量，不以分号结尾
+  Eval(AsgnRVal),
+
+  /// 替换当前的乐谱
+  Score(Score),
+}
@@ -1,20 +1,84 @@
 use std::rc::Rc;
 
-use crate::ast::{block::Block, expr::Expr, track::TrackRVal};
+use crate::ast::{block::Block, expr::{Expr, PrimaryExpr}, span::Span, track::TrackRVal};
 
 
-/// 将指定 Channel 的输入设为一个 Track
+/// 拍的位置，可以是整数表达式或小数字面量
+#[derive(Debug)]
+pub enum Beat {
+  Expr(Expr),
+
+  /// 小数字面量，以分数 (分子, 分母) 保存以免浮点误差，例如 3.5 保存为 (35, 10)
+  Decimal(i64, i64),
+}
+
+/// Track 在乐谱时间轴上的绝对位置，小节和拍都从 1 开始计数
+#[derive(Debug)]
+pub enum TrackPosition {
+  /// `at bar N [beat B]` 第 N 小节(的第 B 拍)
+  Bar(Expr, Option<Beat>),
+
+  /// `at beat B` 从乐谱开头计算的第 B 拍
+  Beat(Beat),
+}
+
+/// 将指定 Channel 的输入设为一个或多个 Track。
+/// 多个 Track 从同一起点同时开始演奏，之后的 Track 从其中最长者结束处继续。
+/// 指定了 position 时从该绝对位置开始，与已有内容按绝对时间合并。
 #[derive(Debug)]
 pub struct SetChannelTrack {
   pub channel: Expr,
-  pub track: TrackRVal,
+  pub tracks: Vec<TrackRVal>,
+  pub position: Option<TrackPosition>,
+}
+
+/// 乐器，可以是 GeneralMidi 编号或乐器名称
+#[derive(Debug)]
+pub enum Instrument {
+  /// 整数表达式给出的编号，或字符串表达式给出的乐器名称。
+  /// 若表达式只是一个未定义的标识符(如 violin)，语义检查阶段将其作为乐器名称解析
+  Program(Expr),
+
+  /// 字符串字面量给出的乐器名称，如 "Acoustic Grand Piano"
+  Name(String),
+}
+
+impl Instrument {
+  /// 字符串字面量作为乐器名称，其余表达式留到语义检查和执行时再确定
+  pub fn new(instrument: Expr) -> Self {
+    match instrument.as_primary_expr() {
+      Some(PrimaryExpr::Str( name )) => Instrument::Name(name.clone()),
+      _ => Instrument::Program(instrument),
+    }
+  }
 }
 
 /// 设置指定 Channel 的 Midi 乐器
 #[derive(Debug)]
 pub struct SetChannelInstrument {
   pub channel: Expr,
-  pub instrument: Expr,
+  pub instrument: Instrument,
+}
+
+/// 设置指定 Channel 的 track 名称或乐器名称，输出为该 channel 的 track 开头的 meta event
+#[derive(Debug)]
+pub struct SetChannelName {
+  pub channel: Expr,
+  pub name: Expr,
+}
+
+/// 设置指定 Channel 的音量或声像，取值 0-127，输出为该 channel 的 track 开头的 control change
+#[derive(Debug)]
+pub struct SetChannelControl {
+  pub channel: Expr,
+  pub value: Expr,
+}
+
+/// 在乐谱的绝对位置上添加文字，输出到 meta track
+#[derive(Debug)]
+pub struct AddText {
+  pub text: Expr,
+  pub position: TrackPosition,
 }
 
 /// 设置节拍类型
@@ -28,18 +92,140 @@ pub struct SetTimeSignature {
 }
 
 
+/// 调式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMode {
+  Major,
+  Minor,
+}
+
+/// 设置调号，如 `Eb major`、`F# minor`。
+/// 指定了 position 时从该绝对位置开始转调，否则位于乐谱开头
+#[derive(Debug)]
+pub struct SetKeySignature {
+  /// 主音，字母 A-G 后可跟 b(降) 或 #(升)
+  pub tonic: String,
+  pub mode: KeyMode,
+  pub position: Option<TrackPosition>,
+}
+
+impl SetKeySignature {
+  /// 调号在五度圈上的位置，即升号(正数)或降号(负数)的个数，主音不合法时返回 None。
+  /// 合法的调号范围为 -7 到 7
+  pub fn fifths(&self) -> Option<i32> {
+    let mut chars = self.tonic.chars();
+    let letter = match chars.next()?.to_ascii_uppercase() {
+      'F' => -1,
+      'C' => 0,
+      'G' => 1,
+      'D' => 2,
+      'A' => 3,
+      'E' => 4,
+      'B' => 5,
+      _ => return None,
+    };
+    let accidental = match chars.as_str() {
+      "" => 0,
+      "#" => 7,
+      "b" => -7,
+      _ => return None,
+    };
+    // 小调与其关系大调(高小三度)的调号相同
+    let mode = match self.mode {
+      KeyMode::Major => 0,
+      KeyMode::Minor => -3,
+    };
+    Some(letter + accidental + mode)
+  }
+}
+
+/// 音律的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuningKind {
+  /// `equal` 十二平均律
+  Equal,
+
+  /// `just` 五限纯律
+  Just,
+
+  /// `meantone` 四分之一音差中全音律
+  Meantone,
+
+  /// `cents(c1, c2, ..., period)` 除主音外各音级的音分，最后一个为周期
+  Cents,
+
+  /// `scala("file.scl")` 从 Scala 音阶文件读取
+  Scala,
+}
+
+/// 设置整首乐曲(打击乐 channel 9 除外)的音律，如 `@tuning = just root 62;`。
+/// root 为音级的起点，其音高与十二平均律相同，缺省为 60
+#[derive(Debug)]
+pub struct SetTuning {
+  pub name: String,
+  pub args: Vec<Expr>,
+  pub root: Option<Expr>,
+}
+
+impl SetTuning {
+  /// 由名称得到音律的种类，名称不合法时返回 None
+  pub fn kind(&self) -> Option<TuningKind> {
+    match self.name.as_str() {
+      "equal" => Some(TuningKind::Equal),
+      "just" => Some(TuningKind::Just),
+      "meantone" => Some(TuningKind::Meantone),
+      "cents" => Some(TuningKind::Cents),
+      "scala" => Some(TuningKind::Scala),
+      _ => None,
+    }
+  }
+}
+
 /// 仅在 Score 的 block 中出现的 channel 相关操作
 #[derive(Debug)]
 pub enum ScoreStmt {
   SetChannelTrack(SetChannelTrack),
   SetChannelInstrument(SetChannelInstrument),
   SetTimeSignature(SetTimeSignature),
-  SetTempo(Expr)
+  SetTempo(Expr),
+
+  /// 设置每个四分音符所占的 tick 数(PPQ)，必须在所有 Track 之前设置
+  SetPpq(Expr),
+
+  /// `@0 name = "Lead";` 设置 channel 的 track 名称
+  SetChannelName(SetChannelName),
+
+  /// `@0 instrument = "Nylon Guitar";` 设置 channel 显示的乐器名称，不影响实际音色
+  SetChannelInstrumentName(SetChannelName),
+
+  /// `@0 volume = 100;` 设置 channel 的音量(CC 7)
+  SetChannelVolume(SetChannelControl),
+
+  /// `@0 pan = 32;` 设置 channel 的声像(CC 10)，0 为最左，64 为中间，127 为最右
+  SetChannelPan(SetChannelControl),
+
+  /// `@title = "My Song";` 设置乐曲名称，即 meta track 的名称
+  SetTitle(Expr),
+
+  /// `@marker = "Chorus" at bar 9;` 排练记号
+  AddMarker(AddText),
+
+  /// `@cue = "Thunder" at bar 12 beat 3;` 提示点
+  AddCuePoint(AddText),
+
+  /// `@keysig = Eb major;` 调号，可以用 `at bar N` 在乐曲中途转调
+  SetKeySignature(SetKeySignature),
+
+  /// `@tuning = meantone;` 音律，只能设置一次
+  SetTuning(SetTuning),
 }
 
 /// 代表一个乐谱，也是程序入口
 #[derive(Debug)]
 pub struct Score {
   pub block: Rc<Block>,
-  pub channel_stmts: Vec<ScoreStmt>
+  pub channel_stmts: Vec<ScoreStmt>,
+
+  /// 每条 channel_stmt 在源文件中的位置，与 channel_stmts 一一对应
+  pub channel_spans: Vec<Span>,
 }
\ No newline at end of file
@@ -0,0 +1,12 @@
+/// 源文件中的一段位置，以字节偏移表示，左闭右开
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Self {
+    Span { start, end }
+  }
+}
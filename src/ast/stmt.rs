@@ -1,11 +1,13 @@
 use std::rc::Rc;
 
+use crate::ast::drum::DrumGrid;
 use crate::ast::measure::Measure;
 use crate::ast::note::Note;
 use crate::ast::phrase::Phrase;
 use crate::ast::track::Track;
 
 use super::block::Block;
+use super::span::Span;
 use super::val::RVal;
 use super::func::FuncDef;
 use super::{val::{BType, LVal}, expr::Expr};
@@ -17,11 +19,16 @@ pub enum AsgnRVal {
   Measure(Measure),
   Phrase(Phrase),
   Track(Track),
+  DrumGrid(DrumGrid),
 }
 
 #[derive(Debug)]
 pub struct ConstDef {
   pub ident: String,
+
+  /// 标识符在源文件中的位置
+  pub span: Span,
+
   pub rval: AsgnRVal,
 }
 
@@ -53,6 +60,10 @@ impl ConstDecl {
 #[derive(Debug)]
 pub struct VarDef {
   pub ident: String,
+
+  /// 标识符在源文件中的位置
+  pub span: Span,
+
   pub rval_: Option<AsgnRVal>,
 }
 
@@ -100,6 +111,15 @@ pub struct While {
   pub body: Box<Stmt>,
 }
 
+/// `assert(cond, "message");` 断言，只在执行测试时检查，条件为假时测试失败
+#[derive(Debug)]
+pub struct Assert {
+  pub cond: Expr,
+
+  /// 断言失败时显示的说明
+  pub message: Option<String>,
+}
+
 
 #[derive(Debug)]
 pub enum Stmt {
@@ -114,4 +134,5 @@ pub enum Stmt {
   Break,
   Continue,
   Return(Option<Expr>),
+  Assert(Assert),
 }
\ No newline at end of file
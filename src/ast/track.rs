@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::ast::{func::FuncCall, phrase::{PhraseRVal, PhraseValue}, val::LVal};
 
 
@@ -19,4 +21,15 @@ pub enum TrackRVal {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TrackValue {
   pub content: Vec<PhraseValue>,
+}
+
+/// 以 yam 字面量的形式显示，如 `{ [ | 60, 62 | ] [ | 64 | ] }`
+impl fmt::Display for TrackValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{{")?;
+    for phrase in &self.content {
+      write!(f, " {phrase}")?;
+    }
+    write!(f, " }}")
+  }
 }
\ No newline at end of file
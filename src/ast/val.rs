@@ -1,13 +1,15 @@
 use std::{cell::RefCell, fmt, rc::Rc};
 
 use crate::ast::{measure::MeasureValue, note::NoteValue, phrase::PhraseValue, track::TrackValue};
+use crate::ast::span::Span;
 
 /// 各个类型的默认初始值
 pub const INT_DEFAULT: i32 = 0;
-pub const NOTE_DEFAULT: NoteValue = NoteValue{notes: vec![], len: None};
+pub const NOTE_DEFAULT: NoteValue = NoteValue{notes: vec![], len: None, velocity: None, cents: None, syllable: None, span: None};
 pub const MEASURE_DEFAULT: MeasureValue = MeasureValue{content: vec![]};
 pub const PHRASE_DEFAULT: PhraseValue = PhraseValue{content: vec![]};
 pub const TRACK_DEFAULT: TrackValue = TrackValue{content: vec![]};
+pub const STR_DEFAULT: String = String::new();
 
 /// 所有的 Base Type 的定义
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -18,6 +20,7 @@ pub enum BType {
   Measure,
   Phrase,
   Track,
+  Str,
 }
 
 impl fmt::Display for BType {
@@ -26,9 +29,10 @@ impl fmt::Display for BType {
       BType::Int => write!(f, "int"),
       BType::Bool => write!(f, "bool"),
       BType::Note => write!(f, "note"),
-      BType::Measure => write!(f, "measute"),
+      BType::Measure => write!(f, "measure"),
       BType::Phrase => write!(f, "phrase"),
       BType::Track => write!(f, "track"),
+      BType::Str => write!(f, "string"),
     }
   }
 }
@@ -42,16 +46,33 @@ pub enum Value {
   Measure(MeasureValue),
   Phrase(PhraseValue),
   Track(TrackValue),
+  Str(String),
 }
 
+/// 以 yam 字面量的形式显示值
 impl fmt::Display for Value {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      Value::Int(_) => write!(f, "i32"),
-      Value::Note(_) => write!(f, "note"),
-      Value::Measure(_) => write!(f, "measute"),
-      Value::Phrase(_) => write!(f, "phrase"),
-      Value::Track(_) => write!(f, "track"),
+      Value::Int( int ) => write!(f, "{int}"),
+      Value::Note( note ) => write!(f, "{note}"),
+      Value::Measure( measure ) => write!(f, "{measure}"),
+      Value::Phrase( phrase ) => write!(f, "{phrase}"),
+      Value::Track( track ) => write!(f, "{track}"),
+      Value::Str( s ) => write!(f, "\"{s}\""),
+    }
+  }
+}
+
+impl Value {
+  /// 值的类型 BType
+  pub fn get_btype(&self) -> BType {
+    match self {
+      Value::Int(_) => BType::Int,
+      Value::Note(_) => BType::Note,
+      Value::Measure(_) => BType::Measure,
+      Value::Phrase(_) => BType::Phrase,
+      Value::Track(_) => BType::Track,
+      Value::Str(_) => BType::Str,
     }
   }
 }
@@ -61,14 +82,18 @@ impl fmt::Display for Value {
 pub struct LVal {
   pub ident: String,
 
+  /// 标识符在源文件中的位置
+  pub span: Span,
+
   /// 语义检查阶段，绑定该左值的右值
   pub rval: Rc<RefCell<Option<Rc<RVal>>>>,
 }
 
 impl LVal {
-  pub fn new(ident: String) -> Self {
+  pub fn new(ident: String, span: Span) -> Self {
     LVal {
       ident,
+      span,
       rval: Rc::new(RefCell::new(None)),
     }
   }
@@ -78,6 +103,7 @@ impl LVal {
     let rval = RVal::new_with_btype(btype);
     LVal {
       ident,
+      span: Span::default(),
       rval: Rc::new(RefCell::new(Some(Rc::new(rval)))),
     }
   }
@@ -114,19 +140,19 @@ impl RVal {
       BType::Measure => RVal{value: Rc::new(RefCell::new(Value::Measure(MEASURE_DEFAULT)))},
       BType::Phrase => RVal{value: Rc::new(RefCell::new(Value::Phrase(PHRASE_DEFAULT)))},
       BType::Track => RVal{value: Rc::new(RefCell::new(Value::Track(TRACK_DEFAULT)))},
+      BType::Str => RVal{value: Rc::new(RefCell::new(Value::Str(STR_DEFAULT)))},
       _ => unimplemented!()
     }
   }
 
+  /// 以给定的值初始化，用于语义检查阶段绑定内置常量
+  pub fn new_with_value(value: Value) -> Self {
+    RVal{value: Rc::new(RefCell::new(value))}
+  }
+
   /// 返回变量类型 Btype
   pub fn get_btype(&self) -> BType {
-    match *self.value.borrow() {
-      Value::Note(_) => BType::Note,
-      Value::Int(_) => BType::Int,
-      Value::Measure(_) => BType::Measure,
-      Value::Phrase(_) => BType::Phrase,
-      Value::Track(_) => BType::Track,
-    }
+    self.value.borrow().get_btype()
   }
 
   /// 赋值
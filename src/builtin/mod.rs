@@ -0,0 +1,70 @@
+pub mod rhythm;
+
+use crate::ast::func::FuncType;
+use crate::ast::val::BType;
+
+/// 内置函数。用户定义的同名函数会遮蔽内置函数。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+  /// `euclid(hits, steps, rotation, note)` 将 hits 个音符尽量均匀地分布在 steps 步中，返回 measure
+  Euclid,
+
+  /// `poly(measure, measure, ...)` 将不同长度的节奏型重复到共同的周期后叠加为多个声部，返回 measure
+  Poly,
+
+  /// `print(value, ...)` 在执行时把各参数以空格分隔输出到 stderr，字符串不加引号
+  Print,
+
+  /// `debug(value, ...)` 在执行时把各参数的类型和值以字面量的形式输出到 stderr，如 `measure | 60, 64 |`
+  Debug,
+
+  /// `len(value)` 返回和弦中音的个数、小节中音符和休止符的个数、phrase 中小节的个数、track 中 phrase 的个数或字符串的字符数
+  Len,
+}
+
+/// 所有的内置函数
+pub const BUILTINS: [Builtin; 5] = [Builtin::Euclid, Builtin::Poly, Builtin::Print, Builtin::Debug, Builtin::Len];
+
+impl Builtin {
+  /// 由函数名查找内置函数
+  pub fn from_ident(ident: &str) -> Option<Self> {
+    match ident {
+      "euclid" => Some(Builtin::Euclid),
+      "poly" => Some(Builtin::Poly),
+      "print" => Some(Builtin::Print),
+      "debug" => Some(Builtin::Debug),
+      "len" => Some(Builtin::Len),
+      _ => None,
+    }
+  }
+
+  /// 内置函数的函数名
+  pub fn ident(&self) -> &'static str {
+    match self {
+      Builtin::Euclid => "euclid",
+      Builtin::Poly => "poly",
+      Builtin::Print => "print",
+      Builtin::Debug => "debug",
+      Builtin::Len => "len",
+    }
+  }
+
+  /// 以 argc 个参数调用时各参数的类型，参数可以是任意类型时为 None；参数数量不合法时返回 None
+  pub fn param_types(&self, argc: usize) -> Option<Vec<Option<BType>>> {
+    match self {
+      Builtin::Euclid => (argc == 4).then(|| vec![Some(BType::Int), Some(BType::Int), Some(BType::Int), Some(BType::Note)]),
+      Builtin::Poly => (argc >= 2).then(|| vec![Some(BType::Measure); argc]),
+      Builtin::Print | Builtin::Debug => (argc >= 1).then(|| vec![None; argc]),
+      Builtin::Len => (argc == 1).then(|| vec![None]),
+    }
+  }
+
+  /// 返回类型
+  pub fn func_type(&self) -> FuncType {
+    match self {
+      Builtin::Euclid | Builtin::Poly => FuncType::BType(BType::Measure),
+      Builtin::Print | Builtin::Debug => FuncType::Void,
+      Builtin::Len => FuncType::BType(BType::Int),
+    }
+  }
+}
@@ -0,0 +1,111 @@
+use crate::ast::measure::{MeasureUnitValue, MeasureValue};
+use crate::ast::note::NoteValue;
+use crate::error::Error;
+
+/// euclid 的步数以及 poly 的循环长度的上限，以免生成过长的小节
+const MAX_STEPS: usize = 4096;
+
+/// 欧几里得节奏：将 hits 个音符尽量均匀地分布在 steps 步中，其余为休止符。
+/// 第 i 步为音符当且仅当 i * hits mod steps < hits，这保证第一步总是音符；
+/// 之后整体向左旋转 rotation 步。
+pub fn euclid(hits: i32, steps: i32, rotation: i32, note: NoteValue) -> Result<MeasureValue, Error> {
+  if steps < 1 || steps as usize > MAX_STEPS {
+    return Err(Error::RuntimeError(format!(
+      "steps of euclid must between 1 and {MAX_STEPS}, but found {steps}"
+    )));
+  }
+  if hits < 0 || hits > steps {
+    return Err(Error::RuntimeError(format!(
+      "hits of euclid must between 0 and {steps}, but found {hits}"
+    )));
+  }
+
+  // 先把 rotation 约化到 [0, steps)，再以 i64 计算以免溢出
+  let (hits, steps, rotation) = (hits as i64, steps as i64, rotation.rem_euclid(steps) as i64);
+  let content = (0..steps)
+    .map(|i| (i + rotation) % steps)
+    .map(|i| match i * hits % steps < hits {
+      true => MeasureUnitValue::NoteValue(note.clone()),
+      false => MeasureUnitValue::Rest,
+    })
+    .collect();
+  Ok(MeasureValue{content})
+}
+
+/// 多声部叠加：每个节奏型都重复到所有节奏型长度的最小公倍数，然后作为小节的各个声部同时开始。
+/// 每个节奏型都必须是只由音符和休止符组成的单声部小节。
+pub fn poly(patterns: &[MeasureValue]) -> Result<MeasureValue, Error> {
+  let mut cycle = 1;
+  for pattern in patterns {
+    let len = pattern.content.len();
+    let plain = pattern.content.iter().all(|unit| matches!(unit, MeasureUnitValue::NoteValue(_) | MeasureUnitValue::Rest));
+    if len == 0 || !plain {
+      return Err(Error::RuntimeError(
+        "patterns of poly must be non-empty measures of notes and rests only".to_string()
+      ));
+    }
+    cycle = lcm(cycle, len);
+    if cycle > MAX_STEPS {
+      return Err(Error::RuntimeError(format!(
+        "cycle of poly must be at most {MAX_STEPS} steps, but the lengths of patterns need {cycle}"
+      )));
+    }
+  }
+
+  let mut content = vec![];
+  for (i, pattern) in patterns.iter().enumerate() {
+    if i > 0 {
+      content.push(MeasureUnitValue::VoiceSeparator);
+    }
+    for _ in 0..cycle / pattern.content.len() {
+      content.extend(pattern.content.iter().cloned());
+    }
+  }
+  Ok(MeasureValue{content})
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+  match b {
+    0 => a,
+    _ => gcd(b, a % b),
+  }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+  a / gcd(a, b) * b
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pattern(measure: &MeasureValue) -> String {
+    measure.content.iter().map(|unit| match unit {
+      MeasureUnitValue::NoteValue(_) => 'x',
+      MeasureUnitValue::Rest => '.',
+      _ => '&',
+    }).collect()
+  }
+
+  fn note() -> NoteValue {
+    NoteValue{ notes: vec![36], len: None, velocity: None, cents: None, syllable: None, span: None }
+  }
+
+  #[test]
+  fn euclid_rotation_is_reduced() {
+    let expected = pattern(&euclid(3, 8, 1, note()).unwrap());
+    assert_eq!(expected, "..x..x.x");
+    assert_eq!(pattern(&euclid(3, 8, 9, note()).unwrap()), expected);
+    assert_eq!(pattern(&euclid(3, 8, -7, note()).unwrap()), expected);
+    assert!(euclid(3, 8, i32::MAX, note()).is_ok());
+    assert!(euclid(3, 8, i32::MIN, note()).is_ok());
+  }
+
+  #[test]
+  fn euclid_and_poly_limit_steps() {
+    assert_eq!(euclid(4096, 4096, 4095, note()).unwrap().content.len(), 4096);
+    assert!(matches!(euclid(1, 46341, 0, note()), Err(Error::RuntimeError(_))));
+    let patterns = [euclid(1, 4093, 0, note()).unwrap(), euclid(1, 4091, 0, note()).unwrap()];
+    assert!(matches!(poly(&patterns), Err(Error::RuntimeError(_))));
+  }
+}
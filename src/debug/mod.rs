@@ -0,0 +1,128 @@
+mod session;
+mod trace;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use midi_file::MidiFile;
+use session::Session;
+use trace::Tracer;
+
+use crate::error::Error;
+use crate::interpret::Interpreter;
+use crate::semantic::Analyzer as SemanticAnalyzer;
+use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+/// 调试时输入 quit 停止执行所返回的错误信息
+const STOPPED: &str = "debugging stopped";
+
+/// 源文件的内容及每一行开始的字节偏移，用于把语句的位置显示为行号、列号和源代码
+struct Source {
+  file: String,
+  text: String,
+  starts: Vec<usize>,
+}
+
+impl Source {
+  fn new(file: &str, text: &str) -> Self {
+    let starts = std::iter::once(0)
+      .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+    Self { file: file.to_string(), text: text.to_string(), starts }
+  }
+
+  /// 字节偏移所在的行号和列号，都从 1 开始，列号按字符计
+  fn position(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.text.len());
+    let line = self.starts.partition_point(|start| *start <= offset) - 1;
+    let column = self.text[self.starts[line]..offset].chars().count();
+    (line + 1, column + 1)
+  }
+
+  /// 第 line 行的源代码，不含换行符，行号从 1 开始
+  fn line(&self, line: usize) -> Option<&str> {
+    let start = *self.starts.get(line.checked_sub(1)?)?;
+    let end = self.starts.get(line).copied().unwrap_or(self.text.len());
+    Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+  }
+
+  /// 源文件的行数
+  fn line_count(&self) -> usize {
+    self.starts.len()
+  }
+
+  /// 以 file:line:column 表示字节偏移所在的位置
+  fn locate(&self, offset: usize) -> String {
+    let (line, column) = self.position(offset);
+    format!("{}:{line}:{column}", self.file)
+  }
+}
+
+/// 把观察者中的输出错误转换为执行错误
+fn output_error(err: io::Error) -> Error {
+  Error::InternalError(format!("can not write debugger output: {err}"))
+}
+
+/// 调试器，在源代码的行上设置断点，逐条语句地执行 yam 源文件并查看当前作用域中的变量和调用栈；
+/// 或者不交互地跟踪执行，记录执行的每条语句和每次函数调用及其实参
+pub struct Debugger {
+  parser: SyntacticAnalyzer,
+
+  /// 初始的断点所在的行号
+  breakpoints: BTreeSet<usize>,
+
+  /// 是否只跟踪执行而不交互
+  trace: bool,
+}
+
+impl Default for Debugger {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Debugger {
+  pub fn new() -> Self {
+    Self {
+      parser: SyntacticAnalyzer::new(),
+      breakpoints: BTreeSet::new(),
+      trace: false,
+    }
+  }
+
+  /// 设置初始的断点所在的行号。没有断点时在执行的第一条语句处暂停
+  pub fn set_breakpoints(&mut self, breakpoints: &[usize]) {
+    self.breakpoints = breakpoints.iter().copied().collect();
+  }
+
+  /// 设置是否只跟踪执行而不交互
+  pub fn set_trace(&mut self, trace: bool) {
+    self.trace = trace;
+  }
+
+  /// 检查并执行文件名为 file 的源代码 source，从 input 读取调试命令，把执行位置、变量和跟踪记录写入 output。
+  /// 执行完成时返回生成的 midi 文件，输入 quit 停止执行时返回 None
+  pub fn run(&self, file: &str, source: &str, input: impl BufRead + 'static, output: impl Write + 'static) -> io::Result<Option<MidiFile>> {
+    let lines = Source::new(file, source);
+    let located = |offset: usize, err: Error| io::Error::other(format!("{}: {err}", lines.locate(offset)));
+
+    let comp_unit = self.parser.parse_with_span(source).map_err(|(err, span)| located(span.start, err))?;
+    let mut semantic_analyzer = SemanticAnalyzer::new();
+    semantic_analyzer.check(&comp_unit).map_err(|err| located(semantic_analyzer.get_current_span().start, err))?;
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_source_dir(Path::new(file).parent().map(Path::to_path_buf));
+    match self.trace {
+      true => interpreter.set_observer(Some(Box::new(Tracer::new(Source::new(file, source), output)))),
+      false => interpreter.set_observer(Some(Box::new(Session::new(
+        Source::new(file, source), self.breakpoints.clone(), input, output,
+      )))),
+    }
+    match interpreter.interpret(&comp_unit) {
+      Ok( midi_file ) => Ok(Some(midi_file)),
+      Err(Error::RuntimeError( msg )) if msg == STOPPED => Ok(None),
+      Err( err ) => Err(located(interpreter.get_current_span().start, err)),
+    }
+  }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::ast::span::Span;
+use crate::ast::stmt::Stmt;
+use crate::ast::val::Value;
+use crate::error::Error;
+use crate::interpret::ctr::RetVal;
+use crate::interpret::observe::{Frame, Observer};
+
+use super::{output_error, Source, STOPPED};
+
+/// 输入提示符
+const PROMPT: &str = "(yam) ";
+
+/// list 显示当前行前后各几行
+const LIST_CONTEXT: usize = 5;
+
+const HELP: &str = "\
+  s, step            执行到下一条语句，进入函数调用
+  n, next            执行到当前函数中的下一条语句，不进入函数调用
+  f, finish          执行到当前函数返回
+  c, continue        执行到下一个断点
+  b, break [line]    在第 line 行设置断点，不带行号时列出所有断点
+  d, delete <line>   删除第 line 行的断点
+  p, print <name>    显示当前函数或全局作用域中的常量或变量
+  locals [frame]     显示调用栈第 frame 帧中可见的常量和变量，默认为当前函数
+  bt, backtrace      显示调用栈
+  l, list            显示当前语句附近的源代码
+  h, help            显示这段说明
+  q, quit            停止执行
+直接回车重复上一条命令";
+
+/// 暂停之后继续执行的方式
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+  /// 在下一条语句暂停
+  Step,
+
+  /// 在调用栈不深于给定帧数的下一条语句暂停
+  Next(usize),
+
+  /// 在调用栈浅于给定帧数的下一条语句暂停
+  Finish(usize),
+
+  /// 只在断点暂停
+  Continue,
+}
+
+/// 交互式调试。在断点或单步执行到的语句之前暂停，从输入读取命令
+pub(super) struct Session {
+  source: Source,
+  breakpoints: BTreeSet<usize>,
+  mode: Mode,
+
+  /// 当前暂停的语句的位置
+  span: Span,
+
+  /// 上一条命令，直接回车时重复
+  last_command: String,
+
+  /// 输入结束后不再暂停，执行到结束
+  input_ended: bool,
+
+  input: Box<dyn BufRead>,
+  output: Box<dyn Write>,
+}
+
+impl Session {
+  pub(super) fn new(source: Source, breakpoints: BTreeSet<usize>, input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+    // 没有断点时在第一条语句暂停
+    let mode = match breakpoints.is_empty() {
+      true => Mode::Step,
+      false => Mode::Continue,
+    };
+    Self {
+      source,
+      breakpoints,
+      mode,
+      span: Span::default(),
+      last_command: String::new(),
+      input_ended: false,
+      input: Box::new(input),
+      output: Box::new(output),
+    }
+  }
+
+  fn say(&mut self, text: &str) -> Result<(), Error> {
+    writeln!(self.output, "{text}").map_err(output_error)
+  }
+
+  /// 显示当前暂停的位置及该行源代码
+  fn show_position(&mut self, frames: &[Frame]) -> Result<(), Error> {
+    let (line, _) = self.source.position(self.span.start);
+    let name = frames.last().map(|frame| frame.name.as_str()).unwrap_or_default();
+    let text = format!(
+      "{} in {name}\n{line:>4} | {}",
+      self.source.locate(self.span.start), self.source.line(line).unwrap_or_default()
+    );
+    self.say(&text)
+  }
+
+  /// 读取并执行命令，直到输入继续执行的命令
+  fn prompt(&mut self, frames: &[Frame]) -> Result<(), Error> {
+    loop {
+      write!(self.output, "{PROMPT}").and_then(|_| self.output.flush()).map_err(output_error)?;
+      let mut command = String::new();
+      if self.input.read_line(&mut command).map_err(output_error)? == 0 {
+        self.input_ended = true;
+        return Ok(());
+      }
+      let command = match command.trim() {
+        "" => self.last_command.clone(),
+        command => command.to_string(),
+      };
+      self.last_command = command.clone();
+
+      let mut words = command.split_whitespace();
+      let text = match (words.next(), words.next()) {
+        (None, _) => continue,
+        (Some("s" | "step"), None) => {
+          self.mode = Mode::Step;
+          return Ok(());
+        },
+        (Some("n" | "next"), None) => {
+          self.mode = Mode::Next(frames.len());
+          return Ok(());
+        },
+        (Some("f" | "finish"), None) => {
+          self.mode = Mode::Finish(frames.len());
+          return Ok(());
+        },
+        (Some("c" | "continue"), None) => {
+          self.mode = Mode::Continue;
+          return Ok(());
+        },
+        (Some("q" | "quit"), None) => return Err(Error::RuntimeError(STOPPED.to_string())),
+        (Some("b" | "break"), None) => match self.breakpoints.is_empty() {
+          true => "no breakpoints".to_string(),
+          false => self.breakpoints.iter()
+            .map(|line| format!("breakpoint at {}:{line}", self.source.file))
+            .collect::<Vec<_>>().join("\n"),
+        },
+        (Some("b" | "break"), Some( line )) => match line.parse::<usize>() {
+          Ok( line ) if (1..=self.source.line_count()).contains(&line) => {
+            self.breakpoints.insert(line);
+            format!("breakpoint at {}:{line}", self.source.file)
+          },
+          _ => format!("invalid line '{line}'"),
+        },
+        (Some("d" | "delete"), Some( line )) => match line.parse::<usize>() {
+          Ok( line ) if self.breakpoints.remove(&line) => format!("deleted breakpoint at {}:{line}", self.source.file),
+          _ => format!("no breakpoint at line '{line}'"),
+        },
+        (Some("p" | "print"), Some( name )) => print_variable(frames, name),
+        (Some("locals"), frame_) => match frame_.map(|frame| frame.parse::<usize>()) {
+          None => locals(frames, 0),
+          Some(Ok( frame )) if frame < frames.len() => locals(frames, frame),
+          Some(_) => format!("invalid frame '{}', expect 0 to {}", frame_.unwrap(), frames.len().saturating_sub(1)),
+        },
+        (Some("bt" | "backtrace"), None) => self.backtrace(frames),
+        (Some("l" | "list"), None) => self.list(),
+        (Some("h" | "help"), None) => HELP.to_string(),
+        (Some( command ), _) => format!("unknown command '{command}', type h for help"),
+      };
+      self.say(&text)?;
+    }
+  }
+
+  /// 调用栈，当前函数为第 0 帧。调用者所在的位置为它调用下一帧的函数调用的位置
+  fn backtrace(&self, frames: &[Frame]) -> String {
+    let mut span_ = Some(self.span);
+    let mut lines = vec![];
+    for (i, frame) in frames.iter().rev().enumerate() {
+      let mut line = format!("#{i} {}", frame.name);
+      if frame.call_span.is_some() {
+        let args = frame.args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ");
+        line += &format!("({args})");
+      }
+      if let Some( span ) = span_ {
+        line += &format!(" at {}", self.source.locate(span.start));
+      }
+      lines.push(line);
+      span_ = frame.call_span;
+    }
+    lines.join("\n")
+  }
+
+  /// 当前语句前后的源代码，=> 标记当前行，* 标记断点
+  fn list(&self) -> String {
+    let (current, _) = self.source.position(self.span.start);
+    let first = current.saturating_sub(LIST_CONTEXT).max(1);
+    let last = (current + LIST_CONTEXT).min(self.source.line_count());
+    (first..=last).map(|line| {
+      let mark = match (line == current, self.breakpoints.contains(&line)) {
+        (true, _) => "=>",
+        (false, true) => " *",
+        (false, false) => "  ",
+      };
+      format!("{mark}{line:>4} | {}", self.source.line(line).unwrap_or_default())
+    }).collect::<Vec<_>>().join("\n")
+  }
+}
+
+/// 以声明的形式显示常量或变量
+fn show_variable(name: &str, value: &Value) -> String {
+  format!("{} {name} = {value}", value.get_btype())
+}
+
+/// 先在当前函数，再在全局作用域中查找常量或变量
+fn print_variable(frames: &[Frame], name: &str) -> String {
+  let rval_ = frames.last().and_then(|frame| frame.lookup(name))
+    .or_else(|| frames.first().and_then(|frame| frame.lookup(name)));
+  match rval_ {
+    Some( rval ) => show_variable(name, &rval.get_value()),
+    None => format!("no variable '{name}' in current scope"),
+  }
+}
+
+/// 从当前函数数起第 frame 帧中可见的常量和变量
+fn locals(frames: &[Frame], frame: usize) -> String {
+  let frame = &frames[frames.len() - 1 - frame];
+  let variables = frame.variables();
+  match variables.is_empty() {
+    true => format!("no variables in {}", frame.name),
+    false => variables.iter()
+      .map(|(name, rval)| show_variable(name, &rval.get_value()))
+      .collect::<Vec<_>>().join("\n"),
+  }
+}
+
+impl Observer for Session {
+  fn stmt(&mut self, frames: &[Frame], _stmt: &Stmt, span: Span) -> Result<(), Error> {
+    if self.input_ended {
+      return Ok(());
+    }
+    let (line, _) = self.source.position(span.start);
+    let depth = frames.len();
+    let at_breakpoint = self.breakpoints.contains(&line);
+    let stop = at_breakpoint || match self.mode {
+      Mode::Step => true,
+      Mode::Next( max_depth ) => depth <= max_depth,
+      Mode::Finish( max_depth ) => depth < max_depth,
+      Mode::Continue => false,
+    };
+    if !stop {
+      return Ok(());
+    }
+
+    self.span = span;
+    if at_breakpoint {
+      self.say(&format!("stopped at breakpoint on line {line}"))?;
+    }
+    self.show_position(frames)?;
+    self.prompt(frames)
+  }
+
+  fn call(&mut self, _frames: &[Frame], _ident: &str, _args: &[Value], _span: Span) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn ret(&mut self, frames: &[Frame], ident: &str, ret_val: &RetVal) -> Result<(), Error> {
+    // finish 时显示返回值
+    match self.mode {
+      Mode::Finish( depth ) if !self.input_ended && depth == frames.len() => self.say(&format!("{ident} returned {ret_val}")),
+      _ => Ok(()),
+    }
+  }
+}
@@ -0,0 +1,46 @@
+use std::io::Write;
+
+use crate::ast::span::Span;
+use crate::ast::stmt::Stmt;
+use crate::ast::val::Value;
+use crate::error::Error;
+use crate::interpret::ctr::RetVal;
+use crate::interpret::observe::{Frame, Observer};
+
+use super::{output_error, Source};
+
+/// 不交互地跟踪执行，记录执行的每条语句的行号和源代码，以及每次函数调用的实参和返回值。
+/// 按调用的深度缩进，函数体中的语句比调用它的语句多缩进一层
+pub(super) struct Tracer {
+  source: Source,
+  output: Box<dyn Write>,
+}
+
+impl Tracer {
+  pub(super) fn new(source: Source, output: impl Write + 'static) -> Self {
+    Self { source, output: Box::new(output) }
+  }
+
+  fn log(&mut self, depth: usize, text: &str) -> Result<(), Error> {
+    writeln!(self.output, "{}{text}", "  ".repeat(depth)).map_err(output_error)
+  }
+}
+
+impl Observer for Tracer {
+  fn stmt(&mut self, frames: &[Frame], _stmt: &Stmt, span: Span) -> Result<(), Error> {
+    // 只记录语句开始的那一行，从语句开始的列起
+    let (line, column) = self.source.position(span.start);
+    let text = self.source.line(line).unwrap_or_default()
+      .chars().skip(column - 1).collect::<String>();
+    self.log(frames.len().saturating_sub(1), &format!("{line}: {}", text.trim_end()))
+  }
+
+  fn call(&mut self, frames: &[Frame], ident: &str, args: &[Value], _span: Span) -> Result<(), Error> {
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ");
+    self.log(frames.len().saturating_sub(1), &format!("call {ident}({args})"))
+  }
+
+  fn ret(&mut self, frames: &[Frame], ident: &str, ret_val: &RetVal) -> Result<(), Error> {
+    self.log(frames.len().saturating_sub(2), &format!("{ident} returned {ret_val}"))
+  }
+}
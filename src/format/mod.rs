@@ -0,0 +1,1140 @@
+use crate::ast::{block::Block, comp_unit::{CompUnit, TestDef}, drum::{DrumGrid, DrumRow}, expr::{AddOp, Expr, EqOp, MulOp, PrimaryExpr, RelOp, UnaryExpr, UnaryOp}, func::{FuncCall, FuncDef, FuncType}, instrument::InstrumentDef, measure::{Measure, MeasureRVal, MeasureUnit}, note::Note, phrase::{Phrase, PhraseRVal}, score::{Beat, Instrument, KeyMode, Score, ScoreStmt, TrackPosition}, span::Span, stmt::{AsgnRVal, IfElse, Stmt}, track::{Track, TrackRVal}, val::BType};
+use crate::error::Error;
+use crate::syntactic::Analyzer;
+
+mod scan;
+
+/// 每一级缩进的空格数
+const INDENT_WIDTH: usize = 2;
+
+/// yam 源文件的格式化器，将语法树按统一的风格重新输出，保留注释以及语句之间的空行(连续的空行合并为一个)。
+/// phrase、track 和鼓组网格在源文件中跨越多行时，保持每一行的内容不变，并对齐 phrase 各行的小节线和鼓组网格各行的节奏
+pub struct Formatter {
+  parser: Analyzer,
+}
+
+impl Default for Formatter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Formatter {
+  pub fn new() -> Self {
+    Self {
+      parser: Analyzer::new(),
+    }
+  }
+
+  /// 格式化 yam 源文件
+  pub fn format(&self, source: &str) -> Result<String, Error> {
+    let comp_unit = self.parser.parse(source)?;
+    let (tokens, comments) = scan::scan(source)?;
+    let mut printer = Printer {
+      source,
+      tokens,
+      comments,
+      next: 0,
+      next_comment: 0,
+      last_end: 0,
+      out: String::new(),
+      indent: 0,
+      line_start: true,
+      continued: false,
+    };
+    printer.comp_unit(&comp_unit)?;
+    printer.finish()
+  }
+}
+
+/// 按语法树输出源文件。输出的 token 序列与源文件完全相同，
+/// 因此可以按 token 的顺序找到注释和空行原本的位置，整数和小数字面量也保持原来的写法
+struct Printer<'a> {
+  source: &'a str,
+  tokens: Vec<Span>,
+  comments: Vec<Span>,
+
+  /// 下一个要输出的 token
+  next: usize,
+
+  /// 下一个要输出的注释
+  next_comment: usize,
+
+  /// 上一个输出的 token 或注释在源文件中的结束位置
+  last_end: usize,
+
+  out: String,
+
+  /// 当前的缩进级数
+  indent: usize,
+
+  /// 当前行还没有输出任何内容
+  line_start: bool,
+
+  /// 因为行中的注释而提前换行，当前行多缩进一级
+  continued: bool,
+}
+
+/// CompUnit 中的一个全局定义，用于按源文件中的顺序输出
+enum GlobalDef<'a> {
+  Stmt(&'a Stmt),
+  InstrumentDef(&'a InstrumentDef),
+  TestDef(&'a TestDef),
+}
+
+impl Printer<'_> {
+  /// 在当前行输出文字，行首先输出缩进
+  fn write(&mut self, text: &str) {
+    if self.line_start {
+      let indent = self.indent + self.continued as usize;
+      self.out.push_str(&" ".repeat(indent * INDENT_WIDTH));
+      self.line_start = false;
+    }
+    self.out.push_str(text);
+  }
+
+  /// 不在行首时输出一个空格
+  fn space(&mut self) {
+    if !self.line_start {
+      self.out.push(' ');
+    }
+  }
+
+  /// 结束当前行，源文件中位于行尾的注释保留在行尾
+  fn newline(&mut self) {
+    if let Some(comment) = self.pending_comment() && self.trailing(comment) {
+      self.write(" ");
+      self.write(self.source[comment.start..comment.end].trim_end());
+      self.last_end = comment.end;
+      self.next_comment += 1;
+    }
+    if !self.line_start {
+      self.out.push('\n');
+      self.line_start = true;
+    }
+    self.continued = false;
+  }
+
+  /// 下一个 token 之前还没有输出的注释
+  fn pending_comment(&self) -> Option<Span> {
+    let next_start = self.tokens.get(self.next).map_or(usize::MAX, |token| token.start);
+    self.comments.get(self.next_comment).copied().filter(|comment| comment.start < next_start)
+  }
+
+  /// 注释是否与上一个输出的 token 位于源文件的同一行
+  fn trailing(&self, comment: Span) -> bool {
+    !self.line_start && !self.source[self.last_end..comment.start].contains('\n')
+  }
+
+  /// 输出下一个 token 之前的所有注释，行尾的注释之后提前换行
+  fn comments(&mut self) {
+    while let Some(comment) = self.pending_comment() {
+      let text = self.source[comment.start..comment.end].trim_end();
+      // 注释打断了一行代码时，其后的部分多缩进一级
+      if self.trailing(comment) {
+        self.write(" ");
+        self.continued = true;
+      } else {
+        if !self.line_start {
+          self.out.push('\n');
+          self.line_start = true;
+          self.continued = true;
+        }
+        self.blank_line(comment.start);
+      }
+      self.write(text);
+      self.out.push('\n');
+      self.line_start = true;
+      self.last_end = comment.end;
+      self.next_comment += 1;
+    }
+  }
+
+  /// 源文件中 start 之前有空行时在行首输出一个空行，位于文件开头或紧跟在 `{`、`[` 之后时除外
+  fn blank_line(&mut self, start: usize) {
+    if !self.line_start || self.out.is_empty() || self.out.ends_with("\n\n") {
+      return;
+    }
+    if self.source[self.last_end..start].matches('\n').count() < 2 {
+      return;
+    }
+    if self.out.trim_end().ends_with(['{', '[']) {
+      return;
+    }
+    self.out.push('\n');
+  }
+
+  /// 输出下一个 token，它必须与源文件中的 token 相同
+  fn token(&mut self, text: &str) -> Result<(), Error> {
+    self.comments();
+    let token = self.expect(Some(text))?;
+    if !matches!(text, "}" | "]") {
+      self.blank_line(token.start);
+    }
+    self.write(&self.source[token.start..token.end]);
+    self.last_end = token.end;
+    self.next += 1;
+    Ok(())
+  }
+
+  /// 按源文件中的写法输出下一个整数或小数字面量
+  fn literal(&mut self) -> Result<(), Error> {
+    self.comments();
+    let token = self.expect(None)?;
+    self.blank_line(token.start);
+    self.write(&self.source[token.start..token.end]);
+    self.last_end = token.end;
+    self.next += 1;
+    Ok(())
+  }
+
+  /// 检查源文件中下一个 token 是否为 text，text 为 None 时只检查是否还有 token
+  fn expect(&self, text: Option<&str>) -> Result<Span, Error> {
+    match self.tokens.get(self.next) {
+      Some( token ) if text.is_none_or(|text| &self.source[token.start..token.end] == text) => Ok(*token),
+      Some( token ) => Err(Error::InternalError(format!(
+        "formatter expects '{}' but found '{}' at {}", text.unwrap(), &self.source[token.start..token.end], token.start
+      ))),
+      None => Err(Error::InternalError(format!(
+        "formatter expects '{}' but found EOF", text.unwrap_or("literal")
+      ))),
+    }
+  }
+
+  /// 下一个 token 为 `[` 或 `{` 时，与它配对的 token 的下标
+  fn closing(&self) -> usize {
+    let mut depth = 0;
+    for (i, token) in self.tokens.iter().enumerate().skip(self.next) {
+      match &self.source[token.start..token.end] {
+        "[" | "{" => depth += 1,
+        "]" | "}" => depth -= 1,
+        _ => (),
+      }
+      if depth == 0 {
+        return i;
+      }
+    }
+    self.tokens.len() - 1
+  }
+
+  /// 下一个 `[` 或 `{` 与它配对的 token 是否在源文件中跨越多行
+  fn multiline(&self) -> bool {
+    let close = self.tokens[self.closing()];
+    self.source[self.tokens[self.next].start..close.end].contains('\n')
+  }
+
+  /// 下一个 `[` 或 `{` 与它配对的 token 之间是否有注释
+  fn has_comments(&self) -> bool {
+    let (open, close) = (self.tokens[self.next], self.tokens[self.closing()]);
+    self.comments.iter().any(|comment| open.start < comment.start && comment.start < close.start)
+  }
+
+  /// 源文件中下一个 token 是否位于新的一行
+  fn line_break(&self) -> bool {
+    self.next > 0 && self.source[self.tokens[self.next - 1].end..self.tokens[self.next].start].contains('\n')
+  }
+
+  /// 将 f 输出的内容收集为字符串而不是写入输出，用于对齐
+  fn capture(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Error>) -> Result<String, Error> {
+    let out = std::mem::take(&mut self.out);
+    let line_start = std::mem::replace(&mut self.line_start, false);
+    f(self)?;
+    self.line_start = line_start;
+    Ok(std::mem::replace(&mut self.out, out))
+  }
+
+  /// 输出剩余的注释，检查所有 token 都已输出
+  fn finish(mut self) -> Result<String, Error> {
+    self.newline();
+    self.comments();
+    if let Some( token ) = self.tokens.get(self.next) {
+      return Err(Error::InternalError(format!(
+        "formatter didn't print '{}' at {}", &self.source[token.start..token.end], token.start
+      )));
+    }
+    Ok(self.out)
+  }
+
+  fn comp_unit(&mut self, comp_unit: &CompUnit) -> Result<(), Error> {
+    // 按各个全局定义在源文件中的位置还原它们的顺序
+    let mut defs: Vec<_> = comp_unit.block.stmts.iter().zip(&comp_unit.block.spans)
+      .map(|(stmt, span)| (span.start, GlobalDef::Stmt(stmt)))
+      .collect();
+    defs.extend(comp_unit.instruments.iter().map(|instrument_def| (instrument_def.span.start, GlobalDef::InstrumentDef(instrument_def))));
+    defs.extend(comp_unit.tests.iter().map(|test_def| (test_def.span.start, GlobalDef::TestDef(test_def))));
+    defs.sort_by_key(|(start, _)| *start);
+
+    for (_, def) in defs {
+      match def {
+        GlobalDef::Stmt( stmt ) => self.stmt(stmt)?,
+        GlobalDef::InstrumentDef( instrument_def ) => self.instrument_def(instrument_def)?,
+        GlobalDef::TestDef( test_def ) => self.test_def(test_def)?,
+      }
+    }
+    self.score(&comp_unit.score)
+  }
+
+  fn test_def(&mut self, test_def: &TestDef) -> Result<(), Error> {
+    self.token("test")?;
+    self.space();
+    self.token(&format!("\"{}\"", test_def.name))?;
+    self.space();
+    self.block(&test_def.block)?;
+    self.newline();
+    Ok(())
+  }
+
+  fn instrument_def(&mut self, instrument_def: &InstrumentDef) -> Result<(), Error> {
+    self.token("instrument")?;
+    self.space();
+    self.token(&instrument_def.ident)?;
+    self.space();
+    self.token("{")?;
+    self.newline();
+    self.indent += 1;
+    for param in &instrument_def.params {
+      self.token(&param.name)?;
+      self.space();
+      self.token("=")?;
+      self.space();
+      self.expr(&param.value)?;
+      self.token(";")?;
+      self.newline();
+    }
+    self.comments();
+    self.indent -= 1;
+    self.token("}")?;
+    self.newline();
+    Ok(())
+  }
+
+  fn score(&mut self, score: &Score) -> Result<(), Error> {
+    self.token("@")?;
+    self.token("score")?;
+    self.space();
+    self.token("{")?;
+    self.newline();
+    self.indent += 1;
+    for stmt in &score.block.stmts {
+      self.stmt(stmt)?;
+    }
+    for score_stmt in &score.channel_stmts {
+      self.score_stmt(score_stmt)?;
+      self.newline();
+    }
+    self.comments();
+    self.indent -= 1;
+    self.token("}")?;
+    self.newline();
+    Ok(())
+  }
+
+  /// 输出 `@channel key = value;` 形式的设置
+  fn channel_setting(&mut self, channel: &Expr, key: &str, value: &Expr) -> Result<(), Error> {
+    self.token("@")?;
+    self.expr(channel)?;
+    self.space();
+    self.token(key)?;
+    self.space();
+    self.token("=")?;
+    self.space();
+    self.expr(value)?;
+    self.token(";")
+  }
+
+  /// 输出 `@key = ` 形式的设置的开头
+  fn score_setting(&mut self, key: &str) -> Result<(), Error> {
+    self.token("@")?;
+    self.token(key)?;
+    self.space();
+    self.token("=")?;
+    self.space();
+    Ok(())
+  }
+
+  fn score_stmt(&mut self, score_stmt: &ScoreStmt) -> Result<(), Error> {
+    match score_stmt {
+      ScoreStmt::SetChannelTrack( set ) => {
+        self.token("@")?;
+        self.expr(&set.channel)?;
+        self.space();
+        self.token("<-")?;
+        for (i, track_rval) in set.tracks.iter().enumerate() {
+          if i > 0 {
+            self.space();
+            self.token("&")?;
+          }
+          self.space();
+          self.track_rval(track_rval)?;
+        }
+        if let Some( position ) = &set.position {
+          self.position(position)?;
+        }
+        self.token(";")
+      },
+      ScoreStmt::SetChannelInstrument( set ) => {
+        self.token("@")?;
+        self.expr(&set.channel)?;
+        self.space();
+        self.token("->")?;
+        self.space();
+        match &set.instrument {
+          Instrument::Program( program ) => self.expr(program)?,
+          Instrument::Name( name ) => self.token(&format!("\"{name}\""))?,
+        }
+        self.token(";")
+      },
+      ScoreStmt::SetChannelName( set ) => self.channel_setting(&set.channel, "name", &set.name),
+      ScoreStmt::SetChannelInstrumentName( set ) => self.channel_setting(&set.channel, "instrument", &set.name),
+      ScoreStmt::SetChannelVolume( set ) => self.channel_setting(&set.channel, "volume", &set.value),
+      ScoreStmt::SetChannelPan( set ) => self.channel_setting(&set.channel, "pan", &set.value),
+      ScoreStmt::SetTitle( title ) => {
+        self.score_setting("title")?;
+        self.expr(title)?;
+        self.token(";")
+      },
+      ScoreStmt::AddMarker( add ) | ScoreStmt::AddCuePoint( add ) => {
+        self.score_setting(match score_stmt {
+          ScoreStmt::AddMarker(_) => "marker",
+          _ => "cue",
+        })?;
+        self.expr(&add.text)?;
+        self.position(&add.position)?;
+        self.token(";")
+      },
+      ScoreStmt::SetKeySignature( set ) => {
+        self.score_setting("keysig")?;
+        match set.tonic.strip_suffix('#') {
+          Some( tonic ) => {
+            self.token(tonic)?;
+            self.token("#")?;
+          },
+          None => self.token(&set.tonic)?,
+        }
+        self.space();
+        self.token(match set.mode {
+          KeyMode::Major => "major",
+          KeyMode::Minor => "minor",
+        })?;
+        if let Some( position ) = &set.position {
+          self.position(position)?;
+        }
+        self.token(";")
+      },
+      ScoreStmt::SetTuning( set ) => {
+        self.score_setting("tuning")?;
+        self.token(&set.name)?;
+        if !set.args.is_empty() {
+          self.token("(")?;
+          self.exprs(&set.args)?;
+          self.token(")")?;
+        }
+        if let Some( root ) = &set.root {
+          self.space();
+          self.token("root")?;
+          self.space();
+          self.expr(root)?;
+        }
+        self.token(";")
+      },
+      ScoreStmt::SetTempo( tempo ) => {
+        self.score_setting("tempo")?;
+        self.expr(tempo)?;
+        self.token(";")
+      },
+      ScoreStmt::SetPpq( ppq ) => {
+        self.score_setting("ppq")?;
+        self.expr(ppq)?;
+        self.token(";")
+      },
+      ScoreStmt::SetTimeSignature( set ) => {
+        self.score_setting("timesig")?;
+        self.expr(&set.top_num)?;
+        self.token(":")?;
+        self.expr(&set.bottom_num)?;
+        self.token(";")
+      },
+    }
+  }
+
+  fn position(&mut self, position: &TrackPosition) -> Result<(), Error> {
+    self.space();
+    self.token("at")?;
+    self.space();
+    match position {
+      TrackPosition::Bar( bar, beat_ ) => {
+        self.token("bar")?;
+        self.space();
+        self.expr(bar)?;
+        if let Some( beat ) = beat_ {
+          self.space();
+          self.token("beat")?;
+          self.space();
+          self.beat(beat)?;
+        }
+      },
+      TrackPosition::Beat( beat ) => {
+        self.token("beat")?;
+        self.space();
+        self.beat(beat)?;
+      },
+    }
+    Ok(())
+  }
+
+  fn beat(&mut self, beat: &Beat) -> Result<(), Error> {
+    match beat {
+      Beat::Expr( expr ) => self.expr(expr),
+      Beat::Decimal(..) => self.literal(),
+    }
+  }
+
+  /// 输出一条语句并换行
+  fn stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    self.stmt_inline(stmt)?;
+    self.newline();
+    Ok(())
+  }
+
+  /// 输出一条语句，不换行
+  fn stmt_inline(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    match stmt {
+      Stmt::FuncDef( func_def ) => self.func_def(func_def),
+      Stmt::Expr( expr_ ) => {
+        if let Some( expr ) = expr_ {
+          self.expr(expr)?;
+        }
+        self.token(";")
+      },
+      Stmt::ConstDecl( const_decl ) => {
+        self.token("const")?;
+        self.space();
+        self.token(btype_name(const_decl.btype))?;
+        for (i, const_def) in const_decl.const_defs.iter().enumerate() {
+          if i > 0 {
+            self.token(",")?;
+          }
+          self.space();
+          self.token(&const_def.ident)?;
+          self.space();
+          self.token("=")?;
+          self.space();
+          self.asgn_rval(&const_def.rval)?;
+        }
+        self.token(";")
+      },
+      Stmt::VarDecl( var_decl ) => {
+        self.token(btype_name(var_decl.btype))?;
+        for (i, var_def) in var_decl.var_defs.iter().enumerate() {
+          if i > 0 {
+            self.token(",")?;
+          }
+          self.space();
+          self.token(&var_def.ident)?;
+          self.space();
+          self.token("=")?;
+          if let Some( rval ) = &var_def.rval_ {
+            self.space();
+            self.asgn_rval(rval)?;
+          }
+        }
+        self.token(";")
+      },
+      Stmt::Asgn( asgn ) => {
+        self.token(&asgn.lval.ident)?;
+        self.space();
+        self.token("=")?;
+        self.space();
+        self.asgn_rval(&asgn.rval)?;
+        self.token(";")
+      },
+      Stmt::Block( block ) => self.block(block),
+      Stmt::IfElse( ifelse ) => self.ifelse(ifelse),
+      Stmt::While( while_ ) => {
+        self.token("while")?;
+        self.space();
+        self.token("(")?;
+        self.expr(&while_.cond)?;
+        self.token(")")?;
+        self.body(&while_.body)
+      },
+      Stmt::Break => {
+        self.token("break")?;
+        self.token(";")
+      },
+      Stmt::Continue => {
+        self.token("continue")?;
+        self.token(";")
+      },
+      Stmt::Return( expr_ ) => {
+        self.token("return")?;
+        if let Some( expr ) = expr_ {
+          self.space();
+          self.expr(expr)?;
+        }
+        self.token(";")
+      },
+      Stmt::Assert( assert ) => {
+        self.token("assert")?;
+        self.token("(")?;
+        self.expr(&assert.cond)?;
+        if let Some( message ) = &assert.message {
+          self.token(",")?;
+          self.space();
+          self.token(&format!("\"{message}\""))?;
+        }
+        self.token(")")?;
+        self.token(";")
+      },
+    }
+  }
+
+  /// 输出 if 或 while 的语句体，Block 与条件位于同一行，其他语句另起一行并缩进
+  fn body(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    match stmt {
+      Stmt::Block( block ) => {
+        self.space();
+        self.block(block)
+      },
+      _ => {
+        self.newline();
+        self.indent += 1;
+        self.stmt_inline(stmt)?;
+        self.indent -= 1;
+        Ok(())
+      },
+    }
+  }
+
+  fn ifelse(&mut self, ifelse: &IfElse) -> Result<(), Error> {
+    self.token("if")?;
+    self.space();
+    self.token("(")?;
+    self.expr(&ifelse.cond)?;
+    self.token(")")?;
+    self.body(&ifelse.if_)?;
+    if let Some( else_ ) = &ifelse.else_ {
+      match ifelse.if_.as_ref() {
+        Stmt::Block(_) => self.space(),
+        _ => self.newline(),
+      }
+      self.token("else")?;
+      match else_.as_ref() {
+        Stmt::IfElse( ifelse ) => {
+          self.space();
+          self.ifelse(ifelse)?;
+        },
+        else_ => self.body(else_)?,
+      }
+    }
+    Ok(())
+  }
+
+  fn block(&mut self, block: &Block) -> Result<(), Error> {
+    self.token("{")?;
+    if block.stmts.is_empty() && self.pending_comment().is_none() {
+      return self.token("}");
+    }
+    self.newline();
+    self.indent += 1;
+    for stmt in &block.stmts {
+      self.stmt(stmt)?;
+    }
+    self.comments();
+    self.indent -= 1;
+    self.token("}")
+  }
+
+  fn func_def(&mut self, func_def: &FuncDef) -> Result<(), Error> {
+    self.token(match func_def.func_type {
+      FuncType::BType( btype ) => btype_name(btype),
+      FuncType::Void => "void",
+    })?;
+    self.space();
+    self.token(&func_def.ident)?;
+    self.token("(")?;
+    for (i, param) in func_def.func_fparams.iter().enumerate() {
+      if i > 0 {
+        self.token(",")?;
+        self.space();
+      }
+      self.token(btype_name(param.rval.get_btype()))?;
+      self.space();
+      self.token(&param.ident)?;
+    }
+    self.token(")")?;
+    self.space();
+    self.block(&func_def.block)
+  }
+
+  fn asgn_rval(&mut self, asgn_rval: &AsgnRVal) -> Result<(), Error> {
+    match asgn_rval {
+      AsgnRVal::Expr( expr ) => self.expr(expr),
+      AsgnRVal::Note( note ) => self.note(note),
+      AsgnRVal::Measure( measure ) => self.measure(measure),
+      AsgnRVal::Phrase( phrase ) => self.phrase(phrase),
+      AsgnRVal::Track( track ) => self.track(track),
+      AsgnRVal::DrumGrid( drum_grid ) => self.drum_grid(drum_grid),
+    }
+  }
+
+  /// 输出音符，和弦的各个音之间没有空格
+  fn note(&mut self, note: &Note) -> Result<(), Error> {
+    for (i, expr) in note.notes.iter().enumerate() {
+      if i > 0 {
+        self.token("'")?;
+      }
+      self.expr(expr)?;
+    }
+    if let Some( cents ) = &note.cents {
+      self.space();
+      self.token("~")?;
+      self.space();
+      self.expr(cents)?;
+    }
+    if let Some( len ) = &note.len {
+      self.token("=")?;
+      self.expr(len)?;
+    }
+    if let Some( syllable ) = &note.syllable {
+      self.space();
+      self.token(&format!("\"{syllable}\""))?;
+    }
+    Ok(())
+  }
+
+  fn measure(&mut self, measure: &Measure) -> Result<(), Error> {
+    self.token("|")?;
+    let mut voice_start = true;
+    for unit in &measure.content {
+      match unit {
+        MeasureUnit::VoiceSeparator => {
+          self.space();
+          self.token("&")?;
+          voice_start = true;
+          continue;
+        },
+        _ if !voice_start => self.token(",")?,
+        _ => (),
+      }
+      voice_start = false;
+      self.space();
+      match unit {
+        MeasureUnit::TimeDilation => self.token("<")?,
+        MeasureUnit::TimeCompression => self.token(">")?,
+        MeasureUnit::Rest => self.token(".")?,
+        MeasureUnit::Note( note ) => self.note(note)?,
+        MeasureUnit::VoiceSeparator => unreachable!(),
+      }
+    }
+    self.space();
+    self.token("|")
+  }
+
+  fn measure_rval(&mut self, measure_rval: &MeasureRVal) -> Result<(), Error> {
+    match measure_rval {
+      MeasureRVal::Measure( measure ) => self.measure(measure),
+      MeasureRVal::LVal( lval ) => {
+        self.token("@")?;
+        self.token(&lval.ident)
+      },
+      MeasureRVal::FuncCall( func_call ) => {
+        self.token("$")?;
+        self.func_call(func_call)
+      },
+    }
+  }
+
+  /// 输出 phrase。源文件中跨越多行时每行的小节保持在同一行，各行对应的小节线对齐
+  fn phrase(&mut self, phrase: &Phrase) -> Result<(), Error> {
+    if !self.multiline() {
+      self.token("[")?;
+      for measure_rval in &phrase.content {
+        self.space();
+        self.measure_rval(measure_rval)?;
+      }
+      self.space();
+      return self.token("]");
+    }
+
+    let aligned = !self.has_comments();
+    self.token("[")?;
+    self.newline();
+    self.indent += 1;
+    match aligned {
+      true => {
+        let mut rows: Vec<Vec<String>> = vec![];
+        for measure_rval in &phrase.content {
+          if rows.is_empty() || self.line_break() {
+            rows.push(vec![]);
+          }
+          let text = self.capture(|printer| printer.measure_rval(measure_rval))?;
+          rows.last_mut().unwrap().push(text);
+        }
+        for row in align_measures(rows) {
+          self.write(&row);
+          self.newline();
+        }
+      },
+      false => {
+        for (i, measure_rval) in phrase.content.iter().enumerate() {
+          if i > 0 {
+            match self.line_break() {
+              true => self.newline(),
+              false => self.space(),
+            }
+          }
+          self.measure_rval(measure_rval)?;
+        }
+        self.newline();
+      },
+    }
+    self.comments();
+    self.indent -= 1;
+    self.token("]")
+  }
+
+  fn phrase_rval(&mut self, phrase_rval: &PhraseRVal) -> Result<(), Error> {
+    match phrase_rval {
+      PhraseRVal::Phrase( phrase ) => self.phrase(phrase),
+      PhraseRVal::DrumGrid( drum_grid ) => self.drum_grid(drum_grid),
+      PhraseRVal::LVal( lval ) => {
+        self.token("@")?;
+        self.token(&lval.ident)
+      },
+      PhraseRVal::FuncCall( func_call ) => {
+        self.token("$")?;
+        self.func_call(func_call)
+      },
+    }
+  }
+
+  /// 输出 track。源文件中跨越多行时每行的 phrase 保持在同一行
+  fn track(&mut self, track: &Track) -> Result<(), Error> {
+    if !self.multiline() {
+      self.token("{")?;
+      for phrase_rval in &track.content {
+        self.space();
+        self.phrase_rval(phrase_rval)?;
+      }
+      self.space();
+      return self.token("}");
+    }
+
+    self.token("{")?;
+    self.newline();
+    self.indent += 1;
+    for (i, phrase_rval) in track.content.iter().enumerate() {
+      if i > 0 {
+        match self.line_break() {
+          true => self.newline(),
+          false => self.space(),
+        }
+      }
+      self.phrase_rval(phrase_rval)?;
+    }
+    self.newline();
+    self.comments();
+    self.indent -= 1;
+    self.token("}")
+  }
+
+  fn track_rval(&mut self, track_rval: &TrackRVal) -> Result<(), Error> {
+    match track_rval {
+      TrackRVal::Track( track ) => self.track(track),
+      TrackRVal::LVal( lval ) => self.token(&lval.ident),
+      TrackRVal::FuncCall( func_call ) => self.func_call(func_call),
+    }
+  }
+
+  /// 输出鼓组网格。源文件中跨越多行时每行一个乐器，各行的节奏对齐
+  fn drum_grid(&mut self, drum_grid: &DrumGrid) -> Result<(), Error> {
+    self.token("drums")?;
+    if let Some( steps ) = &drum_grid.steps {
+      self.token("(")?;
+      self.expr(steps)?;
+      self.token(")")?;
+    }
+    self.space();
+    if !self.multiline() {
+      self.token("{")?;
+      for row in &drum_grid.rows {
+        self.space();
+        self.drum_row_note(row)?;
+        self.space();
+        self.drum_row_pattern(row)?;
+      }
+      self.space();
+      return self.token("}");
+    }
+
+    let aligned = !self.has_comments();
+    self.token("{")?;
+    self.newline();
+    self.indent += 1;
+    match aligned {
+      true => {
+        let mut rows = vec![];
+        for row in &drum_grid.rows {
+          let note = self.capture(|printer| printer.drum_row_note(row))?;
+          let pattern = self.capture(|printer| printer.drum_row_pattern(row))?;
+          rows.push((note, pattern));
+        }
+        let width = rows.iter().map(|(note, _)| note.chars().count()).max().unwrap_or(0);
+        for (note, pattern) in rows {
+          let padding = " ".repeat(width - note.chars().count());
+          self.write(&format!("{note}{padding} {pattern}"));
+          self.newline();
+        }
+      },
+      false => {
+        for row in &drum_grid.rows {
+          self.drum_row_note(row)?;
+          self.space();
+          self.drum_row_pattern(row)?;
+          self.newline();
+        }
+      },
+    }
+    self.comments();
+    self.indent -= 1;
+    self.token("}")
+  }
+
+  /// 输出鼓组网格一行的 `note:`
+  fn drum_row_note(&mut self, row: &DrumRow) -> Result<(), Error> {
+    self.expr(&row.note)?;
+    self.token(":")
+  }
+
+  /// 输出鼓组网格一行的 `"pattern";`
+  fn drum_row_pattern(&mut self, row: &DrumRow) -> Result<(), Error> {
+    self.token(&format!("\"{}\"", row.pattern))?;
+    self.token(";")
+  }
+
+  fn func_call(&mut self, func_call: &FuncCall) -> Result<(), Error> {
+    self.token(&func_call.ident)?;
+    self.token("(")?;
+    for (i, asgn_rval) in func_call.func_rparams.iter().enumerate() {
+      if i > 0 {
+        self.token(",")?;
+        self.space();
+      }
+      self.asgn_rval(asgn_rval)?;
+    }
+    self.token(")")
+  }
+
+  /// 输出逗号分隔的表达式
+  fn exprs(&mut self, exprs: &[Expr]) -> Result<(), Error> {
+    for (i, expr) in exprs.iter().enumerate() {
+      if i > 0 {
+        self.token(",")?;
+        self.space();
+      }
+      self.expr(expr)?;
+    }
+    Ok(())
+  }
+
+  /// 输出表达式，二元运算符两侧各有一个空格
+  fn expr(&mut self, expr: &Expr) -> Result<(), Error> {
+    for (i, land_exp) in expr.land_exps.iter().enumerate() {
+      if i > 0 {
+        self.binary_op("||")?;
+      }
+      for (j, eq_exp) in land_exp.eq_exps.iter().enumerate() {
+        if j > 0 {
+          self.binary_op("&&")?;
+        }
+        for (k, rel_exp) in eq_exp.rel_exps.iter().enumerate() {
+          if k > 0 {
+            self.binary_op(match eq_exp.eq_ops[k - 1] {
+              EqOp::Eq => "==",
+              EqOp::Ne => "!=",
+            })?;
+          }
+          for (l, add_exp) in rel_exp.add_exps.iter().enumerate() {
+            if l > 0 {
+              self.binary_op(match rel_exp.rel_ops[l - 1] {
+                RelOp::Gt => ">",
+                RelOp::Lt => "<",
+                RelOp::Ge => ">=",
+                RelOp::Le => "<=",
+              })?;
+            }
+            for (m, mul_exp) in add_exp.mul_exps.iter().enumerate() {
+              if m > 0 {
+                self.binary_op(match add_exp.add_ops[m - 1] {
+                  AddOp::Add => "+",
+                  AddOp::Sub => "-",
+                })?;
+              }
+              for (n, unary_exp) in mul_exp.unary_exps.iter().enumerate() {
+                if n > 0 {
+                  self.binary_op(match mul_exp.mul_ops[n - 1] {
+                    MulOp::Mul => "*",
+                    MulOp::Div => "/",
+                    MulOp::Mod => "%",
+                  })?;
+                }
+                self.unary_expr(unary_exp)?;
+              }
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn binary_op(&mut self, op: &str) -> Result<(), Error> {
+    self.space();
+    self.token(op)?;
+    self.space();
+    Ok(())
+  }
+
+  /// 输出一元表达式，语法分析时最外层的运算符最后加入 unary_ops
+  fn unary_expr(&mut self, unary_exp: &UnaryExpr) -> Result<(), Error> {
+    for unary_op in unary_exp.unary_ops.iter().rev() {
+      self.token(match unary_op {
+        UnaryOp::Plus => "+",
+        UnaryOp::Minus => "-",
+        UnaryOp::Not => "!",
+      })?;
+    }
+    match &unary_exp.primary_exp {
+      PrimaryExpr::Expr( expr ) => {
+        self.token("(")?;
+        self.expr(expr)?;
+        self.token(")")
+      },
+      PrimaryExpr::LVal( lval ) => self.token(&lval.ident),
+      PrimaryExpr::Number(_) => self.literal(),
+      PrimaryExpr::FuncCall( func_call ) => self.func_call(func_call),
+      PrimaryExpr::Str( str ) => self.token(&format!("\"{str}\"")),
+    }
+  }
+}
+
+/// 源代码中的类型名称
+fn btype_name(btype: BType) -> &'static str {
+  match btype {
+    BType::Int | BType::Bool => "int",
+    BType::Note => "note",
+    BType::Measure => "measure",
+    BType::Phrase => "phrase",
+    BType::Track => "track",
+    BType::Str => "string",
+  }
+}
+
+/// 将 phrase 各行的小节按列对齐，同一列中较短的小节在结束的小节线之前补齐空格，
+/// `@measure` 和 `$func()` 在之后补齐空格。只有一行时不对齐
+fn align_measures(rows: Vec<Vec<String>>) -> Vec<String> {
+  if rows.len() < 2 {
+    return rows.into_iter().map(|row| row.join(" ")).collect();
+  }
+  let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+  let widths: Vec<usize> = (0..columns)
+    .map(|column| rows.iter()
+      .filter_map(|row| row.get(column))
+      .map(|text| text.chars().count())
+      .max()
+      .unwrap_or(0))
+    .collect();
+  rows.into_iter()
+    .map(|row| {
+      let cells: Vec<String> = row.into_iter().zip(&widths)
+        .map(|(text, width)| {
+          let padding = " ".repeat(width - text.chars().count());
+          match text.strip_suffix('|') {
+            Some( body ) if text.starts_with('|') => format!("{body}{padding}|"),
+            _ => format!("{text}{padding}"),
+          }
+        })
+        .collect();
+      cells.join(" ").trim_end().to_string()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SOURCE: &str = concat!(
+    "// 全局常量\n",
+    "const int base=60;\n",
+    "int at = 1;\n",
+    "instrument pad { wave = \"sine\"; attack = 10; }\n",
+    "note root( int n ){\n",
+    "  if(n>0){return base+n;}else{ return base; }\n",
+    "}\n",
+    "measure verse = | root(0), 62=2, . |;\n",
+    "phrase p = [ @verse\n",
+    "             @verse ];\n",
+    "\n",
+    "\n",
+    "test \"verse\" { assert(root(2)==62, \"root adds\"); }\n",
+    "@score{\n",
+    "  track t = { @p };\n",
+    "  @tempo=120;\n",
+    "  @0 -> pad;\n",
+    "  @0 name = \"lead\";\n",
+    "  @9 <- { drums(4) { kick: \"x.x.\"; } };\n",
+    "  @0 <- t at bar 2 beat 1.5;\n",
+    "  @keysig = F# minor at bar at;\n",
+    "}\n",
+  );
+
+  const FORMATTED: &str = concat!(
+    "// 全局常量\n",
+    "const int base = 60;\n",
+    "int at = 1;\n",
+    "instrument pad {\n",
+    "  wave = \"sine\";\n",
+    "  attack = 10;\n",
+    "}\n",
+    "note root(int n) {\n",
+    "  if (n > 0) {\n",
+    "    return base + n;\n",
+    "  } else {\n",
+    "    return base;\n",
+    "  }\n",
+    "}\n",
+    "measure verse = | root(0), 62=2, . |;\n",
+    "phrase p = [\n",
+    "  @verse\n",
+    "  @verse\n",
+    "];\n",
+    "\n",
+    "test \"verse\" {\n",
+    "  assert(root(2) == 62, \"root adds\");\n",
+    "}\n",
+    "@score {\n",
+    "  track t = { @p };\n",
+    "  @tempo = 120;\n",
+    "  @0 -> pad;\n",
+    "  @0 name = \"lead\";\n",
+    "  @9 <- { drums(4) { kick: \"x.x.\"; } };\n",
+    "  @0 <- t at bar 2 beat 1.5;\n",
+    "  @keysig = F# minor at bar at;\n",
+    "}\n",
+  );
+
+  #[test]
+  fn format_source() {
+    assert_eq!(Formatter::new().format(SOURCE).unwrap(), FORMATTED);
+  }
+
+  #[test]
+  fn format_is_idempotent() {
+    let formatter = Formatter::new();
+    let formatted = formatter.format(SOURCE).unwrap();
+    assert_eq!(formatter.format(&formatted).unwrap(), formatted);
+    assert_eq!(formatter.format(FORMATTED).unwrap(), FORMATTED);
+  }
+}
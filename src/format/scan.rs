@@ -0,0 +1,75 @@
+use crate::ast::span::Span;
+use crate::error::Error;
+
+/// 由两个字符组成的符号，按最长匹配优先于单个字符的符号
+const DOUBLE_PUNCTS: [&str; 8] = ["<-", "->", ">=", "<=", "==", "!=", "&&", "||"];
+
+/// 由单个字符组成的符号
+const SINGLE_PUNCTS: &str = "!#$%&'()*+,-./:;<=>@[]{|}~";
+
+/// 按与语法分析器的词法规则相同的方式切分源文件，返回所有 token 和所有注释的位置。
+/// 注释不含行尾的换行符
+pub(super) fn scan(source: &str) -> Result<(Vec<Span>, Vec<Span>), Error> {
+  let bytes = source.as_bytes();
+  let mut tokens = vec![];
+  let mut comments = vec![];
+  let mut i = 0;
+  while i < source.len() {
+    let rest = &source[i..];
+    let c = rest.chars().next().unwrap();
+    if c.is_whitespace() {
+      i += c.len_utf8();
+      continue;
+    }
+    if rest.starts_with("//") {
+      let len = rest.find(['\n', '\r']).unwrap_or(rest.len());
+      comments.push(Span::new(i, i + len));
+      i += len;
+      continue;
+    }
+
+    let len = if c == '"' {
+      match rest[1..].find(['"', '\n', '\r']) {
+        Some( end ) if rest[1 + end..].starts_with('"') => end + 2,
+        _ => return Err(Error::ParseError(format!("Invalid token found at {i}"))),
+      }
+    } else if c.is_ascii_digit() {
+      number_len(&bytes[i..])
+    } else if c == '_' || c.is_ascii_alphabetic() {
+      rest.find(|c: char| c != '_' && !c.is_ascii_alphanumeric()).unwrap_or(rest.len())
+    } else if DOUBLE_PUNCTS.iter().any(|punct| rest.starts_with(punct)) {
+      2
+    } else if SINGLE_PUNCTS.contains(c) {
+      1
+    } else {
+      return Err(Error::ParseError(format!("Invalid token found at {i}")));
+    };
+    tokens.push(Span::new(i, i + len));
+    i += len;
+  }
+  Ok((tokens, comments))
+}
+
+/// 以数字开头的 token 的长度，依次尝试十六进制整数、小数、八进制整数和十进制整数
+fn number_len(bytes: &[u8]) -> usize {
+  let digits = |from: usize, pred: fn(&u8) -> bool| {
+    bytes[from..].iter().take_while(|b| pred(b)).count()
+  };
+  if bytes[0] == b'0' && matches!(bytes.get(1), Some(b'x' | b'X')) {
+    let hex = digits(2, u8::is_ascii_hexdigit);
+    if hex > 0 {
+      return 2 + hex;
+    }
+  }
+  let int = digits(0, u8::is_ascii_digit);
+  if bytes.get(int) == Some(&b'.') {
+    let frac = digits(int + 1, u8::is_ascii_digit);
+    if frac > 0 {
+      return int + 1 + frac;
+    }
+  }
+  match bytes[0] {
+    b'0' => 1 + digits(1, |b| (b'0'..=b'7').contains(b)),
+    _ => int,
+  }
+}
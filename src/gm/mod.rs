@@ -0,0 +1,185 @@
+/// General MIDI 标准的 128 种乐器名称，下标即为从 0 开始的 program 编号
+pub const INSTRUMENTS: [&str; 128] = [
+  // Piano
+  "Acoustic Grand Piano", "Bright Acoustic Piano", "Electric Grand Piano", "Honky-tonk Piano",
+  "Electric Piano 1", "Electric Piano 2", "Harpsichord", "Clavi",
+  // Chromatic Percussion
+  "Celesta", "Glockenspiel", "Music Box", "Vibraphone",
+  "Marimba", "Xylophone", "Tubular Bells", "Dulcimer",
+  // Organ
+  "Drawbar Organ", "Percussive Organ", "Rock Organ", "Church Organ",
+  "Reed Organ", "Accordion", "Harmonica", "Tango Accordion",
+  // Guitar
+  "Acoustic Guitar (nylon)", "Acoustic Guitar (steel)", "Electric Guitar (jazz)", "Electric Guitar (clean)",
+  "Electric Guitar (muted)", "Overdriven Guitar", "Distortion Guitar", "Guitar Harmonics",
+  // Bass
+  "Acoustic Bass", "Electric Bass (finger)", "Electric Bass (pick)", "Fretless Bass",
+  "Slap Bass 1", "Slap Bass 2", "Synth Bass 1", "Synth Bass 2",
+  // Strings
+  "Violin", "Viola", "Cello", "Contrabass",
+  "Tremolo Strings", "Pizzicato Strings", "Orchestral Harp", "Timpani",
+  // Ensemble
+  "String Ensemble 1", "String Ensemble 2", "Synth Strings 1", "Synth Strings 2",
+  "Choir Aahs", "Voice Oohs", "Synth Voice", "Orchestra Hit",
+  // Brass
+  "Trumpet", "Trombone", "Tuba", "Muted Trumpet",
+  "French Horn", "Brass Section", "Synth Brass 1", "Synth Brass 2",
+  // Reed
+  "Soprano Sax", "Alto Sax", "Tenor Sax", "Baritone Sax",
+  "Oboe", "English Horn", "Bassoon", "Clarinet",
+  // Pipe
+  "Piccolo", "Flute", "Recorder", "Pan Flute",
+  "Blown Bottle", "Shakuhachi", "Whistle", "Ocarina",
+  // Synth Lead
+  "Lead 1 (square)", "Lead 2 (sawtooth)", "Lead 3 (calliope)", "Lead 4 (chiff)",
+  "Lead 5 (charang)", "Lead 6 (voice)", "Lead 7 (fifths)", "Lead 8 (bass + lead)",
+  // Synth Pad
+  "Pad 1 (new age)", "Pad 2 (warm)", "Pad 3 (polysynth)", "Pad 4 (choir)",
+  "Pad 5 (bowed)", "Pad 6 (metallic)", "Pad 7 (halo)", "Pad 8 (sweep)",
+  // Synth Effects
+  "FX 1 (rain)", "FX 2 (soundtrack)", "FX 3 (crystal)", "FX 4 (atmosphere)",
+  "FX 5 (brightness)", "FX 6 (goblins)", "FX 7 (echoes)", "FX 8 (sci-fi)",
+  // Ethnic
+  "Sitar", "Banjo", "Shamisen", "Koto",
+  "Kalimba", "Bag pipe", "Fiddle", "Shanai",
+  // Percussive
+  "Tinkle Bell", "Agogo", "Steel Drums", "Woodblock",
+  "Taiko Drum", "Melodic Tom", "Synth Drum", "Reverse Cymbal",
+  // Sound Effects
+  "Guitar Fret Noise", "Breath Noise", "Seashore", "Bird Tweet",
+  "Telephone Ring", "Helicopter", "Applause", "Gunshot",
+];
+
+/// General MIDI 标准打击乐(channel 9)的音符名称及其音高
+pub const PERCUSSION: [(&str, i32); 47] = [
+  ("Acoustic Bass Drum", 35), ("Bass Drum 1", 36), ("Side Stick", 37), ("Acoustic Snare", 38),
+  ("Hand Clap", 39), ("Electric Snare", 40), ("Low Floor Tom", 41), ("Closed Hi-Hat", 42),
+  ("High Floor Tom", 43), ("Pedal Hi-Hat", 44), ("Low Tom", 45), ("Open Hi-Hat", 46),
+  ("Low-Mid Tom", 47), ("Hi-Mid Tom", 48), ("Crash Cymbal 1", 49), ("High Tom", 50),
+  ("Ride Cymbal 1", 51), ("Chinese Cymbal", 52), ("Ride Bell", 53), ("Tambourine", 54),
+  ("Splash Cymbal", 55), ("Cowbell", 56), ("Crash Cymbal 2", 57), ("Vibraslap", 58),
+  ("Ride Cymbal 2", 59), ("Hi Bongo", 60), ("Low Bongo", 61), ("Mute Hi Conga", 62),
+  ("Open Hi Conga", 63), ("Low Conga", 64), ("High Timbale", 65), ("Low Timbale", 66),
+  ("High Agogo", 67), ("Low Agogo", 68), ("Cabasa", 69), ("Maracas", 70),
+  ("Short Whistle", 71), ("Long Whistle", 72), ("Short Guiro", 73), ("Long Guiro", 74),
+  ("Claves", 75), ("Hi Wood Block", 76), ("Low Wood Block", 77), ("Mute Cuica", 78),
+  ("Open Cuica", 79), ("Mute Triangle", 80), ("Open Triangle", 81),
+];
+
+/// 常用打击乐的简称
+pub const PERCUSSION_ALIASES: [(&str, i32); 12] = [
+  ("kick", 36), ("rim", 37), ("snare", 38), ("clap", 39),
+  ("closed_hat", 42), ("pedal_hat", 44), ("open_hat", 46), ("low_tom", 45),
+  ("mid_tom", 47), ("high_tom", 50), ("crash", 49), ("ride", 51),
+];
+
+/// 将名称转为标识符形式，例如 "Closed Hi-Hat" 转为 closed_hi_hat
+pub fn to_ident(name: &str) -> String {
+  let mut ident = String::new();
+  for c in name.chars() {
+    if c.is_ascii_alphanumeric() {
+      ident.push(c.to_ascii_lowercase());
+    } else if !ident.is_empty() && !ident.ends_with('_') {
+      ident.push('_');
+    }
+  }
+  ident.trim_end_matches('_').to_string()
+}
+
+/// 比较名称时忽略大小写、空格、下划线和标点
+fn normalize(name: &str) -> String {
+  name.chars()
+    .filter(|c| c.is_ascii_alphanumeric())
+    .map(|c| c.to_ascii_lowercase())
+    .collect()
+}
+
+/// 由乐器名称查找从 0 开始的 program 编号，名称可以是 "Acoustic Grand Piano" 或 acoustic_grand_piano
+pub fn instrument_by_name(name: &str) -> Option<i32> {
+  let key = normalize(name);
+  INSTRUMENTS.iter()
+    .position(|instr| normalize(instr) == key)
+    .map(|i| i as i32)
+}
+
+/// 由打击乐名称或简称查找音高，名称可以是 "Closed Hi-Hat"、closed_hi_hat 或 closed_hat
+pub fn percussion_by_name(name: &str) -> Option<i32> {
+  let key = normalize(name);
+  PERCUSSION.iter()
+    .chain(PERCUSSION_ALIASES.iter())
+    .find(|(percussion, _)| normalize(percussion) == key)
+    .map(|(_, note)| *note)
+}
+
+/// 两个字符串的编辑距离
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut prev: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut cur = vec![i + 1];
+    for (j, cb) in b.iter().enumerate() {
+      let cost = if ca == *cb { 0 } else { 1 };
+      cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+    }
+    prev = cur;
+  }
+  prev[b.len()]
+}
+
+/// 从候选名称中找出与 name 相近的名称，以标识符形式返回，最多 5 个
+fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+  let key = normalize(name);
+  let mut scored: Vec<(usize, String)> = candidates
+    .filter_map(|candidate| {
+      let candidate_key = normalize(candidate);
+      let distance = edit_distance(&key, &candidate_key);
+      let similar = distance <= 2.max(key.len() / 3)
+        || (key.len() >= 3 && candidate_key.contains(&key));
+      similar.then(|| (distance, to_ident(candidate)))
+    })
+    .collect();
+  scored.sort();
+  scored.dedup_by(|a, b| a.1 == b.1);
+  scored.into_iter().take(5).map(|(_, ident)| ident).collect()
+}
+
+/// 与 name 相近的乐器名称
+pub fn suggest_instruments(name: &str) -> Vec<String> {
+  suggest(name, INSTRUMENTS.iter().copied())
+}
+
+/// 与 name 相近的打击乐名称
+pub fn suggest_percussion(name: &str) -> Vec<String> {
+  suggest(name, PERCUSSION.iter().chain(PERCUSSION_ALIASES.iter()).map(|(name, _)| *name))
+}
+
+/// 将候选名称格式化为附加在错误信息后的提示，没有候选时为空
+pub fn did_you_mean(suggestions: &[String]) -> String {
+  match suggestions.is_empty() {
+    true => String::new(),
+    false => format!(", did you mean: {}?", suggestions.join(", ")),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn instrument_names_map_to_zero_based_programs() {
+    assert_eq!(instrument_by_name("Acoustic Grand Piano"), Some(0));
+    assert_eq!(instrument_by_name("acoustic_grand_piano"), Some(0));
+    assert_eq!(instrument_by_name("violin"), Some(40));
+    assert_eq!(instrument_by_name("Trumpet"), Some(56));
+    assert_eq!(instrument_by_name("gunshot"), Some(127));
+    for (i, name) in INSTRUMENTS.iter().enumerate() {
+      assert_eq!(instrument_by_name(&to_ident(name)), Some(i as i32));
+    }
+  }
+
+  #[test]
+  fn percussion_names_are_not_instruments() {
+    assert_eq!(instrument_by_name("cowbell"), None);
+    assert_eq!(instrument_by_name("kick"), None);
+  }
+}
@@ -0,0 +1,746 @@
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+
+use super::{ImportedScore, PERCUSSION_CHANNEL};
+
+/// 读取 ABC 时每个四分音符所占的 tick 数，可以被三连音、五连音整除
+const ABC_PPQ: u64 = 480;
+
+/// 按五度圈排列的音名，有 n 个升号的调升高前 n 个音，有 n 个降号的调降低后 n 个音
+const FIFTHS_ORDER: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// 音名及其在 C 大调中的音级
+const STEPS: [(char, i32); 7] = [('C', 0), ('D', 2), ('E', 4), ('F', 5), ('G', 7), ('A', 9), ('B', 11)];
+
+/// 大调主音的名称，下标为升降号数加 7
+const MAJOR_TONICS: [&str; 15] = ["Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#"];
+
+/// ABC 中的一个声部，即 `V:` 字段
+#[derive(Default)]
+struct Voice {
+  id: String,
+
+  /// 声部的乐谱正文，正文中的 `K:`、`L:`、`M:` 字段行转换为内联字段
+  body: String,
+
+  /// `%%MIDI program` 指定的乐器，从 0 开始
+  program: Option<u8>,
+
+  /// `%%MIDI channel` 指定的 channel，从 0 开始
+  channel: Option<u8>,
+}
+
+/// 两个小节线之间的一段乐谱
+struct Bar {
+  text: String,
+
+  /// 前面的小节线是否为反复开始
+  start_repeat: bool,
+
+  /// 所在的反复跳跃记号，如 `[1`
+  ending: Option<u32>,
+
+  /// 后面的小节线是否为反复结束
+  end_repeat: bool,
+}
+
+/// 一个声部的读取状态
+struct Reader {
+  /// 单位音符长度，即 `L:`，以全音符的分数表示
+  unit: (u64, u64),
+
+  /// 拍号，即 `M:`
+  meter: (u64, u64),
+
+  /// 调号对各音名的变音
+  key: [i8; 7],
+
+  /// 小节内临时记号对各 (音名, 八度) 的变音
+  accidentals: BTreeMap<(usize, i32), i8>,
+
+  tick: u64,
+
+  /// (开始, 结束, 音高)
+  notes: Vec<(u64, u64, u8)>,
+
+  /// 用连音线与下一个音符连接的音符在 notes 中的下标
+  tied: Vec<usize>,
+
+  /// 上一个音符、和弦或休止符的 (开始, 时值, 在 notes 中的下标)，用于附点节奏
+  last: Option<(u64, u64, Vec<usize>)>,
+
+  /// 附点节奏中下一个音符的时值倍数
+  broken: Option<(u64, u64)>,
+
+  /// 连音 (p, q, 剩余音符数)，即 p 个音符占 q 个音符的时值
+  tuplet: Option<(u64, u64, u64)>,
+}
+
+/// 读取 ABC 记谱法的第一首乐曲。
+/// 头部的 `T:`、`M:`、`L:`、`Q:`、`K:` 字段转换为乐曲名称、拍号、速度和调号，
+/// 每个 `V:` 声部为一个 channel，`%%MIDI program` 和 `%%MIDI channel` 指令设置声部的乐器和 channel
+pub(super) fn read(text: &str) -> Result<ImportedScore, Error> {
+  let mut score = ImportedScore{ppq: ABC_PPQ, ..Default::default()};
+  let mut meter_ = None;
+  let mut unit_ = None;
+  let mut tempo_ = None;
+  let mut key_ = None;
+  let mut in_body = false;
+  let mut voices: Vec<Voice> = vec![];
+  let mut current = 0;
+  for line in text.lines() {
+    if let Some(directive) = line.strip_prefix("%%") {
+      let words: Vec<&str> = directive.split_whitespace().collect();
+      if let ["MIDI", command, value, ..] = words[..] {
+        let value: u8 = value.parse().map_err(|_| Error::ParseError(format!(
+          "invalid %%MIDI {command} value: {value}"
+        )))?;
+        if voices.is_empty() {
+          voices.push(Voice::default());
+        }
+        match command {
+          "program" => voices[current].program = Some(value.min(127)),
+          "channel" => voices[current].channel = Some(value.clamp(1, 16) - 1),
+          _ => {},
+        }
+      }
+      continue;
+    }
+    let line = line.split('%').next().unwrap_or("").trim();
+
+    // 空行为乐曲的结束
+    if line.is_empty() {
+      if in_body {
+        break;
+      }
+      continue;
+    }
+
+    let field = match line.as_bytes() {
+      [name, b':', ..] if name.is_ascii_alphabetic() => Some((*name as char, line[2..].trim())),
+      _ => None,
+    };
+    match (field, in_body) {
+      (Some( ('V', value) ), _) => {
+        current = voice_index(&mut voices, value);
+      },
+      (Some( (name, value) ), false) => match name {
+        'T' if score.title.is_none() => score.title = Some(value.to_string()),
+        'M' => meter_ = parse_meter(value)?,
+        'L' => unit_ = Some(parse_fraction(value)?),
+        'Q' => tempo_ = Some(value.to_string()),
+        'K' => {
+          key_ = parse_key(value)?;
+          in_body = true;
+        },
+        _ => {},
+      },
+      (Some( (name @ ('K' | 'L' | 'M'), value) ), true) => {
+        if voices.is_empty() {
+          voices.push(Voice::default());
+        }
+        voices[current].body.push_str(&format!("[{name}:{value}]"));
+      },
+      (Some(_), true) => {},
+      (None, true) => {
+        // 行首的内联声部字段，如 [V:1] abc|
+        let mut line = line;
+        if let Some(rest) = line.strip_prefix("[V:") && let Some((id, rest)) = rest.split_once(']') {
+          current = voice_index(&mut voices, id);
+          line = rest;
+        }
+        if voices.is_empty() {
+          voices.push(Voice::default());
+        }
+        voices[current].body.push_str(line);
+        voices[current].body.push(' ');
+      },
+      (None, false) => {},
+    }
+  }
+  if !in_body {
+    return Err(Error::ParseError(
+      "ABC tune must have a K: field".to_string()
+    ));
+  }
+
+  // 单位音符长度缺省时，拍号小于 3/4 为十六分音符，否则为八分音符
+  let meter = meter_.unwrap_or((4, 4));
+  let unit = unit_.unwrap_or(match 4 * meter.0 < 3 * meter.1 {
+    true => (1, 16),
+    false => (1, 8),
+  });
+  score.time_sig = meter_.map(|(numerator, denominator)| (numerator as u32, denominator as u32));
+  score.tempo = tempo_.and_then(|tempo| parse_tempo(&tempo, unit));
+  let key = match &key_ {
+    Some( (fifths, _) ) => key_accidentals(*fifths),
+    None => [0; 7],
+  };
+  score.key = key_.map(|(_, key)| key);
+
+  // 指定了 channel 的声部使用该 channel，其余声部依次使用未被占用的 channel，跳过打击乐 channel
+  let mut used: Vec<u8> = voices.iter().filter_map(|voice| voice.channel).collect();
+  for voice in voices {
+    let channel = match voice.channel {
+      Some( channel ) => channel,
+      None => {
+        let channel = (0..16).find(|channel| *channel != PERCUSSION_CHANNEL && !used.contains(channel))
+          .ok_or(Error::ParseError(format!("too many ABC voices, no channel left for voice {}", voice.id)))?;
+        used.push(channel);
+        channel
+      },
+    };
+    let mut reader = Reader {
+      unit,
+      meter,
+      key,
+      accidentals: BTreeMap::new(),
+      tick: 0,
+      notes: vec![],
+      tied: vec![],
+      last: None,
+      broken: None,
+      tuplet: None,
+    };
+    for bar in expand_repeats(split_bars(&voice.body)) {
+      reader.read_bar(&bar)?;
+    }
+    score.notes.entry(channel).or_default().extend(reader.notes);
+    if let Some(program) = voice.program {
+      score.programs.entry(channel).or_insert(program);
+    }
+  }
+  Ok(score)
+}
+
+/// 声部在 voices 中的下标，没有时新建。字段值的第一个词为声部的编号
+fn voice_index(voices: &mut Vec<Voice>, value: &str) -> usize {
+  let id = value.split_whitespace().next().unwrap_or("").to_string();
+  match voices.iter().position(|voice| voice.id == id) {
+    Some( index ) => index,
+    None => {
+      // 在 V: 之前出现的指令属于第一个声部
+      if let [voice] = &mut voices[..] && voice.id.is_empty() && voice.body.is_empty() {
+        voice.id = id;
+        return 0;
+      }
+      voices.push(Voice{id, ..Default::default()});
+      voices.len() - 1
+    },
+  }
+}
+
+/// 解析分数，如 `1/8`，没有分母时为整数
+fn parse_fraction(value: &str) -> Result<(u64, u64), Error> {
+  let invalid = || Error::ParseError(format!("invalid ABC fraction: {value}"));
+  let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+  let numerator = numerator.trim().parse().map_err(|_| invalid())?;
+  let denominator = denominator.trim().parse().map_err(|_| invalid())?;
+  match (numerator, denominator) {
+    (0, _) | (_, 0) => Err(invalid()),
+    fraction => Ok(fraction),
+  }
+}
+
+/// 解析拍号，`C` 为 4/4，`C|` 为 2/2，分子可以是 `2+3` 这样的和，`none` 为无拍号
+fn parse_meter(value: &str) -> Result<Option<(u64, u64)>, Error> {
+  match value {
+    "" | "none" => Ok(None),
+    "C" => Ok(Some((4, 4))),
+    "C|" => Ok(Some((2, 2))),
+    _ => {
+      let (numerator, denominator) = value.split_once('/').ok_or(Error::ParseError(format!(
+        "invalid ABC meter: {value}"
+      )))?;
+      let numerator = numerator.split('+')
+        .map(|n| n.trim().parse::<u64>())
+        .sum::<Result<u64, _>>()
+        .map_err(|_| Error::ParseError(format!("invalid ABC meter: {value}")))?;
+      let (_, denominator) = parse_fraction(&format!("1/{denominator}"))?;
+      Ok(Some((numerator, denominator)))
+    },
+  }
+}
+
+/// 解析速度，得到每分钟的四分音符数。可以是 `1/4=120`、`3/8=60` 或只有一个数，
+/// 只有一个数时为每分钟的单位音符数
+fn parse_tempo(value: &str, unit: (u64, u64)) -> Option<u32> {
+  // 去掉引号中的文字，如 "Allegro"
+  let value: String = value.split('"').step_by(2).collect();
+  let (beats, bpm) = match value.split_once('=') {
+    Some( (beats, bpm) ) => {
+      let beats = beats.split_whitespace()
+        .map(|beat| parse_fraction(beat).ok())
+        .collect::<Option<Vec<_>>>()?;
+      (beats, bpm)
+    },
+    None => (vec![unit], value.as_str()),
+  };
+  let bpm: f64 = bpm.trim().parse().ok()?;
+  let quarters: f64 = beats.iter().map(|(n, d)| 4.0 * *n as f64 / *d as f64).sum();
+  Some((bpm * quarters).round() as u32)
+}
+
+/// 解析调号，得到升降号数和 yam 的调号，如 `Bb major`。
+/// 教会调式转换为升降号相同的大调，`none` 以及空的调号没有调号
+fn parse_key(value: &str) -> Result<Option<(i32, String)>, Error> {
+  let invalid = || Error::ParseError(format!("invalid ABC key: {value}"));
+  let words: Vec<&str> = value.split_whitespace().filter(|word| !word.contains('=')).collect();
+  let (tonic, mode) = match words[..] {
+    [] | ["none", ..] | ["HP", ..] | ["Hp", ..] => return Ok(None),
+    [tonic] => (tonic, ""),
+    [tonic, mode, ..] => (tonic, mode),
+  };
+
+  let mut chars = tonic.chars();
+  let letter = chars.next().filter(|c| ('A'..='G').contains(c)).ok_or_else(invalid)?;
+  let mut rest = chars.as_str();
+  let mut fifths = FIFTHS_ORDER.iter().position(|step| *step == letter).unwrap() as i32 - 1;
+  let mut name = letter.to_string();
+  if let Some(accidental) = rest.chars().next() && (accidental == '#' || accidental == 'b') {
+    fifths += match accidental {
+      '#' => 7,
+      _ => -7,
+    };
+    name.push(accidental);
+    rest = &rest[1..];
+  }
+  // 调式可以紧跟主音，也可以用空格隔开，只比较前三个字母
+  let mode = match rest {
+    "" => mode,
+    _ => rest,
+  }.to_lowercase();
+  let (offset, minor) = match &mode[..mode.len().min(3)] {
+    "" | "maj" | "ion" => (0, false),
+    "m" | "min" | "aeo" => (-3, true),
+    "mix" => (-1, false),
+    "dor" => (-2, false),
+    "phr" => (-4, false),
+    "lyd" => (1, false),
+    "loc" => (-5, false),
+    "exp" => (0, false),
+    _ => return Err(invalid()),
+  };
+  fifths += offset;
+  if !(-7..=7).contains(&fifths) {
+    return Err(invalid());
+  }
+  let key = match minor {
+    true => format!("{name} minor"),
+    false => format!("{} major", MAJOR_TONICS[(fifths + 7) as usize]),
+  };
+  Ok(Some((fifths, key)))
+}
+
+/// 调号对各音名(按 STEPS 的顺序)的变音
+fn key_accidentals(fifths: i32) -> [i8; 7] {
+  let mut key = [0; 7];
+  for (i, (step, _)) in STEPS.iter().enumerate() {
+    let position = FIFTHS_ORDER.iter().position(|s| s == step).unwrap() as i32;
+    key[i] = match fifths {
+      f if f > 0 && position < f => 1,
+      f if f < 0 && position >= 7 + f => -1,
+      _ => 0,
+    };
+  }
+  key
+}
+
+/// 按小节线将正文拆分为小节。引号中的文字、装饰记号和内联字段中的字符不作为小节线
+fn split_bars(body: &str) -> Vec<Bar> {
+  let chars: Vec<char> = body.chars().collect();
+  let mut bars: Vec<Bar> = vec![];
+  let mut text = String::new();
+  let mut start_repeat = false;
+  let mut ending = None;
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let next = chars.get(i + 1).copied();
+    let is_barline_start = match c {
+      '|' => true,
+      ':' => matches!(next, Some('|' | ':')),
+      '[' => next.is_some_and(|n| n == '|' || n.is_ascii_digit()),
+      _ => false,
+    };
+    if !is_barline_start {
+      // 引号和装饰记号中的内容原样保留
+      let close = match c {
+        '"' => Some('"'),
+        '!' => Some('!'),
+        '[' if next.is_some_and(|n| n.is_ascii_alphabetic()) && chars.get(i + 2) == Some(&':') => Some(']'),
+        _ => None,
+      };
+      text.push(c);
+      i += 1;
+      if let Some(close) = close {
+        while i < chars.len() {
+          text.push(chars[i]);
+          i += 1;
+          if chars[i - 1] == close {
+            break;
+          }
+        }
+      }
+      continue;
+    }
+
+    let mut barline = String::new();
+    while i < chars.len() {
+      let c = chars[i];
+      let next = chars.get(i + 1).copied();
+      if c == '|' || c == ':' || c == ']' || (c == '[' && next.is_some_and(|n| n == '|' || n.is_ascii_digit())) {
+        barline.push(c);
+        i += 1;
+      } else {
+        break;
+      }
+    }
+    let digits: String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+    i += digits.len();
+    // 跳过 [1,3 或 [1-2 这样的列表中其余的部分，只取第一个数
+    while i < chars.len() && (chars[i] == ',' || chars[i] == '-' || chars[i].is_ascii_digit()) && !digits.is_empty() {
+      i += 1;
+    }
+    let end_repeat = barline.starts_with(':');
+    let closes = barline.contains('|') || barline.contains(':');
+
+    if closes && !text.trim().is_empty() {
+      bars.push(Bar{text: std::mem::take(&mut text), start_repeat, ending, end_repeat});
+      start_repeat = false;
+      ending = None;
+    } else if end_repeat && let Some(bar) = bars.last_mut() {
+      bar.end_repeat = true;
+    }
+    if barline.trim_end_matches(['[', ']']).ends_with(':') {
+      start_repeat = true;
+    }
+    if let Ok(number) = digits.parse() {
+      ending = Some(number);
+    }
+  }
+  if !text.trim().is_empty() {
+    bars.push(Bar{text, start_repeat, ending, end_repeat: false});
+  }
+  bars
+}
+
+/// 展开反复记号和反复跳跃记号，得到演奏顺序的各小节。反复跳跃记号持续到反复结束记号为止
+fn expand_repeats(bars: Vec<Bar>) -> Vec<String> {
+  let mut expanded = vec![];
+  let mut section = 0;
+  let mut pass = 1;
+  let mut ending_ = None;
+  let mut i = 0;
+  while i < bars.len() {
+    let bar = &bars[i];
+    if bar.start_repeat && i != section {
+      section = i;
+      pass = 1;
+    }
+    if bar.ending.is_some() {
+      ending_ = bar.ending;
+    }
+    let skip = ending_.is_some_and(|ending| ending != pass);
+    if !skip {
+      expanded.push(bar.text.clone());
+    }
+    if bar.end_repeat && !skip && pass == 1 {
+      pass = 2;
+      ending_ = None;
+      i = section;
+      continue;
+    }
+    // 第二遍结束后从下一个小节开始新的反复段落
+    if !skip && pass == 2 && (bar.end_repeat || ending_.is_some()) {
+      section = i + 1;
+      pass = 1;
+    }
+    if bar.end_repeat {
+      ending_ = None;
+    }
+    i += 1;
+  }
+  expanded
+}
+
+/// 读取数字
+fn read_number(chars: &[char], i: &mut usize) -> Option<u64> {
+  let digits: String = chars[*i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+  *i += digits.len();
+  digits.parse().ok()
+}
+
+/// 读取音符长度，如 `3`、`/`、`3/2`、`//`，返回单位音符长度的倍数
+fn read_length(chars: &[char], i: &mut usize) -> (u64, u64) {
+  let numerator = read_number(chars, i).unwrap_or(1);
+  let mut denominator = 1;
+  while chars.get(*i) == Some(&'/') {
+    *i += 1;
+    denominator *= read_number(chars, i).unwrap_or(2);
+  }
+  (numerator, denominator.max(1))
+}
+
+impl Reader {
+  /// 读取一个小节，小节中的临时记号在小节结束后失效。
+  /// `&` 之后的音符从小节开头重新开始，作为同一 channel 中重叠的声部
+  fn read_bar(&mut self, text: &str) -> Result<(), Error> {
+    self.accidentals.clear();
+    let bar_start = self.tick;
+    let mut main_end_ = None;
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+      let c = chars[i];
+      match c {
+        '&' => {
+          main_end_.get_or_insert(self.tick);
+          self.tick = bar_start;
+          self.last = None;
+          self.broken = None;
+          self.tuplet = None;
+          i += 1;
+        },
+        // 引号中的和弦名称与注释、装饰记号以及装饰音都被忽略
+        '"' | '!' | '+' | '{' => {
+          let close = match c {
+            '{' => '}',
+            c => c,
+          };
+          i += 1;
+          while i < chars.len() && chars[i] != close {
+            i += 1;
+          }
+          i += 1;
+        },
+        '[' if chars.get(i + 2) == Some(&':') => {
+          let end = chars[i..].iter().position(|c| *c == ']').map(|p| i + p).unwrap_or(chars.len());
+          let value: String = chars[i + 3..end].iter().collect();
+          self.field(chars[i + 1], value.trim())?;
+          i = end + 1;
+        },
+        '[' => {
+          i += 1;
+          let mut pitches = vec![];
+          let mut length_ = None;
+          while i < chars.len() && chars[i] != ']' {
+            if let Some(pitch) = self.read_pitch(&chars, &mut i)? {
+              let length = read_length(&chars, &mut i);
+              length_.get_or_insert(length);
+              let tie = chars.get(i) == Some(&'-');
+              if tie {
+                i += 1;
+              }
+              pitches.push((pitch, tie));
+            } else {
+              i += 1;
+            }
+          }
+          i += 1;
+          let (n, m) = length_.unwrap_or((1, 1));
+          let (outer_n, outer_m) = read_length(&chars, &mut i);
+          if chars.get(i) == Some(&'-') {
+            i += 1;
+            pitches.iter_mut().for_each(|(_, tie)| *tie = true);
+          }
+          self.sound(&pitches, (n * outer_n, m * outer_m));
+        },
+        '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+          if let Some(pitch) = self.read_pitch(&chars, &mut i)? {
+            let length = read_length(&chars, &mut i);
+            let tie = chars.get(i) == Some(&'-');
+            if tie {
+              i += 1;
+            }
+            self.sound(&[(pitch, tie)], length);
+          }
+        },
+        'z' | 'x' => {
+          i += 1;
+          let length = read_length(&chars, &mut i);
+          self.rest(length);
+        },
+        // 多小节休止
+        'Z' | 'X' => {
+          i += 1;
+          let count = read_number(&chars, &mut i).unwrap_or(1);
+          let ticks = count * 4 * ABC_PPQ * self.meter.0 / self.meter.1;
+          self.last = Some((self.tick, ticks, vec![]));
+          self.tick += ticks;
+        },
+        '(' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+          i += 1;
+          let p = read_number(&chars, &mut i).unwrap_or(3);
+          let mut q_ = None;
+          let mut r_ = None;
+          if chars.get(i) == Some(&':') {
+            i += 1;
+            q_ = read_number(&chars, &mut i);
+            if chars.get(i) == Some(&':') {
+              i += 1;
+              r_ = read_number(&chars, &mut i);
+            }
+          }
+          // 缺省的 q 为 3 或 2，复拍子中的 5、7、9 连音为 3
+          let compound = self.meter.0.is_multiple_of(3) && self.meter.0 > 3;
+          let q = q_.unwrap_or(match p {
+            2 | 4 | 8 => 3,
+            3 | 6 => 2,
+            _ if compound => 3,
+            _ => 2,
+          });
+          self.tuplet = Some((p, q, r_.unwrap_or(p)));
+        },
+        '>' | '<' => {
+          let count = chars[i..].iter().take_while(|d| **d == c).count() as u32;
+          i += count as usize;
+          self.broken_rhythm(c == '>', count);
+        },
+        _ => i += 1,
+      }
+    }
+    if let Some(main_end) = main_end_ {
+      self.tick = main_end;
+    }
+
+    // 只有在小节末尾结束的音符才能与下一个小节的音符连接
+    let (notes, tick) = (&self.notes, self.tick);
+    self.tied.retain(|index| notes[*index].1 == tick);
+    self.tied.sort();
+    self.tied.dedup();
+    Ok(())
+  }
+
+  /// 内联字段或正文中的字段行，只有 `K:`、`L:`、`M:` 影响读取
+  fn field(&mut self, name: char, value: &str) -> Result<(), Error> {
+    match name {
+      'K' => if let Some((fifths, _)) = parse_key(value)? {
+        self.key = key_accidentals(fifths);
+      },
+      'L' => self.unit = parse_fraction(value)?,
+      'M' => if let Some(meter) = parse_meter(value)? {
+        self.meter = meter;
+      },
+      _ => {},
+    }
+    Ok(())
+  }
+
+  /// 读取带临时记号和八度记号的音高，不是音符时返回 None
+  fn read_pitch(&mut self, chars: &[char], i: &mut usize) -> Result<Option<u8>, Error> {
+    let start = *i;
+    let mut accidental_ = None;
+    while let Some(c @ ('^' | '_' | '=')) = chars.get(*i) {
+      accidental_ = Some(accidental_.unwrap_or(0) + match c {
+        '^' => 1,
+        '_' => -1,
+        _ => 0,
+      });
+      *i += 1;
+    }
+    let letter = match chars.get(*i) {
+      Some( letter ) if letter.is_ascii_alphabetic() && ('A'..='G').contains(&letter.to_ascii_uppercase()) => *letter,
+      _ => return Ok(None),
+    };
+    *i += 1;
+    let mut octave = match letter.is_ascii_uppercase() {
+      true => 4,
+      false => 5,
+    };
+    while let Some(c @ ('\'' | ',')) = chars.get(*i) {
+      octave += match c {
+        '\'' => 1,
+        _ => -1,
+      };
+      *i += 1;
+    }
+
+    let step = STEPS.iter().position(|(s, _)| *s == letter.to_ascii_uppercase()).unwrap();
+    let alter = match accidental_ {
+      Some( alter ) => {
+        self.accidentals.insert((step, octave), alter);
+        alter
+      },
+      None => self.accidentals.get(&(step, octave)).copied().unwrap_or(self.key[step]),
+    };
+    let pitch = (octave + 1) * 12 + STEPS[step].1 + alter as i32;
+    match pitch {
+      0..=127 => Ok(Some(pitch as u8)),
+      _ => Err(Error::ParseError(format!(
+        "ABC note {} is out of MIDI range", chars[start..*i].iter().collect::<String>()
+      ))),
+    }
+  }
+
+  /// 单位音符长度的倍数对应的 tick 数，计入连音和附点节奏
+  fn duration(&mut self, (n, m): (u64, u64)) -> u64 {
+    let (mut numerator, mut denominator) = (4 * ABC_PPQ * self.unit.0 * n, self.unit.1 * m);
+    if let Some((p, q, remaining)) = self.tuplet {
+      numerator *= q;
+      denominator *= p;
+      self.tuplet = (remaining > 1).then_some((p, q, remaining - 1));
+    }
+    if let Some((bn, bm)) = self.broken.take() {
+      numerator *= bn;
+      denominator *= bm;
+    }
+    (numerator + denominator / 2) / denominator
+  }
+
+  /// 在当前位置发出一个音符或和弦，与前面用连音线连接且恰好在此处结束的同音高音符合并为一个音符
+  fn sound(&mut self, pitches: &[(u8, bool)], length: (u64, u64)) {
+    let duration = self.duration(length);
+    let end = self.tick + duration;
+    let mut indices = vec![];
+    for &(pitch, tie) in pitches {
+      let continued = self.tied.iter().copied().find(|&index| {
+        let (_, note_end, note) = self.notes[index];
+        note == pitch && note_end == self.tick
+      });
+      let index = match continued {
+        Some( index ) => {
+          self.notes[index].1 = end;
+          index
+        },
+        None => {
+          self.notes.push((self.tick, end, pitch));
+          self.notes.len() - 1
+        },
+      };
+      if tie {
+        self.tied.push(index);
+      }
+      indices.push(index);
+    }
+    self.last = Some((self.tick, duration, indices));
+    self.tick = end;
+  }
+
+  /// 休止
+  fn rest(&mut self, length: (u64, u64)) {
+    let duration = self.duration(length);
+    self.last = Some((self.tick, duration, vec![]));
+    self.tick += duration;
+  }
+
+  /// 附点节奏，`>` 使前一个音符变长、后一个音符变短，`<` 相反，符号的个数为附点数
+  fn broken_rhythm(&mut self, longer_first: bool, dots: u32) {
+    let Some((start, duration, indices)) = self.last.take() else {
+      return;
+    };
+    let (long, short) = ((1 << (dots + 1)) - 1, 1 << dots);
+    let (first, next) = match longer_first {
+      true => ((long, short), (1, short)),
+      false => ((1, short), (long, short)),
+    };
+    let new_duration = duration * first.0 / first.1;
+    for index in &indices {
+      self.notes[*index].1 = start + new_duration;
+    }
+    self.tick = start + new_duration;
+    self.broken = Some(next);
+    self.last = Some((start, new_duration, indices));
+  }
+}
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use midi_file::MidiFile;
+use midi_file::core::Message;
+use midi_file::file::{Division, Event, MetaEvent};
+
+use crate::{error::Error, gm};
+
+mod abc;
+mod smf;
+
+/// 默认的量化网格，即十六分音符
+const DEFAULT_GRID: u32 = 16;
+
+/// 没有 set tempo 事件时的速度
+const DEFAULT_TEMPO: u32 = 120;
+
+/// 每个 phrase 包含的小节数
+const MEASURES_PER_PHRASE: usize = 4;
+
+/// General MIDI 的打击乐 channel，音高以打击乐名称输出
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// 从 midi 或 ABC 中读出的乐曲，音符以 tick 表示
+#[derive(Default)]
+struct ImportedScore {
+  /// 每个四分音符所占的 tick 数
+  ppq: u64,
+
+  /// 各 channel 的 (开始, 结束, 音高)
+  notes: BTreeMap<u8, Vec<(u64, u64, u8)>>,
+
+  /// 各 channel 的 program，从 0 开始
+  programs: BTreeMap<u8, u8>,
+
+  /// 每分钟的四分音符数
+  tempo: Option<u32>,
+
+  time_sig: Option<(u32, u32)>,
+
+  title: Option<String>,
+
+  /// 调号，如 `Eb major`
+  key: Option<String>,
+}
+
+/// 量化后的一个音符
+struct ImportedNote {
+  /// 开始的网格位置
+  slot: u32,
+
+  /// 占的网格数，至少为 1
+  len: u32,
+
+  note: u8,
+}
+
+/// 将 SMF 或 ABC 记谱法的乐曲转换为 yam 源文件
+pub struct Importer {
+  /// 量化网格，以全音符的几分之一表示
+  grid: u32,
+}
+
+impl Default for Importer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Importer {
+  pub fn new() -> Self {
+    Self {
+      grid: DEFAULT_GRID,
+    }
+  }
+
+  /// 设置量化网格，以全音符的几分之一表示，例如 16 表示十六分音符
+  pub fn set_grid(&mut self, grid: u32) {
+    self.grid = grid;
+  }
+
+  /// 读取 midi 文件，生成 yam 源代码
+  pub fn import(&self, midi_file: &MidiFile) -> Result<String, Error> {
+    let ppq = match midi_file.header().division() {
+      Division::QuarterNote( ppq ) => ppq.get() as u64,
+      Division::Smpte(_) => return Err(Error::RuntimeError(
+        "SMPTE time division is not supported".to_string()
+      )),
+    };
+
+    // 各 channel 的音符、乐器以及第一个速度和拍号，format 0 和 format 1 的文件都按 channel 拆分
+    let mut score = ImportedScore{ppq, ..Default::default()};
+    let mut tempo_ = None;
+    for track in midi_file.tracks() {
+      let mut tick = 0_u64;
+      let mut sounding: BTreeMap<(u8, u8), VecDeque<u64>> = BTreeMap::new();
+      for track_event in track.events() {
+        tick += track_event.delta_time() as u64;
+        match track_event.event() {
+          Event::Midi(Message::NoteOn( msg )) if msg.velocity().get() > 0 => {
+            sounding.entry((msg.channel().get(), msg.note_number().get())).or_default().push_back(tick);
+          },
+          Event::Midi(Message::NoteOn( msg )) | Event::Midi(Message::NoteOff( msg )) => {
+            let (channel, note) = (msg.channel().get(), msg.note_number().get());
+            if let Some(start) = sounding.get_mut(&(channel, note)).and_then(|starts| starts.pop_front()) {
+              score.notes.entry(channel).or_default().push((start, tick, note));
+            }
+          },
+          Event::Midi(Message::ProgramChange( value )) => {
+            score.programs.entry(value.channel().get()).or_insert(value.program().get());
+          },
+          Event::Meta(MetaEvent::SetTempo( value )) if tempo_.is_none() => {
+            tempo_ = Some(value.get());
+          },
+          Event::Meta(MetaEvent::TimeSignature( value )) if score.time_sig.is_none() => {
+            score.time_sig = Some((value.numerator() as u32, 1_u32 << value.denominator() as u32));
+          },
+          _ => {},
+        }
+      }
+      // 没有 note off 的音符持续到 track 结束
+      for ((channel, note), starts) in sounding {
+        for start in starts {
+          score.notes.entry(channel).or_default().push((start, tick, note));
+        }
+      }
+    }
+    score.tempo = tempo_.map(|micros| (60_000_000 + micros / 2) / micros);
+    self.render(&score)
+  }
+
+  /// 读取 SMF 文件的内容，生成 yam 源代码。先去掉 midi_file 无法解析的 sysex 事件
+  pub fn import_smf(&self, bytes: &[u8]) -> Result<String, Error> {
+    let bytes = smf::strip_sysex(bytes)?;
+    let midi_file = MidiFile::read(bytes.as_slice()).map_err(|e| Error::RuntimeError(e.to_string()))?;
+    self.import(&midi_file)
+  }
+
+  /// 读取 ABC 记谱法的乐曲，生成 yam 源代码
+  pub fn import_abc(&self, text: &str) -> Result<String, Error> {
+    self.render(&abc::read(text)?)
+  }
+
+  /// 将各 channel 的音符量化并输出为 yam 源代码
+  fn render(&self, score: &ImportedScore) -> Result<String, Error> {
+    if !self.grid.is_power_of_two() {
+      return Err(Error::RuntimeError(format!(
+        "grid must be a power of 2, but found {}", self.grid
+      )));
+    }
+
+    // 量化网格不能比拍号的单位更粗，每个网格用 '<' 从拍号的单位缩短得到
+    let (numerator, denominator) = score.time_sig.unwrap_or((4, 4));
+    let grid = self.grid.max(denominator);
+    let dilations = (grid / denominator).trailing_zeros() as usize;
+    let measure_slots = numerator * grid / denominator;
+    if measure_slots == 0 {
+      return Err(Error::RuntimeError(
+        "numerator of time signature must be at least 1".to_string()
+      ));
+    }
+    let tempo = score.tempo.unwrap_or(DEFAULT_TEMPO).clamp(1, 255);
+
+    let mut source = String::new();
+    let mut channels = vec![];
+    for (channel, channel_notes) in &score.notes {
+      let mut imported: Vec<ImportedNote> = channel_notes.iter()
+        .map(|&(start, end, note)| {
+          let slot = quantize(start, score.ppq, grid);
+          let len = quantize(end, score.ppq, grid).saturating_sub(slot).max(1);
+          ImportedNote{slot, len, note}
+        })
+        .collect();
+      if imported.is_empty() {
+        continue;
+      }
+      imported.sort_by_key(|note| (note.slot, note.len, note.note));
+      imported.dedup_by_key(|note| (note.slot, note.len, note.note));
+
+      let ident = format!("channel_{channel}");
+      writeln!(source, "track {ident} = {{").unwrap();
+      let measures = render_measures(&imported, measure_slots, dilations, *channel);
+      for phrase in measures.chunks(MEASURES_PER_PHRASE) {
+        writeln!(source, "  [").unwrap();
+        for measure in phrase {
+          writeln!(source, "    {measure}").unwrap();
+        }
+        writeln!(source, "  ]").unwrap();
+      }
+      writeln!(source, "}};\n").unwrap();
+      channels.push((*channel, ident));
+    }
+
+    writeln!(source, "@score {{").unwrap();
+    // yam 的字符串中不能出现双引号
+    if let Some(title) = &score.title {
+      writeln!(source, "  @title = \"{}\";", title.replace('"', "'")).unwrap();
+    }
+    writeln!(source, "  @tempo = {tempo};").unwrap();
+    writeln!(source, "  @timesig = {numerator}:{denominator};").unwrap();
+    if let Some(key) = &score.key {
+      writeln!(source, "  @keysig = {key};").unwrap();
+    }
+    for (channel, ident) in &channels {
+      // 打击乐 channel 的 program change 并不选择乐器
+      if let Some(program) = score.programs.get(channel) && *channel != PERCUSSION_CHANNEL {
+        writeln!(source, "  @{channel} -> \"{}\";", gm::INSTRUMENTS[*program as usize]).unwrap();
+      }
+      writeln!(source, "  @{channel} <- {ident};").unwrap();
+    }
+    writeln!(source, "}}").unwrap();
+    Ok(source)
+  }
+}
+
+/// 将 tick 四舍五入到最近的网格位置
+fn quantize(tick: u64, ppq: u64, grid: u32) -> u32 {
+  let whole = 4 * ppq;
+  ((tick * grid as u64 * 2 + whole) / (2 * whole)) as u32
+}
+
+/// 将一个 channel 的音符排布为各小节的源代码。
+/// 同时开始且时值相同的音符合并为和弦，同时开始但时值不同的音符放入不同的声部；
+/// 第一个小节的每个声部开头都用 '<' 将单位缩短为一个网格，之后的小节沿用该单位
+fn render_measures(notes: &[ImportedNote], measure_slots: u32, dilations: usize, channel: u8) -> Vec<String> {
+  // 按开始位置和时值分组，每组即一个和弦
+  let mut chords: BTreeMap<u32, Vec<(u32, Vec<u8>)>> = BTreeMap::new();
+  for note in notes {
+    let group = chords.entry(note.slot).or_default();
+    match group.last_mut() {
+      Some( (len, chord) ) if *len == note.len => chord.push(note.note),
+      _ => group.push((note.len, vec![note.note])),
+    }
+  }
+
+  let last_slot = notes.iter().map(|note| note.slot).max().unwrap_or(0);
+  let measure_count = last_slot / measure_slots + 1;
+  (0..measure_count).map(|measure| {
+    let start = measure * measure_slots;
+    let voice_count = chords.range(start..start + measure_slots)
+      .map(|(_, group)| group.len())
+      .max()
+      .unwrap_or(1);
+    let voices: Vec<String> = (0..voice_count).map(|voice| {
+      let mut units = match measure {
+        0 => vec!["<".to_string(); dilations],
+        _ => vec![],
+      };
+      for slot in start..start + measure_slots {
+        units.push(match chords.get(&slot).and_then(|group| group.get(voice)) {
+          Some( (len, chord) ) => {
+            let chord: Vec<String> = chord.iter().map(|note| note_name(*note, channel)).collect();
+            match len {
+              1 => chord.join("'"),
+              len => format!("{}={len}", chord.join("'")),
+            }
+          },
+          None => ".".to_string(),
+        });
+      }
+      units.join(", ")
+    }).collect();
+    format!("| {} |", voices.join(" & "))
+  }).collect()
+}
+
+/// 音符的源代码，打击乐 channel 上使用 General MIDI 打击乐名称
+fn note_name(note: u8, channel: u8) -> String {
+  let percussion = gm::PERCUSSION.iter().find(|(_, pitch)| *pitch == note as i32);
+  match (channel, percussion) {
+    (PERCUSSION_CHANNEL, Some( (name, _) )) => gm::to_ident(name),
+    _ => note.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 由 track 的事件组成只有一个 track 的 SMF，ppq 为 480
+  fn smf(events: &[u8]) -> Vec<u8> {
+    let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xe0MTrk".to_vec();
+    bytes.extend((events.len() as u32).to_be_bytes());
+    bytes.extend(events);
+    bytes
+  }
+
+  #[test]
+  fn import_skips_sysex_events() {
+    let bytes = smf(&[
+      0x00, 0xf0, 0x05, 0x7e, 0x7f, 0x09, 0x01, 0xf7,  // GM reset
+      0x00, 0xc0, 40,
+      0x00, 0x90, 60, 100,
+      0x83, 0x60, 0xf7, 0x02, 0x01, 0x02,  // 480 tick 之后的 sysex，其 delta time 累加到下一个事件上
+      0x00, 62, 100,  // running status
+      0x00, 0x80, 60, 0,
+      0x83, 0x60, 0x80, 62, 0,
+      0x00, 0xff, 0x2f, 0x00,
+    ]);
+    let source = Importer::new().import_smf(&bytes).unwrap();
+    assert!(source.contains("| <, <, 60=4, ., ., ., 62=4, ., ., ., ., ., ., ., ., ., ., . |"), "{source}");
+    assert!(source.contains("@0 -> \"Violin\";"), "{source}");
+  }
+
+  /// 检查并执行 yam 源文件，得到 SMF 的字节
+  fn compile(source: &str) -> Vec<u8> {
+    let comp_unit = crate::syntactic::Analyzer::new().parse(source).unwrap();
+    crate::semantic::Analyzer::new().check(&comp_unit).unwrap();
+    let midi_file = crate::interpret::Interpreter::new().interpret(&comp_unit).unwrap();
+    let mut bytes = vec![];
+    midi_file.write(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn import_round_trip() {
+    let source = "@score {\n  measure m = | 60, 62=2, ., <, 64, 65, >, 60'64'67 |;\n  @tempo = 90;\n  @timesig = 4:4;\n  @0 -> 40;\n  @0 <- { [ @m @m ] };\n}\n";
+    let bytes = compile(source);
+    let imported = Importer::new().import_smf(&bytes).unwrap();
+    assert_eq!(compile(&imported), bytes, "{imported}");
+  }
+
+  #[test]
+  fn strip_sysex_keeps_other_events() {
+    let events = [0x00, 0x90, 60, 100, 0x60, 0x80, 60, 0, 0x00, 0xff, 0x2f, 0x00];
+    assert_eq!(smf::strip_sysex(&smf(&events)).unwrap(), smf(&events));
+  }
+}
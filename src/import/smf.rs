@@ -0,0 +1,113 @@
+use crate::error::Error;
+
+/// 变长数值最多 4 个字节，即 28 位
+const MAX_VLQ: u32 = 0x0fff_ffff;
+
+/// 读取 SMF 时出错
+fn malformed(what: &str) -> Error {
+  Error::RuntimeError(format!("malformed midi file: {what}"))
+}
+
+/// 从 pos 开始读取一个变长数值，返回数值和之后的位置
+fn read_vlq(bytes: &[u8], mut pos: usize) -> Result<(u32, usize), Error> {
+  let mut value = 0_u32;
+  for _ in 0..4 {
+    let byte = *bytes.get(pos).ok_or_else(|| malformed("unexpected end of track"))?;
+    pos += 1;
+    value = (value << 7) | (byte & 0x7f) as u32;
+    if byte & 0x80 == 0 {
+      return Ok((value, pos));
+    }
+  }
+  Err(malformed("variable-length quantity is too long"))
+}
+
+/// 以变长数值的形式写入 value
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+  let mut bytes = vec![(value & 0x7f) as u8];
+  let mut value = value >> 7;
+  while value > 0 {
+    bytes.push((value & 0x7f) as u8 | 0x80);
+    value >>= 7;
+  }
+  out.extend(bytes.iter().rev());
+}
+
+/// 去掉一个 track chunk 中的 sysex 事件，其 delta time 累加到下一个事件上。
+/// 省略了 status 的 channel 消息都补全 status，以免去掉 sysex 之后改变 running status 的含义
+fn strip_track(track: &[u8]) -> Result<Vec<u8>, Error> {
+  let mut out = vec![];
+  let mut pos = 0;
+  let mut delta = 0_u32;
+  let mut running_status = None;
+  while pos < track.len() {
+    let (event_delta, next) = read_vlq(track, pos)?;
+    delta = delta.checked_add(event_delta).filter(|delta| *delta <= MAX_VLQ)
+      .ok_or_else(|| malformed("delta time is too large"))?;
+    pos = next;
+
+    let first = *track.get(pos).ok_or_else(|| malformed("missing event after delta time"))?;
+    let start = pos;
+    match first {
+      // sysex 事件：F0/F7 之后为变长的长度和数据
+      0xf0 | 0xf7 => {
+        let (len, next) = read_vlq(track, pos + 1)?;
+        pos = next + len as usize;
+        continue;
+      },
+      // meta 事件：FF、类型、变长的长度和数据
+      0xff => {
+        let (len, next) = read_vlq(track, pos + 2)?;
+        pos = next + len as usize;
+      },
+      0x80..=0xef => {
+        running_status = Some(first);
+        pos += 1 + channel_message_len(first);
+      },
+      0x00..=0x7f => pos += channel_message_len(running_status.ok_or_else(|| malformed("data byte without status"))?),
+      _ => return Err(malformed(&format!("unexpected status {first:#04x}"))),
+    }
+    if pos > track.len() {
+      return Err(malformed("unexpected end of track"));
+    }
+
+    write_vlq(&mut out, delta);
+    delta = 0;
+    if first < 0x80 {
+      out.push(running_status.unwrap());
+    }
+    out.extend(&track[start..pos]);
+  }
+  Ok(out)
+}
+
+/// channel 消息的 status 之后的数据字节数
+fn channel_message_len(status: u8) -> usize {
+  match status & 0xf0 {
+    0xc0 | 0xd0 => 1,
+    _ => 2,
+  }
+}
+
+/// 去掉 SMF 中所有 track 的 sysex 事件(如 GM reset)。
+/// midi_file 无法解析 sysex 事件，而导入时也用不到它们
+pub(super) fn strip_sysex(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+  let mut out = vec![];
+  let mut pos = 0;
+  while pos < bytes.len() {
+    let header = bytes.get(pos..pos + 8).ok_or_else(|| malformed("truncated chunk header"))?;
+    let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let data = bytes.get(pos + 8..pos + 8 + len).ok_or_else(|| malformed("truncated chunk"))?;
+    match &header[..4] {
+      b"MTrk" => {
+        let track = strip_track(data)?;
+        out.extend(b"MTrk");
+        out.extend((track.len() as u32).to_be_bytes());
+        out.extend(track);
+      },
+      _ => out.extend(&bytes[pos..pos + 8 + len]),
+    }
+    pos += 8 + len;
+  }
+  Ok(out)
+}
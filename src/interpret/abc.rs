@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::{ast::score::KeyMode, error::Error};
+
+use super::notation::{fifths_at, key_change, key_tonic, layout, measure_voices, spell, Duration, ScoreInfo, Segment};
+use super::score::PERCUSSION_CHANNEL;
+use super::timeline::Timeline;
+
+/// 单位音符长度 L 为八分音符
+const UNIT_NOTE_LENGTH: u32 = 8;
+
+/// 每行的小节数
+const MEASURES_PER_LINE: usize = 4;
+
+/// 按五度圈排列的音名，有 n 个升号的调升高前 n 个音，有 n 个降号的调降低后 n 个音
+const FIFTHS_ORDER: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// ABC 的调号，如 `Eb`、`F#m`
+fn key_name(fifths: i8, mode: KeyMode) -> String {
+  let (step, alter) = key_tonic(fifths, mode);
+  let accidental = match alter {
+    1 => "#",
+    -1 => "b",
+    _ => "",
+  };
+  let mode = match mode {
+    KeyMode::Major => "",
+    KeyMode::Minor => "m",
+  };
+  format!("{step}{accidental}{mode}")
+}
+
+/// 调号对音名的变音
+fn key_alter(step: char, fifths: i8) -> i8 {
+  let position = FIFTHS_ORDER.iter().position(|s| *s == step).unwrap() as i8;
+  match fifths {
+    f if f > 0 && position < f => 1,
+    f if f < 0 && position >= 7 + f => -1,
+    _ => 0,
+  }
+}
+
+/// 以单位音符长度的倍数表示的时值，如 `2`、`/`、`3/2`，恰为单位音符长度时为空
+fn length_name(duration: Duration, ppq: u16) -> String {
+  let (mut a, mut b) = (duration.ticks * UNIT_NOTE_LENGTH, 4 * ppq as u32);
+  let (numerator, denominator) = (a, b);
+  while b != 0 {
+    (a, b) = (b, a % b);
+  }
+  match (numerator / a, denominator / a) {
+    (1, 1) => String::new(),
+    (n, 1) => n.to_string(),
+    (1, 2) => "/".to_string(),
+    (1, d) => format!("/{d}"),
+    (n, d) => format!("{n}/{d}"),
+  }
+}
+
+/// 由各 channel 的音符事件生成 ABC 记谱法的乐曲。
+/// 每个 channel 为一个 `V:` 声部并用 `%%MIDI` 指令保留 channel 和乐器；按拍号划分小节，
+/// 重叠的音符在小节内用 `&` 写为多个声部，跨小节的音符用 `-` 连接
+pub(super) fn score_abc(timeline: &Timeline) -> Result<String, Error> {
+  let info = ScoreInfo::new(timeline);
+  let layout = layout(&info, &timeline.channels)?;
+  let measure_ticks = layout.measure_ticks;
+  let measure_rest = length_name(Duration{ticks: measure_ticks, note_type: None}, info.ppq);
+
+  let mut abc = String::new();
+  writeln!(abc, "X:1").unwrap();
+  if let Some(title) = info.title {
+    writeln!(abc, "T:{title}").unwrap();
+  }
+  writeln!(abc, "M:{}/{}", info.time_sig_numerator, info.time_sig_denominator).unwrap();
+  writeln!(abc, "L:1/{UNIT_NOTE_LENGTH}").unwrap();
+  if let Some(tempo) = info.tempo {
+    writeln!(abc, "Q:1/4={tempo}").unwrap();
+  }
+  let (fifths, mode) = key_change(&info.key_signatures, 0, measure_ticks).unwrap_or((0, KeyMode::Major));
+  writeln!(abc, "K:{}", key_name(fifths, mode)).unwrap();
+
+  for part in &layout.parts {
+    let percussion = part.channel == PERCUSSION_CHANNEL;
+    let clef = match (percussion, part.is_low()) {
+      (true, _) => " clef=perc",
+      (false, true) => " clef=bass",
+      (false, false) => "",
+    };
+    writeln!(abc, "V:{} name=\"{}\"{clef}", part.channel, part.name().replace('"', "'")).unwrap();
+    writeln!(abc, "%%MIDI channel {}", part.channel + 1).unwrap();
+    if let Some(instrument) = part.timeline.instrument && !percussion {
+      writeln!(abc, "%%MIDI program {}", instrument).unwrap();
+    }
+
+    let mut measures = vec![];
+    for measure in 0..layout.measure_count {
+      let mut text = String::new();
+      // 第一个小节的调号已写在 K: 字段中
+      if let Some((fifths, mode)) = key_change(&info.key_signatures, measure, measure_ticks) && measure > 0 && !percussion {
+        write!(text, "[K:{}] ", key_name(fifths, mode)).unwrap();
+      }
+      let fifths = match percussion {
+        true => 0,
+        false => fifths_at(&info.key_signatures, measure * measure_ticks),
+      };
+
+      // 临时记号在小节内有效，为避免不同实现对 & 声部的理解不同，其他声部改变过的音总是写出变音记号
+      let mut altered_by_others: BTreeSet<(char, i32)> = BTreeSet::new();
+      let mut voices = vec![];
+      for (_, segments) in measure_voices(&part.voices, measure, measure_ticks, info.ppq) {
+        let mut accidentals: BTreeMap<(char, i32), i8> = BTreeMap::new();
+        let mut tokens = vec![];
+        for segment in segments {
+          tokens.push(match segment {
+            Segment::MeasureRest => format!("z{measure_rest}"),
+            Segment::Rest( duration ) => format!("z{}", length_name(duration, info.ppq)),
+            Segment::Chord{notes, duration, tie_start, ..} => {
+              let pitches: Vec<String> = notes.iter().map(|note| {
+                let (step, alter, octave) = spell(*note, fifths);
+                let current = accidentals.get(&(step, octave)).copied().unwrap_or(key_alter(step, fifths));
+                let accidental = match current != alter || altered_by_others.contains(&(step, octave)) {
+                  true => match alter {
+                    2 => "^^",
+                    1 => "^",
+                    -1 => "_",
+                    -2 => "__",
+                    _ => "=",
+                  },
+                  false => "",
+                };
+                accidentals.insert((step, octave), alter);
+                let name = match octave {
+                  o if o >= 5 => format!("{}{}", step.to_ascii_lowercase(), "'".repeat(o as usize - 5)),
+                  o => format!("{step}{}", ",".repeat((4 - o).max(0) as usize)),
+                };
+                format!("{accidental}{name}")
+              }).collect();
+              let tie = match tie_start {
+                true => "-",
+                false => "",
+              };
+              match pitches.len() {
+                1 => format!("{}{}{tie}", pitches[0], length_name(duration, info.ppq)),
+                _ => format!("[{}]{}{tie}", pitches.concat(), length_name(duration, info.ppq)),
+              }
+            },
+          });
+        }
+        altered_by_others.extend(accidentals.iter()
+          .filter(|((step, _), alter)| **alter != key_alter(*step, fifths))
+          .map(|(key, _)| *key));
+        voices.push(tokens.join(" "));
+      }
+      text.push_str(&voices.join(" & "));
+      measures.push(text);
+    }
+
+    let lines: Vec<String> = measures.chunks(MEASURES_PER_LINE)
+      .map(|line| line.join(" | "))
+      .collect();
+    let last = lines.len() - 1;
+    for (i, line) in lines.iter().enumerate() {
+      let barline = match i == last {
+        true => "|]",
+        false => "|",
+      };
+      writeln!(abc, "{line} {barline}").unwrap();
+    }
+  }
+  Ok(abc)
+}
@@ -1,7 +1,19 @@
-use crate::{ast::{measure::{Measure, MeasureRVal, MeasureUnit, MeasureUnitValue, MeasureValue}, note::{Note, NoteValue}, phrase::{Phrase, PhraseRVal, PhraseValue}, stmt::AsgnRVal, track::{Track, TrackValue}, val::Value}, error::Error, interpret::ctr::RetVal};
+use crate::{ast::{drum::{DrumGrid, DrumStep}, measure::{Measure, MeasureRVal, MeasureUnit, MeasureUnitValue, MeasureValue}, note::{Note, NoteValue}, phrase::{Phrase, PhraseRVal, PhraseValue}, stmt::AsgnRVal, track::{Track, TrackRVal, TrackValue}, val::Value}, error::Error, interpret::ctr::RetVal};
 
 use super:: Interpreter;
 
+/// 鼓组网格缺省每拍的步数
+const DEFAULT_DRUM_STEPS: i32 = 4;
+
+/// 鼓组网格每拍的最大步数
+const MAX_DRUM_STEPS: i32 = 64;
+
+/// 鼓组网格中重音 'X' 的力度
+const ACCENT_VELOCITY: i32 = 112;
+
+/// 鼓组网格中轻击 'o' 的力度
+const GHOST_VELOCITY: i32 = 40;
+
 impl Interpreter {
   /// 翻译 Note 为 NoteValue
   pub fn interpret_note(&mut self, note: &Note) -> Result<NoteValue, Error> {
@@ -28,7 +40,16 @@ impl Interpreter {
       },
       false => None
     };
-    Ok(NoteValue{notes, len})
+    let cents = match &note.cents {
+      Some( expr ) => match self.calc_expr(expr)? {
+        RetVal::Value(Value::Int( int )) => Some(int),
+        val => return Err(Error::RuntimeError(format!(
+          "expect i32, but found {val}",
+        )))
+      },
+      None => None,
+    };
+    Ok(NoteValue{notes, len, velocity: None, cents, syllable: note.syllable.clone(), span: Some(note.span)})
   }
 
   /// 翻译 Measure 为 MeasureValue
@@ -38,6 +59,7 @@ impl Interpreter {
       let unit_val = match unit {
         MeasureUnit::Note( note ) => MeasureUnitValue::NoteValue(self.interpret_note(note)?),
         MeasureUnit::Rest => MeasureUnitValue::Rest,
+        MeasureUnit::VoiceSeparator => MeasureUnitValue::VoiceSeparator,
         MeasureUnit::TimeDilation => MeasureUnitValue::TimeDilation,
         MeasureUnit::TimeCompression => MeasureUnitValue::TimeCompression,
       };
@@ -79,12 +101,77 @@ impl Interpreter {
     Ok(PhraseValue{content})
   }
 
-  /// 翻译 Phrase 为 PhraseValue
+  /// 翻译 DrumGrid 为 PhraseValue。
+  /// 每个小节中，网格的每一行成为一个声部，声部开头用 '<' 将时值缩短到一步，结尾再用 '>' 恢复。
+  pub fn interpret_drum_grid(&mut self, drum_grid: &DrumGrid) -> Result<PhraseValue, Error> {
+    let steps = match &drum_grid.steps {
+      Some( expr ) => match self.calc_expr(expr)? {
+        RetVal::Value(Value::Int( int )) => int,
+        val => return Err(Error::RuntimeError(format!(
+          "expect i32, but found {val}",
+        )))
+      },
+      None => DEFAULT_DRUM_STEPS,
+    };
+    if !(1..=MAX_DRUM_STEPS).contains(&steps) || steps & (steps - 1) != 0 {
+      return Err(Error::RuntimeError(format!(
+        "steps per beat of drums must be a power of 2 between 1 and {MAX_DRUM_STEPS}, but found {steps}"
+      )));
+    }
+    let dilations = steps.trailing_zeros() as usize;
+
+    let mut rows = vec![];
+    for row in &drum_grid.rows {
+      let note = match self.calc_expr(&row.note)? {
+        RetVal::Value(Value::Int( int )) => int,
+        val => return Err(Error::RuntimeError(format!(
+          "expect i32, but found {val}",
+        )))
+      };
+      let measures = row.measures().map_err(|c| Error::RuntimeError(format!(
+        "invalid drum step '{c}'"
+      )))?;
+      rows.push((note, measures, row.span));
+    }
+
+    let measure_count = rows.iter().map(|(_, measures, _)| measures.len()).max().unwrap_or(0);
+    let mut content = vec![];
+    for i in 0..measure_count {
+      // 小节的步数取各行中最长者，较短的行用休止符补齐
+      let len = rows.iter().map(|(_, measures, _)| measures.get(i).map_or(0, |m| m.len())).max().unwrap_or(0);
+      let mut units = vec![];
+      for (j, (note, measures, span)) in rows.iter().enumerate() {
+        if j > 0 {
+          units.push(MeasureUnitValue::VoiceSeparator);
+        }
+        units.extend(std::iter::repeat_n(MeasureUnitValue::TimeDilation, dilations));
+        for k in 0..len {
+          let step = measures.get(i).and_then(|m| m.get(k)).copied().unwrap_or(DrumStep::Rest);
+          let velocity = match step {
+            DrumStep::Rest => {
+              units.push(MeasureUnitValue::Rest);
+              continue;
+            },
+            DrumStep::Hit => None,
+            DrumStep::Accent => Some(ACCENT_VELOCITY),
+            DrumStep::Ghost => Some(GHOST_VELOCITY),
+          };
+          units.push(MeasureUnitValue::NoteValue(NoteValue{notes: vec![*note], len: None, velocity, cents: None, syllable: None, span: Some(*span)}));
+        }
+        units.extend(std::iter::repeat_n(MeasureUnitValue::TimeCompression, dilations));
+      }
+      content.push(MeasureValue{content: units});
+    }
+    Ok(PhraseValue{content})
+  }
+
+  /// 翻译 Track 为 TrackValue
   pub fn interpret_track(&mut self, track: &Track) -> Result<TrackValue, Error> {
     let mut content = vec![];
     for phrase_rval in &track.content {
       let phrase_val = match phrase_rval {
         PhraseRVal::Phrase( phrase ) => self.interpret_phrase(phrase)?,
+        PhraseRVal::DrumGrid( drum_grid ) => self.interpret_drum_grid(drum_grid)?,
         PhraseRVal::LVal( lval ) => {
           match lval.rval.borrow().as_ref().clone().unwrap().get_value() {
             Value::Phrase( v ) => v,
@@ -113,6 +200,34 @@ impl Interpreter {
     Ok(TrackValue{content})
   }
 
+  /// 翻译 TrackRVal 为 TrackValue
+  pub fn interpret_track_rval(&mut self, track_rval: &TrackRVal) -> Result<TrackValue, Error> {
+    match track_rval {
+      TrackRVal::Track( track ) => self.interpret_track(track),
+      TrackRVal::LVal( lval ) => {
+        match lval.get_value() {
+          Value::Track( v ) => Ok(v),
+          val => Err(Error::RuntimeError(format!(
+            "expect track, but found {val}",
+          )))
+        }
+      },
+      TrackRVal::FuncCall( func_call ) => {
+        match self.call_func(func_call)? {
+          RetVal::Void => Err(Error::RuntimeError(
+            "expect track, but found void".to_string(),
+          )),
+          RetVal::Value( v ) => match v {
+            Value::Track( v ) => Ok(v),
+            val => Err(Error::RuntimeError(format!(
+              "expect track, but found {val}",
+            )))
+          }
+        }
+      },
+    }
+  }
+
   /// 翻译右值表达式
   pub fn interpret_asgn_rval(&mut self, asgn_rval: &AsgnRVal) -> Result<RetVal, Error> {
     Ok(RetVal::Value(
@@ -121,7 +236,8 @@ impl Interpreter {
         AsgnRVal::Note( note  ) => Value::Note(self.interpret_note(note)?),
         AsgnRVal::Measure( measure  ) => Value::Measure(self.interpret_measure(measure)?),
         AsgnRVal::Phrase( phrase  ) => Value::Phrase(self.interpret_phrase(phrase)?),
-        AsgnRVal::Track( track  ) => Value::Track(self.interpret_track(track)?)
+        AsgnRVal::Track( track  ) => Value::Track(self.interpret_track(track)?),
+        AsgnRVal::DrumGrid( drum_grid  ) => Value::Phrase(self.interpret_drum_grid(drum_grid)?)
       }
     ))
   }
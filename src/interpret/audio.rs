@@ -0,0 +1,71 @@
+
+use crate::synth::{self, soundfont::{SoundFont, PERCUSSION_BANK}, wav::{self, WavFormat}, Patch, Sound, SynthChannel, SynthNote};
+
+use super::score::PERCUSSION_CHANNEL;
+use super::timeline::{Note, Timeline};
+
+/// 没有设置速度时每分钟的四分音符数，与 SMF 的默认值相同
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// 没有设置音量时的音量，与 General MIDI 的默认值相同
+const DEFAULT_VOLUME: u8 = 100;
+
+/// 没有设置声像时的声像，即中间
+const DEFAULT_PAN: u8 = 64;
+
+/// A4 的频率
+const A4_FREQUENCY: f64 = 440.0;
+
+/// 将各 channel 的音符渲染为 WAV 文件，给出 SoundFont 时播放其中 General MIDI 乐器的采样，否则用内置合成器。
+/// 音高直接按音律和音分偏移计算，不需要弯音；打击乐 channel 不受音律影响
+pub(super) fn render_wav(timeline: &Timeline, soundfont: Option<&SoundFont>, format: WavFormat) -> Vec<u8> {
+  let tempo = timeline.tempo.map(|tempo| tempo.max(1) as f64).unwrap_or(DEFAULT_TEMPO);
+  let seconds_per_tick = 60.0 / (tempo * timeline.ppq as f64);
+
+  let channels: Vec<SynthChannel> = timeline.channels.iter()
+    .map(|(channel_u8, channel)| {
+      let percussion = *channel_u8 == PERCUSSION_CHANNEL;
+      let program = channel.instrument.unwrap_or(0);
+      let bank = match percussion {
+        true => PERCUSSION_BANK,
+        false => 0,
+      };
+
+      let mut notes = vec![];
+      for Note{tick, duration, key, velocity, cents, ..} in channel.notes.iter().copied() {
+        let pitch = match (percussion, &timeline.tuning) {
+          (false, Some( tuning )) => tuning.pitch(key as i32) + cents as f64,
+          (false, None) => key as f64 * 100.0 + cents as f64,
+          (true, _) => key as f64 * 100.0,
+        };
+        // 绑定了乐谱中定义的乐器时使用其音色，否则使用 SoundFont，
+        // SoundFont 中没有对应的预设时退回内置合成器的音色
+        let regions = || soundfont.and_then(|soundfont| soundfont.regions(bank, program, key, velocity));
+        let sounds = match channel.patch {
+          Some( patch ) => vec![Sound::Patch(patch)],
+          None => match regions() {
+            Some( regions ) => regions.into_iter().map(Sound::Sample).collect(),
+            None if percussion => vec![Sound::Patch(Patch::percussion(key))],
+            None => vec![Sound::Patch(Patch::general_midi(program))],
+          },
+        };
+        for sound in sounds {
+          notes.push(SynthNote {
+            start: tick as f64 * seconds_per_tick,
+            end: (tick + duration) as f64 * seconds_per_tick,
+            frequency: A4_FREQUENCY * 2_f64.powf((pitch - 6900.0) / 1200.0),
+            velocity: velocity as f64 / 127.0,
+            sound,
+          });
+        }
+      }
+
+      // 音量按 General MIDI 的约定取平方，声像 0 到 127 映射到 -1 到 1
+      let volume = channel.volume.unwrap_or(DEFAULT_VOLUME) as f64 / 127.0;
+      let pan = (channel.pan.unwrap_or(DEFAULT_PAN) as f64 - 64.0) / 63.0;
+      SynthChannel{notes, volume: volume * volume, pan}
+    })
+    .collect();
+
+  wav::encode(&synth::render(&channels, format.sample_rate), format)
+}
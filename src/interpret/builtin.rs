@@ -0,0 +1,93 @@
+use crate::ast::func::FuncCall;
+use crate::ast::measure::MeasureUnitValue;
+use crate::ast::note::NoteValue;
+use crate::ast::val::Value;
+use crate::builtin::{rhythm, Builtin};
+use crate::error::Error;
+
+use super::ctr::RetVal;
+use super::Interpreter;
+
+impl Interpreter {
+  /// 执行内置函数，参数按顺序求值
+  pub fn call_builtin(&mut self, func_call: &FuncCall, builtin: Builtin) -> Result<RetVal, Error> {
+    let mut args = vec![];
+    for asgn_rval in &func_call.func_rparams {
+      match self.interpret_asgn_rval(asgn_rval)? {
+        RetVal::Value( v ) => args.push(v),
+        val => return Err(Error::RuntimeError(format!(
+          "can not use {val} as param when calling '{}'", builtin.ident()
+        ))),
+      }
+    }
+
+    self.observe_call(builtin.ident(), &args, func_call.span)?;
+
+    match builtin {
+      Builtin::Euclid => {
+        let hits = expect_int(&args[0])?;
+        let steps = expect_int(&args[1])?;
+        let rotation = expect_int(&args[2])?;
+        let note = match &args[3] {
+          Value::Int( int ) => NoteValue{notes: vec![*int], len: None, velocity: None, cents: None, syllable: None, span: Some(func_call.span)},
+          Value::Note( note ) => note.clone(),
+          val => return Err(Error::RuntimeError(format!(
+            "expect note, but found {val}",
+          ))),
+        };
+        let measure = rhythm::euclid(hits, steps, rotation, note)?;
+        Ok(RetVal::Value(Value::Measure(measure)))
+      },
+      Builtin::Poly => {
+        let mut patterns = vec![];
+        for arg in args {
+          match arg {
+            Value::Measure( measure ) => patterns.push(measure),
+            val => return Err(Error::RuntimeError(format!(
+              "expect measure, but found {val}",
+            ))),
+          }
+        }
+        let measure = rhythm::poly(&patterns)?;
+        Ok(RetVal::Value(Value::Measure(measure)))
+      },
+      Builtin::Print => {
+        let texts: Vec<_> = args.iter().map(|arg| match arg {
+          Value::Str( s ) => s.clone(),
+          val => val.to_string(),
+        }).collect();
+        eprintln!("{}", texts.join(" "));
+        Ok(RetVal::Void)
+      },
+      Builtin::Debug => {
+        let texts: Vec<_> = args.iter().map(|arg| format!("{} {arg}", arg.get_btype())).collect();
+        eprintln!("{}", texts.join(", "));
+        Ok(RetVal::Void)
+      },
+      Builtin::Len => {
+        let len = match &args[0] {
+          Value::Note( note ) => note.notes.len(),
+          Value::Measure( measure ) => measure.content.iter()
+            .filter(|unit| matches!(unit, MeasureUnitValue::NoteValue(_) | MeasureUnitValue::Rest))
+            .count(),
+          Value::Phrase( phrase ) => phrase.content.len(),
+          Value::Track( track ) => track.content.len(),
+          Value::Str( s ) => s.chars().count(),
+          val => return Err(Error::RuntimeError(format!(
+            "expect note, measure, phrase, track or string, but found {val}",
+          ))),
+        };
+        Ok(RetVal::Value(Value::Int(len as i32)))
+      },
+    }
+  }
+}
+
+fn expect_int(val: &Value) -> Result<i32, Error> {
+  match val {
+    Value::Int( int ) => Ok(*int),
+    val => Err(Error::RuntimeError(format!(
+      "expect i32, but found {val}",
+    ))),
+  }
+}
@@ -15,6 +15,7 @@ impl Interpreter {
       PrimaryExpr::FuncCall(func_call) => self.call_func(func_call)?,
       PrimaryExpr::LVal(lval) => RetVal::Value(lval.get_value()),
       PrimaryExpr::Number(v) => RetVal::Value(Value::Int(v.clone())),
+      PrimaryExpr::Str(s) => RetVal::Value(Value::Str(s.clone())),
     };
     for op in &unary_expr.unary_ops { // TODO:暂时没规定和检查顺序
       match op {
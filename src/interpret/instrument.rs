@@ -0,0 +1,102 @@
+use crate::ast::instrument::InstrumentDef;
+use crate::ast::val::Value;
+use crate::error::Error;
+use crate::gm;
+use crate::synth::{Envelope, Patch, Waveform};
+
+use super::ctr::RetVal;
+use super::Interpreter;
+
+/// 乐器定义中没有给出的参数的默认值，时间以毫秒为单位，sustain 为百分比
+const DEFAULT_ATTACK: i32 = 10;
+const DEFAULT_DECAY: i32 = 100;
+const DEFAULT_SUSTAIN: i32 = 80;
+const DEFAULT_RELEASE: i32 = 100;
+
+/// 乐谱中用内置合成器定义的乐器
+#[derive(Debug, Clone, Copy)]
+pub(super) struct SynthInstrument {
+  /// 渲染音频时使用的音色
+  pub(super) patch: Patch,
+
+  /// 输出 midi 时使用的 General MIDI 乐器，从 0 开始
+  pub(super) program: u8,
+}
+
+/// 由从 0 开始的 program 编号或乐器名称得到从 0 开始的 General MIDI 乐器编号
+pub(super) fn general_midi(instrument: RetVal) -> Result<u8, Error> {
+  let instr_i32 = match instrument {
+    RetVal::Value(Value::Int( int )) => int,
+    RetVal::Value(Value::Str( name )) => gm::instrument_by_name(&name).ok_or(Error::RuntimeError(format!(
+      "unknown instrument '{name}'{}", gm::did_you_mean(&gm::suggest_instruments(&name))
+    )))?,
+    val => return Err(Error::RuntimeError(format!(
+      "expect i32 or string, but found {val}",
+    )))
+  };
+  let instr_u8 = match u8::try_from(instr_i32).is_ok_and(|v| v<128) {
+    true => u8::try_from(instr_i32).unwrap(),
+    false => return Err(Error::RuntimeError(
+      "instrument must between 0 and 127".to_string(),
+    ))
+  };
+  Ok(instr_u8)
+}
+
+impl Interpreter {
+  /// 计算乐器定义的参数，保存为内置合成器的音色
+  pub fn interpret_instrument_def(&mut self, instrument_def: &InstrumentDef) -> Result<(), Error> {
+    let InstrumentDef{ident, params, ..} = instrument_def;
+    let mut waveform = Waveform::Sine;
+    let mut program = 0;  // Acoustic Grand Piano
+    let mut cutoff = None;
+    let (mut attack, mut decay, mut sustain, mut release) = (DEFAULT_ATTACK, DEFAULT_DECAY, DEFAULT_SUSTAIN, DEFAULT_RELEASE);
+
+    for param in params {
+      let name = param.name.as_str();
+      let value = self.calc_expr(&param.value)?;
+      match name {
+        "wave" => waveform = match value {
+          RetVal::Value(Value::Str( wave )) => Waveform::from_name(&wave).ok_or(Error::RuntimeError(format!(
+            "unknown waveform '{wave}' in instrument '{ident}'"
+          )))?,
+          val => return Err(Error::RuntimeError(format!("expect string, but found {val}"))),
+        },
+        "program" => program = general_midi(value)?,
+        _ => {
+          let int = match value {
+            RetVal::Value(Value::Int( int )) => int,
+            val => return Err(Error::RuntimeError(format!("expect i32, but found {val}"))),
+          };
+          let (valid, expect) = match name {
+            "sustain" => ((0..=100).contains(&int), "between 0 and 100"),
+            "cutoff" => (int > 0, "positive"),
+            _ => (int >= 0, "non-negative"),
+          };
+          if !valid {
+            return Err(Error::RuntimeError(format!(
+              "{name} of instrument '{ident}' must be {expect}, but found {int}"
+            )));
+          }
+          match name {
+            "attack" => attack = int,
+            "decay" => decay = int,
+            "sustain" => sustain = int,
+            "release" => release = int,
+            _ => cutoff = Some(int as f64),
+          }
+        },
+      }
+    }
+
+    let envelope = Envelope::new(
+      attack as f64 / 1000.0,
+      decay as f64 / 1000.0,
+      sustain as f64 / 100.0,
+      release as f64 / 1000.0,
+    );
+    let patch = Patch{waveform, envelope, cutoff};
+    self.instruments.insert(ident.clone(), SynthInstrument{patch, program});
+    Ok(())
+  }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Write;
+
+use crate::{ast::score::KeyMode, error::Error};
+
+use super::notation::{fifths_at, key_change, key_tonic, layout, measure_voices, spell, Duration, ScoreInfo, Segment};
+use super::score::PERCUSSION_CHANNEL;
+use super::timeline::Timeline;
+
+/// 生成的 .ly 文件所要求的 LilyPond 版本
+const LILYPOND_VERSION: &str = "2.24.0";
+
+/// General MIDI 打击乐(音高 35 到 81)在 LilyPond drummode 中的名称
+const DRUM_NAMES: [&str; 47] = [
+  "acousticbassdrum", "bassdrum", "sidestick", "acousticsnare", "handclap", "electricsnare",
+  "lowfloortom", "closedhihat", "highfloortom", "pedalhihat", "lowtom", "openhihat",
+  "lowmidtom", "himidtom", "crashcymbala", "hightom", "ridecymbala", "chinesecymbal",
+  "ridebell", "tambourine", "splashcymbal", "cowbell", "crashcymbalb", "vibraslap",
+  "ridecymbalb", "hibongo", "lobongo", "mutehiconga", "openhiconga", "loconga",
+  "hitimbale", "lotimbale", "hiagogo", "loagogo", "cabasa", "maracas",
+  "shortwhistle", "longwhistle", "shortguiro", "longguiro", "claves", "hiwoodblock",
+  "lowoodblock", "mutecuica", "opencuica", "mutetriangle", "opentriangle",
+];
+
+/// 声部的符干方向指令 \voiceOne 到 \voiceFour，更多的声部与第四声部相同
+const VOICE_NAMES: [&str; 4] = ["One", "Two", "Three", "Four"];
+
+/// DRUM_NAMES 中第一个打击乐的音高
+const FIRST_DRUM: u8 = 35;
+
+/// 将 LilyPond 字符串中的特殊字符转义
+fn escape(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// 荷兰语音名，如 c、fis、bes，不含八度
+fn pitch_name(step: char, alter: i8) -> String {
+  let accidental = match alter {
+    1 => "is",
+    -1 => "es",
+    _ => "",
+  };
+  format!("{}{accidental}", step.to_ascii_lowercase())
+}
+
+/// 绝对八度模式下的音符，小字组(中央 C 下方的八度)没有八度记号
+fn absolute_pitch(note: u8, fifths: i8) -> String {
+  let (step, alter, octave) = spell(note, fifths);
+  let marks = match octave - 3 {
+    up if up >= 0 => "'".repeat(up as usize),
+    down => ",".repeat(-down as usize),
+  };
+  format!("{}{marks}", pitch_name(step, alter))
+}
+
+/// 时值，无法用音符类型表示时写为全音符的分数倍
+fn duration_name(duration: Duration, ppq: u16) -> String {
+  match duration.note_type {
+    Some((note_type, dots)) => format!("{}{}", 1 << note_type, ".".repeat(dots as usize)),
+    None => {
+      let whole = 4 * ppq as u32;
+      let (mut a, mut b) = (duration.ticks, whole);
+      while b != 0 {
+        (a, b) = (b, a % b);
+      }
+      format!("1*{}/{}", duration.ticks / a, whole / a)
+    },
+  }
+}
+
+/// 调号指令，如 \key ees \major
+fn key_command(fifths: i8, mode: KeyMode) -> String {
+  let (step, alter) = key_tonic(fifths, mode);
+  let mode = match mode {
+    KeyMode::Major => "major",
+    KeyMode::Minor => "minor",
+  };
+  format!("\\key {} \\{mode}", pitch_name(step, alter))
+}
+
+/// 由各 channel 的音符事件生成 LilyPond 源文件。
+/// 每个 channel 为一个谱表，打击乐 channel 为鼓谱；按拍号划分小节，
+/// 重叠的音符在小节内写为多个声部，跨小节的音符用连音线连接。
+pub(super) fn score_lilypond(timeline: &Timeline) -> Result<String, Error> {
+  let info = ScoreInfo::new(timeline);
+  let layout = layout(&info, &timeline.channels)?;
+  let measure_ticks = layout.measure_ticks;
+
+  let mut ly = String::new();
+  writeln!(ly, "\\version \"{LILYPOND_VERSION}\"\n").unwrap();
+  if let Some(title) = info.title {
+    writeln!(ly, "\\header {{\n  title = \"{}\"\n}}\n", escape(title)).unwrap();
+  }
+  writeln!(ly, "\\score {{").unwrap();
+  writeln!(ly, "  <<").unwrap();
+  for (i, part) in layout.parts.iter().enumerate() {
+    // 只有全部音符都是 General MIDI 打击乐时才使用鼓谱
+    let drums = part.channel == PERCUSSION_CHANNEL && part.voices.iter()
+      .flatten()
+      .flat_map(|chord| chord.notes.iter())
+      .all(|note| (FIRST_DRUM..FIRST_DRUM + DRUM_NAMES.len() as u8).contains(note));
+    let staff = match drums {
+      true => "\\new DrumStaff",
+      false => "\\new Staff",
+    };
+    let mode = match drums {
+      true => "\\drummode ",
+      false => "",
+    };
+
+    // 谱号、拍号、速度以及调号都写在第一个声部中
+    let mut header = vec![];
+    if !drums {
+      let clef = match part.is_low() {
+        true => "bass",
+        false => "treble",
+      };
+      header.push(format!("\\clef {clef}"));
+    }
+    header.push(format!("\\time {}/{}", info.time_sig_numerator, info.time_sig_denominator));
+    if let Some(tempo) = info.tempo && i == 0 {
+      header.push(format!("\\tempo 4 = {tempo}"));
+    }
+
+    // 每个声部为贯穿全曲的一行，声部在某个小节中没有音符时用空白休止占位
+    let mut voices: Vec<Vec<String>> = vec![vec![]; part.voices.len()];
+    for measure in 0..layout.measure_count {
+      let fifths = fifths_at(&info.key_signatures, measure * measure_ticks);
+      let mut measure_voices = measure_voices(&part.voices, measure, measure_ticks, info.ppq).into_iter().peekable();
+      for (v, voice) in voices.iter_mut().enumerate() {
+        let mut tokens = vec![];
+        if let Some((fifths, mode)) = key_change(&info.key_signatures, measure, measure_ticks) && v == 0 && !drums {
+          tokens.push(key_command(fifths, mode));
+        }
+        match measure_voices.next_if(|(voice, _)| *voice == v) {
+          Some((_, segments)) => for segment in segments {
+            tokens.push(match segment {
+              Segment::MeasureRest => format!("R1*{}/{}", info.time_sig_numerator, info.time_sig_denominator),
+              Segment::Rest( duration ) => format!("r{}", duration_name(duration, info.ppq)),
+              Segment::Chord{notes, duration, tie_start, ..} => {
+                let pitches: Vec<String> = notes.iter()
+                  .map(|note| match drums {
+                    true => DRUM_NAMES[(note - FIRST_DRUM) as usize].to_string(),
+                    false => absolute_pitch(*note, fifths),
+                  })
+                  .collect();
+                let tie = match tie_start {
+                  true => "~",
+                  false => "",
+                };
+                match pitches.len() {
+                  1 => format!("{}{}{tie}", pitches[0], duration_name(duration, info.ppq)),
+                  _ => format!("<{}>{}{tie}", pitches.join(" "), duration_name(duration, info.ppq)),
+                }
+              },
+            });
+          },
+          None => tokens.push(format!("s1*{}/{}", info.time_sig_numerator, info.time_sig_denominator)),
+        }
+        voice.push(format!("{} |", tokens.join(" ")));
+      }
+    }
+
+    match voices.len() {
+      1 => {
+        writeln!(ly, "    {staff} \\with {{ instrumentName = \"{}\" }} {mode}{{", escape(&part.name())).unwrap();
+        for line in header.iter().chain(&voices[0]) {
+          writeln!(ly, "      {line}").unwrap();
+        }
+        writeln!(ly, "    }}").unwrap();
+      },
+      _ => {
+        writeln!(ly, "    {staff} \\with {{ instrumentName = \"{}\" }} {mode}<<", escape(&part.name())).unwrap();
+        for (v, voice) in voices.iter().enumerate() {
+          writeln!(ly, "      \\new {} {{", match drums {
+            true => "DrumVoice",
+            false => "Voice",
+          }).unwrap();
+          writeln!(ly, "        \\voice{}", VOICE_NAMES[v.min(VOICE_NAMES.len() - 1)]).unwrap();
+          let header = match v {
+            0 => header.as_slice(),
+            _ => &[],
+          };
+          for line in header.iter().chain(voice) {
+            writeln!(ly, "        {line}").unwrap();
+          }
+          writeln!(ly, "      }}").unwrap();
+        }
+        writeln!(ly, "    >>").unwrap();
+      },
+    }
+  }
+  writeln!(ly, "  >>").unwrap();
+  writeln!(ly, "  \\layout {{ }}").unwrap();
+  writeln!(ly, "}}").unwrap();
+  Ok(ly)
+}
@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, HashMap};
+
+use midi_file::{MidiFile, Settings, Text};
+use midi_file::core::{Channel, Clocks, DurationName, NoteNumber, Velocity};
+use midi_file::file::{Division, Event, Format, MetaEvent, QuarterNoteDivision, QuartersPerMinute};
+use midi_file::file::Track as MidiTrack;
+
+use crate::ast::score::KeyMode;
+use crate::error::Error;
+use crate::tuning::Tuning;
+
+use super::Interpreter;
+use super::score::{DEFAULT_TUNING_ROOT, PERCUSSION_CHANNEL};
+use super::timeline::{ChannelTimeline, Note, ScoreEvent, Timeline};
+use super::tuning::apply_pitch_bend;
+
+/// 音符关闭力度
+const NOTE_OFF_VELOCITY: Velocity = Velocity::new(72);
+
+const DEFAULT_TIME_SIGNATURE_CLOCKS: Clocks = Clocks::Quarter;
+
+/// .kar 文件第一个 track 开头的标识
+const KARAOKE_HEADER: &str = "@KMIDI KARAOKE FILE";
+
+/// .kar 文件中存放歌词的 track 名称
+const KARAOKE_WORDS_TRACK_NAME: &str = "Words";
+
+/// 一个 channel 上以绝对 tick 计时的音符事件
+#[derive(Debug, Clone, Copy)]
+pub(super) struct NoteEvent {
+  /// 事件发生的绝对 tick
+  pub(super) tick: u32,
+
+  /// 为真表示开启音符,否则表示关闭音符
+  pub(super) on: bool,
+
+  pub(super) note: u8,
+
+  pub(super) velocity: Velocity,
+
+  /// 音高偏移的音分
+  pub(super) cents: i32,
+
+  /// 为实现音律而轮换到的 channel，为 None 时使用 track 所属的 channel
+  pub(super) channel: Option<u8>,
+}
+
+/// 一个 channel 转换为 midi track 之前的事件
+#[derive(Debug, Default)]
+pub(super) struct MidiChannel {
+  pub(super) events: Vec<NoteEvent>,
+
+  /// 附在音符上的歌词音节及其绝对 tick，输出 .kar 时移到 Words track
+  lyrics: Vec<(u32, String)>,
+
+  /// 在同一 tick 的音符开启之前发送的控制事件，如弯音、program change
+  pub(super) controls: Vec<(u32, Event)>,
+}
+
+impl MidiChannel {
+  /// 将音符拆分为开启和关闭两个事件
+  fn new(timeline: &ChannelTimeline) -> Self {
+    let events = timeline.notes.iter()
+      .flat_map(|Note{tick, duration, key, velocity, cents, ..}| [
+        NoteEvent{tick: *tick, on: true, note: *key, velocity: Velocity::new(*velocity), cents: *cents, channel: None},
+        NoteEvent{tick: tick + duration, on: false, note: *key, velocity: NOTE_OFF_VELOCITY, cents: *cents, channel: None},
+      ])
+      .collect();
+    Self {
+      events,
+      lyrics: timeline.lyrics.clone(),
+      controls: vec![],
+    }
+  }
+}
+
+impl Interpreter {
+  /// 由 Timeline 生成 midi 文件。音律以 MIDI Tuning Standard 的 bulk dump 单独输出，或者用弯音实现
+  pub(super) fn timeline_midi(&mut self, timeline: &Timeline) -> Result<MidiFile, Error> {
+    let mut channels: BTreeMap<u8, MidiChannel> = timeline.channels.iter()
+      .map(|(channel_u8, channel)| (*channel_u8, MidiChannel::new(channel)))
+      .collect();
+
+    match self.mts {
+      true => {
+        let has_cents = timeline.channels.iter()
+          .filter(|(channel_u8, _)| **channel_u8 != PERCUSSION_CHANNEL)
+          .any(|(_, channel)| channel.notes.iter().any(|note| note.cents != 0));
+        if has_cents {
+          return Err(Error::RuntimeError(
+            "cent offsets on notes can only be realized with pitch bend, not MIDI Tuning Standard".to_string()
+          ));
+        }
+        self.tuning_dump = timeline.tuning.as_ref()
+          .map(|tuning| tuning.bulk_tuning_dump(timeline.title.as_deref().unwrap_or_default()));
+      },
+      false => {
+        let tuning = timeline.tuning.clone().unwrap_or(Tuning::equal(DEFAULT_TUNING_ROOT));
+        apply_pitch_bend(timeline, &mut channels, &tuning)?;
+      },
+    }
+
+    // 速度、拍号都位于开头，marker、cue point、调号按绝对 tick 排在其后
+    let mut meta_track = MidiTrack::default();
+    if let Some(tempo) = timeline.tempo {
+      meta_track.push_tempo(0, QuartersPerMinute::new(tempo))
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+    if let Some((numerator, denominator)) = timeline.time_signature {
+      meta_track.push_time_signature(0, numerator, duration_name(denominator)?, DEFAULT_TIME_SIGNATURE_CLOCKS)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+    let mut last_tick = 0;
+    for (tick, event) in &timeline.events {
+      let event = match event {
+        ScoreEvent::Marker(text) => Event::Meta(MetaEvent::Marker(Text::new(text))),
+        ScoreEvent::CuePoint(text) => Event::Meta(MetaEvent::CuePoint(Text::new(text))),
+        ScoreEvent::KeySignature(fifths, mode) => key_signature_event(*fifths, *mode)?,
+      };
+      meta_track.push_event(tick - last_tick, event)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+      last_tick = *tick;
+    }
+    if let Some(title) = &timeline.title {
+      meta_track.set_name(title)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+
+    // .kar 文件的歌词以 text event 的形式集中在 meta track 之后的 Words track 中
+    let mut words_track_ = None;
+    if self.karaoke {
+      meta_track.insert_event(0, 0, Event::Meta(MetaEvent::OtherText(Text::new(KARAOKE_HEADER))))
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+
+      let mut words = vec![];
+      for channel in channels.values_mut() {
+        words.append(&mut channel.lyrics);
+      }
+      words.sort_by_key(|(tick, _)| *tick);
+
+      let mut words_track = MidiTrack::default();
+      words_track.set_name(KARAOKE_WORDS_TRACK_NAME)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+      let mut last_tick = 0;
+      for (tick, syllable) in words {
+        words_track.push_event(tick - last_tick, Event::Meta(MetaEvent::OtherText(Text::new(syllable))))
+          .map_err(|e| Error::RuntimeError(e.to_string()))?;
+        last_tick = tick;
+      }
+      words_track_ = Some(words_track);
+    }
+
+    // 输出的 track 顺序是固定的: meta track 在最前，其后为各 channel 的 track，按 channel 编号升序排列
+    let mut midi_tracks = vec![meta_track];
+    midi_tracks.extend(words_track_);
+    for (channel_u8, channel) in channels {
+      midi_tracks.push(channel.into_midi_track(&timeline.channels[&channel_u8], Channel::new(channel_u8))?);
+    }
+
+    let format = match self.single_track {
+      true => Format::Single,
+      false => Format::Multi,
+    };
+    let mut midi_file = MidiFile::new_with_settings(
+      Settings::new()
+        .format(format)
+        .divisions(Division::QuarterNote(QuarterNoteDivision::new(timeline.ppq)))
+    );
+    if self.single_track {
+      midi_tracks = vec![merge_tracks(midi_tracks)?];
+    }
+    for track in midi_tracks {
+      midi_file.push_track(track)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+
+    Ok(midi_file)
+  }
+}
+
+/// 设置音量和声像的 control change，写在 channel 开头，轮换 channel 时也要复制到新的 channel 上
+pub(super) fn mix_controls(timeline: &ChannelTimeline, channel_u8: u8) -> Result<Vec<Event>, Error> {
+  let mut controls = vec![];
+  if let Some(volume) = timeline.volume {
+    controls.push(raw_event(&[0xb0 | channel_u8, 7, volume])?);
+  }
+  if let Some(pan) = timeline.pan {
+    controls.push(raw_event(&[0xb0 | channel_u8, 10, pan])?);
+  }
+  Ok(controls)
+}
+
+impl MidiChannel {
+  /// 按绝对 tick 排序后转换为 delta 计时的 midi track，乐器、名称、音量等取自 timeline。
+  /// 同一 tick 上先关闭音符再开启音符，避免同音高的相邻音符被立即关闭；
+  /// 歌词和控制事件排在同一 tick 的音符开启之前。
+  /// 同一 channel 上同音高的音符重叠时，只在最后一个音符结束时关闭，以免先结束的音符提前关闭之后的音符。
+  fn into_midi_track(mut self, timeline: &ChannelTimeline, channel: Channel) -> Result<MidiTrack, Error> {
+    self.events.sort_by_key(|e| (e.tick, e.on));
+    let mix_controls = mix_controls(timeline, channel.get())?;
+    let mut prefixes: Vec<(u32, Event)> = mix_controls.into_iter()
+      .map(|control| (0, control))
+      .chain(self.lyrics.into_iter().map(|(tick, syllable)| (tick, Event::Meta(MetaEvent::Lyric(Text::new(syllable))))))
+      .chain(self.controls)
+      .collect();
+    prefixes.sort_by_key(|(tick, _)| *tick);
+
+    let mut track = MidiTrack::default();
+    if let Some(instrument) = timeline.instrument {
+      track.push_event(0, program_change(channel.get(), instrument)?)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+
+    let mut last_tick = 0;
+    let mut prefixes = prefixes.into_iter().peekable();
+    // 各 (channel, 音高) 上正在发声的音符个数
+    let mut sounding: HashMap<(u8, u8), usize> = HashMap::new();
+    for NoteEvent{tick, on, note, velocity, channel: channel_, ..} in self.events {
+      while let Some((prefix_tick, event)) = prefixes.next_if(|(prefix_tick, _)| *prefix_tick < tick || (*prefix_tick == tick && on)) {
+        track.push_event(prefix_tick - last_tick, event)
+          .map_err(|e| Error::RuntimeError(e.to_string()))?;
+        last_tick = prefix_tick;
+      }
+      let channel = channel_.map(Channel::new).unwrap_or(channel);
+      let count = sounding.entry((channel.get(), note)).or_default();
+      match on {
+        true => *count += 1,
+        false => {
+          *count -= 1;
+          if *count > 0 {
+            continue;
+          }
+        },
+      }
+      let delta = tick - last_tick;
+      last_tick = tick;
+      match on {
+        true => track.push_note_on(delta, channel, NoteNumber::new(note), velocity),
+        false => track.push_note_off(delta, channel, NoteNumber::new(note), velocity),
+      }.map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+    for (prefix_tick, event) in prefixes {
+      track.push_event(prefix_tick - last_tick, event)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+      last_tick = prefix_tick;
+    }
+
+    // 以下事件都插入到 track 开头，最终顺序为名称、乐器名称、program change
+    if let Some(instrument_name) = &timeline.instrument_name {
+      track.set_instrument_name(instrument_name)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+    if let Some(name) = &timeline.name {
+      track.set_name(name)
+        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    }
+    Ok(track)
+  }
+}
+
+/// 拍号分母对应的音符时值
+fn duration_name(denominator: i32) -> Result<DurationName, Error> {
+  Ok(match denominator {
+    1 => DurationName::Whole,
+    2 => DurationName::Half,
+    4 => DurationName::Quarter,
+    8 => DurationName::Eighth,
+    16 => DurationName::Sixteenth,
+    32 => DurationName::D32,
+    64 => DurationName::D64,
+    128 => DurationName::D128,
+    256 => DurationName::D256,
+    512 => DurationName::D512,
+    1024 => DurationName::D1024,
+    _ => return Err(Error::InternalError(format!(
+      "denominator of time signature {denominator} was not checked"
+    ))),
+  })
+}
+
+/// 构造调号 meta event
+fn key_signature_event(fifths: i8, mode: KeyMode) -> Result<Event, Error> {
+  let mode = match mode {
+    KeyMode::Major => 0,
+    KeyMode::Minor => 1,
+  };
+  raw_event(&[0xff, 0x59, 0x02, fifths as u8, mode])
+}
+
+/// 构造 program change，program 从 0 开始。midi_file 的 set_general_midi 把从 1 开始的
+/// GeneralMidi 编号直接写入 program，因此自行构造该事件
+pub(super) fn program_change(channel_u8: u8, program: u8) -> Result<Event, Error> {
+  raw_event(&[0xc0 | channel_u8, program])
+}
+
+/// 由 SMF 中一个事件(不含 delta time)的字节构造 Event。
+/// midi_file 没有导出调号、control change 等事件的构造方式，因此解析一个只含该事件的最小 SMF 来得到它
+pub(super) fn raw_event(bytes: &[u8]) -> Result<Event, Error> {
+  let track_len = bytes.len() as u8 + 5;
+  let mut smf = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk\x00\x00\x00".to_vec();
+  smf.extend([track_len, 0x00]);
+  smf.extend(bytes);
+  smf.extend([0x00, 0xff, 0x2f, 0x00]);
+
+  let midi_file = MidiFile::read(smf.as_slice())
+    .map_err(|e| Error::InternalError(e.to_string()))?;
+  midi_file.tracks()
+    .flat_map(|track| track.events())
+    .map(|track_event| track_event.event().clone())
+    .next()
+    .ok_or(Error::InternalError("failed to build midi event".to_string()))
+}
+
+/// 将多个 midi track 按绝对 tick 合并为一个 track，用于 SMF format 0。
+/// 同一 tick 上的事件保持原有 track 的先后顺序。
+fn merge_tracks(tracks: Vec<MidiTrack>) -> Result<MidiTrack, Error> {
+  let mut events = vec![];
+  for track in &tracks {
+    let mut tick = 0;
+    for track_event in track.events() {
+      tick += track_event.delta_time();
+      // 合并后的 track 由 MidiFile 统一补上 EndOfTrack
+      if let Event::Meta(MetaEvent::EndOfTrack) = track_event.event() {
+        continue;
+      }
+      events.push((tick, track_event.event().clone()));
+    }
+  }
+  events.sort_by_key(|(tick, _)| *tick);
+
+  let mut merged = MidiTrack::default();
+  let mut last_tick = 0;
+  for (tick, event) in events {
+    merged.push_event(tick - last_tick, event)
+      .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    last_tick = tick;
+  }
+  Ok(merged)
+}
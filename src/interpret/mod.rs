@@ -2,35 +2,209 @@ pub mod calc; /// 表达式计算
 pub mod ctr;  /// 控制流
 pub mod asgn_rval;  /// 翻译右值表达式
 pub mod score;  /// 翻译 Score 
+pub mod timeline;  /// 与输出格式无关的中间表示
+pub mod midi;  /// 由中间表示生成 midi
+pub mod builtin;  /// 内置函数
+pub mod tuning;  /// 音律的弯音实现
+pub mod notation;  /// 乐谱的小节、声部划分
+pub mod musicxml;  /// 输出 MusicXML 乐谱
+pub mod lilypond;  /// 输出 LilyPond 乐谱
+pub mod abc;  /// 输出 ABC 记谱法
+pub mod audio;  /// 用内置合成器渲染音频
+pub mod instrument;  /// 内置合成器的乐器定义
+pub mod source_map;  /// 音符到源文件位置的映射
+pub mod test;  /// 执行测试
+pub mod observe;  /// 调试器和执行跟踪使用的调用栈
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std:: rc::Rc;
 
 use ctr::{Ctr, RetVal};
+use instrument::SynthInstrument;
+use notation::Notation;
+use observe::{Frame, Observer};
+use source_map::SourceMap;
+use crate::synth::{soundfont::SoundFont, wav::WavFormat};
 use midi_file::MidiFile;
 
 use crate::ast::expr::Expr;
-use crate::ast::stmt::{Asgn, ConstDecl, IfElse, Stmt, VarDecl, While};
+use crate::ast::span::Span;
+use crate::ast::stmt::{Asgn, Assert, ConstDecl, IfElse, Stmt, VarDecl, While};
 use crate::ast::val::Value;
 use crate::error::Error;
 
 use crate::ast::{block::Block, func::FuncCall, comp_unit::CompUnit};
+use crate::ast::repl::{ReplItem, ReplUnit};
 
 /// 解释器
-pub struct Interpreter {}
+pub struct Interpreter {
+  /// 是否将所有 track 合并输出为单轨的 SMF format 0 文件
+  single_track: bool,
+
+  /// 命令行指定的 PPQ，优先于乐谱中的 @ppq
+  ppq: Option<u16>,
+
+  /// 是否输出 .kar 格式，歌词集中输出到单独的 Words track
+  karaoke: bool,
+
+  /// 是否以 MIDI Tuning Standard 输出音律，否则用弯音实现
+  mts: bool,
+
+  /// 以 MIDI Tuning Standard 输出时生成的 bulk tuning dump
+  tuning_dump: Option<Vec<u8>>,
+
+  /// 输出乐谱而不是 midi 时的乐谱格式
+  notation: Option<Notation>,
+
+  /// 输出乐谱时生成的乐谱源文件
+  score_text: Option<String>,
+
+  /// 输出音频而不是 midi 时的 WAV 格式
+  wav_format: Option<WavFormat>,
+
+  /// 输出音频时渲染得到的 WAV 文件
+  wav: Option<Vec<u8>>,
+
+  /// 渲染音频时使用的 SoundFont，为 None 时使用内置合成器
+  soundfont: Option<SoundFont>,
+
+  /// 乐谱中定义的内置合成器乐器
+  instruments: HashMap<String, SynthInstrument>,
+
+  /// 源文件所在的目录，乐谱中的相对路径(如 scala 文件)相对它解析，为 None 时相对当前目录
+  source_dir: Option<PathBuf>,
+
+  /// 是否生成音符到源文件位置的映射
+  source_mapping: bool,
+
+  /// 生成的音符到源文件位置的映射
+  source_map: Option<SourceMap>,
+
+  /// 是否正在执行测试，只有执行测试时才检查断言
+  testing: bool,
+
+  /// 当前执行到的语句在源文件中的位置，执行出错时即为出错的语句的位置
+  current_span: Span,
+
+  /// 执行过程的观察者，为 None 时不维护调用栈
+  observer: Option<Box<dyn Observer>>,
+
+  /// 有观察者时维护的调用栈
+  frames: Vec<Frame>,
+}
 
 impl Interpreter {
   pub fn new() -> Self {
-    Self {}
+    Self {
+      single_track: false,
+      ppq: None,
+      karaoke: false,
+      mts: false,
+      tuning_dump: None,
+      notation: None,
+      score_text: None,
+      wav_format: None,
+      wav: None,
+      soundfont: None,
+      instruments: HashMap::new(),
+      source_dir: None,
+      source_mapping: false,
+      source_map: None,
+      testing: false,
+      current_span: Span::default(),
+      observer: None,
+      frames: vec![],
+    }
+  }
+
+  /// 设置是否将所有 track 合并输出为单轨的 SMF format 0 文件
+  pub fn set_single_track(&mut self, single_track: bool) {
+    self.single_track = single_track;
+  }
+
+  /// 设置每个四分音符所占的 tick 数(PPQ)，优先于乐谱中的 @ppq
+  pub fn set_ppq(&mut self, ppq: u16) {
+    self.ppq = Some(ppq);
+  }
+
+  /// 设置是否输出 .kar 格式
+  pub fn set_karaoke(&mut self, karaoke: bool) {
+    self.karaoke = karaoke;
+  }
+
+  /// 设置是否以 MIDI Tuning Standard 输出音律
+  pub fn set_mts(&mut self, mts: bool) {
+    self.mts = mts;
+  }
+
+  /// 以 MIDI Tuning Standard 输出音律时，执行后得到的 bulk tuning dump(sysex)
+  pub fn tuning_dump(&self) -> Option<&[u8]> {
+    self.tuning_dump.as_deref()
+  }
+
+  /// 设置输出的乐谱格式，为 None 时输出 midi
+  pub fn set_notation(&mut self, notation: Option<Notation>) {
+    self.notation = notation;
+  }
+
+  /// 输出乐谱时，执行后得到的乐谱源文件
+  pub fn score_text(&self) -> Option<&str> {
+    self.score_text.as_deref()
+  }
+
+  /// 设置输出音频的 WAV 格式，为 None 时不渲染音频
+  pub fn set_wav_format(&mut self, wav_format: Option<WavFormat>) {
+    self.wav_format = wav_format;
+  }
+
+  /// 输出音频时，执行后渲染得到的 WAV 文件
+  pub fn wav(&self) -> Option<&[u8]> {
+    self.wav.as_deref()
+  }
+
+  /// 设置渲染音频时使用的 SoundFont，为 None 时使用内置合成器
+  pub fn set_soundfont(&mut self, soundfont: Option<SoundFont>) {
+    self.soundfont = soundfont;
+  }
+
+  /// 设置源文件所在的目录，乐谱中的相对路径相对它解析
+  pub fn set_source_dir(&mut self, source_dir: Option<PathBuf>) {
+    self.source_dir = source_dir;
+  }
+
+  /// 设置是否生成音符到源文件位置的映射
+  pub fn set_source_mapping(&mut self, source_mapping: bool) {
+    self.source_mapping = source_mapping;
+  }
+
+  /// 生成映射时，执行后得到的音符到源文件位置的映射
+  pub fn source_map(&self) -> Option<&SourceMap> {
+    self.source_map.as_ref()
+  }
+
+  /// 当前执行到的语句在源文件中的位置，执行出错后即为出错的语句的位置
+  pub fn get_current_span(&self) -> Span {
+    self.current_span
   }
 
   /// 执行一段函数，返回结果为 RetVal 类型
   pub fn call_func(&mut self, func_call: &FuncCall) -> Result<RetVal, Error> {
+    if let Some( builtin ) = func_call.get_builtin() {
+      return self.call_builtin(func_call, builtin);
+    }
+
     let func_def = func_call.get_func_def();
     let len = func_def.func_fparams.len();
+    let mut args = vec![];
     for i in 0..len {
       let param = &func_def.func_fparams[i];
       let asgn_rval = &func_call.func_rparams[i];
       match self.interpret_asgn_rval(asgn_rval)? {
+        RetVal::Value( v ) if self.observed() => {
+          args.push(v.clone());
+          param.set_value(v);
+        },
         RetVal::Value( v ) => param.set_value(v),
         val => {
           let ident = &func_def.func_fparams[i].ident;
@@ -43,15 +217,24 @@ impl Interpreter {
       }
     }
 
-    match self.interpret_block(func_def.block.clone())? {
-      Ctr::Return( v ) => Ok(v),
-      _ => Err(Error::RuntimeError("function didn't return".to_string())),
+    self.observe_call(&func_def.ident, &args, func_call.span)?;
+    self.push_frame(&func_def.ident, args, Some(func_call.span));
+    for param in &func_def.func_fparams {
+      self.declare(&param.ident, &param.rval);
     }
+    let ret_val = match self.interpret_block(func_def.block.clone())? {
+      Ctr::Return( v ) => v,
+      _ => return Err(Error::RuntimeError("function didn't return".to_string())),
+    };
+    self.observe_return(&func_def.ident, &ret_val)?;
+    self.pop_frame();
+    Ok(ret_val)
   }
 
   pub fn interpret_const_decl(&mut self, const_decl: &ConstDecl) -> Result<Ctr, Error> {
     let len = const_decl.const_defs.len();
     for i in 0..len {
+      self.declare(&const_decl.const_defs[i].ident, &const_decl.rvals[i]);
       match self.interpret_asgn_rval(&const_decl.const_defs[i].rval)? {
         RetVal::Value( v ) => const_decl.rvals[i].set_value(v),
         val => {
@@ -70,6 +253,7 @@ impl Interpreter {
     let len = var_decl.var_defs.len();
     for i in 0..len {
       let var_def = &var_decl.var_defs[i];
+      self.declare(&var_def.ident, &var_decl.rvals[i]);
       let asgn_rval_ = var_def.rval_.as_ref();
       if asgn_rval_.is_none() {
         continue;
@@ -149,7 +333,28 @@ impl Interpreter {
     }
   }
 
+  /// 执行测试时检查断言，否则跳过
+  pub fn interpret_assert(&mut self, assert: &Assert) -> Result<Ctr, Error> {
+    if !self.testing {
+      return Ok(Ctr::None);
+    }
+    match self.calc_expr(&assert.cond)? {
+      RetVal::Value(Value::Int(0)) => Err(Error::RuntimeError(match &assert.message {
+        Some( message ) => format!("assertion failed: {message}"),
+        None => "assertion failed".to_string(),
+      })),
+      RetVal::Value(Value::Int(_)) => Ok(Ctr::None),
+      val => Err(Error::RuntimeError(format!(
+        "expect int/bool for assertion, but found {val}"
+      ))),
+    }
+  }
+
   pub fn interpret_stmt(&mut self, stmt: &Stmt) -> Result<Ctr, Error> {
+    // 函数定义和 Block 本身没有需要执行的内容，不作为一步
+    if !matches!(stmt, Stmt::FuncDef(_) | Stmt::Block(_)) {
+      self.observe_stmt(stmt)?;
+    }
     match stmt {
       Stmt::FuncDef( _ ) => Ok(Ctr::None),
       Stmt::Break => Ok(Ctr::Break),
@@ -165,28 +370,65 @@ impl Interpreter {
         true => self.calc_expr(expr_.as_ref().unwrap()).map(|_| { Ctr::None }),
         false => Ok(Ctr::None),
       },
+      Stmt::Assert( assert ) => self.interpret_assert(assert),
     }
   }
 
   pub fn interpret_block(&mut self, block: Rc<Block>) -> Result<Ctr, Error> {
-    for stmt in &block.stmts {
+    // 出错时保留出错的语句的位置，否则恢复为包含这一 Block 的语句的位置
+    let cur_span = self.current_span;
+    let mut ctr = Ctr::None;
+    self.push_scope();
+    for (stmt, span) in block.stmts.iter().zip(&block.spans) {
+      self.current_span = *span;
       match self.interpret_stmt(stmt)? {
-        Ctr::Break => return Ok(Ctr::Break),
-        Ctr::Return( v ) => return Ok(Ctr::Return( v )),
-        _ => (),
+        Ctr::Break => ctr = Ctr::Break,
+        Ctr::Return( v ) => ctr = Ctr::Return( v ),
+        _ => continue,
       }
+      break;
     }
-    Ok(Ctr::None)
+    self.pop_scope();
+    self.current_span = cur_span;
+    Ok(ctr)
   }
 
-  pub fn interpret(&mut self, comp_unit: &CompUnit) -> Result<MidiFile, Error> {
-    for stmt in &comp_unit.block.stmts {
+  /// 按顺序初始化全局常量和变量。有观察者时重新建立调用栈，全局常量和变量位于最底下的一帧
+  pub fn interpret_globals(&mut self, comp_unit: &CompUnit) -> Result<(), Error> {
+    self.frames.clear();
+    self.push_frame("<global>", vec![], None);
+    for (stmt, span) in comp_unit.block.stmts.iter().zip(&comp_unit.block.spans) {
+      self.current_span = *span;
       match stmt {
-        Stmt::VarDecl( var_decl ) => self.interpret_var_decl(var_decl),
-        Stmt::ConstDecl( const_decl ) => self.interpret_const_decl(const_decl),
+        Stmt::VarDecl(_) | Stmt::ConstDecl(_) => self.interpret_stmt(stmt),
         _ => Ok(Ctr::None),
       }?;
     }
+    Ok(())
+  }
+
+  pub fn interpret(&mut self, comp_unit: &CompUnit) -> Result<MidiFile, Error> {
+    self.interpret_globals(comp_unit)?;
+    for instrument_def in &comp_unit.instruments {
+      self.interpret_instrument_def(instrument_def)?;
+    }
     self.interpret_score(&comp_unit.score)
   }
+
+  /// 执行交互式解释器的一次输入，返回求值的结果。乐谱由交互式解释器保存，在写出文件时才执行
+  pub fn interpret_repl(&mut self, repl_unit: &ReplUnit) -> Result<RetVal, Error> {
+    match repl_unit {
+      ReplUnit::Items( items ) => {
+        for (item, _) in items {
+          match item {
+            ReplItem::Stmt( stmt ) => self.interpret_stmt(stmt).map(|_| ())?,
+            ReplItem::InstrumentDef( instrument_def ) => self.interpret_instrument_def(instrument_def)?,
+          }
+        }
+        Ok(RetVal::Void)
+      },
+      ReplUnit::Eval( asgn_rval ) => self.interpret_asgn_rval(asgn_rval),
+      ReplUnit::Score(_) => Ok(RetVal::Void),
+    }
+  }
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use crate::{ast::score::KeyMode, error::Error, gm};
+
+use super::notation::{fifths_at, key_change, layout, measure_voices, spell, Duration, ScoreInfo, Segment};
+use super::score::PERCUSSION_CHANNEL;
+use super::timeline::Timeline;
+
+/// 音符类型名称，下标为全音符减半的次数
+const NOTE_TYPES: [&str; 8] = ["whole", "half", "quarter", "eighth", "16th", "32nd", "64th", "128th"];
+
+/// 将 XML 文本中的特殊字符转义
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+/// 由各 channel 的音符事件生成 score-partwise 形式的 MusicXML。
+/// 每个 channel 为一个 part，按拍号划分小节，同时开始、同时结束的音符为和弦，
+/// 重叠的音符放入不同的声部，跨小节的音符用连音线连接。
+pub(super) fn score_partwise(timeline: &Timeline) -> Result<String, Error> {
+  let info = ScoreInfo::new(timeline);
+  let layout = layout(&info, &timeline.channels)?;
+  let measure_ticks = layout.measure_ticks;
+
+  let mut xml = String::new();
+  writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#).unwrap();
+  writeln!(xml, r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#).unwrap();
+  writeln!(xml, r#"<score-partwise version="4.0">"#).unwrap();
+  if let Some(title) = info.title {
+    writeln!(xml, "  <work><work-title>{}</work-title></work>", escape(title)).unwrap();
+  }
+  writeln!(xml, "  <identification><encoding><software>yam</software></encoding></identification>").unwrap();
+
+  // part-list 中每个 part 的名称和 midi 设置
+  writeln!(xml, "  <part-list>").unwrap();
+  for part in &layout.parts {
+    let (channel, timeline) = (part.channel, part.timeline);
+    let instrument_name = timeline.instrument
+      .map(|instrument| gm::INSTRUMENTS[instrument as usize].to_string());
+    let part_name = part.name();
+    writeln!(xml, r#"    <score-part id="P{channel}">"#).unwrap();
+    writeln!(xml, "      <part-name>{}</part-name>", escape(&part_name)).unwrap();
+    writeln!(xml, r#"      <score-instrument id="P{channel}-I1"><instrument-name>{}</instrument-name></score-instrument>"#,
+      escape(&timeline.instrument_name.clone().or(instrument_name).unwrap_or(part_name))).unwrap();
+    write!(xml, r#"      <midi-instrument id="P{channel}-I1"><midi-channel>{}</midi-channel>"#, channel + 1).unwrap();
+    if let Some(instrument) = timeline.instrument {
+      // MusicXML 的 midi-program 从 1 开始
+      write!(xml, "<midi-program>{}</midi-program>", instrument + 1).unwrap();
+    }
+    writeln!(xml, "</midi-instrument>").unwrap();
+    writeln!(xml, "    </score-part>").unwrap();
+  }
+  writeln!(xml, "  </part-list>").unwrap();
+
+  for (i, part) in layout.parts.iter().enumerate() {
+    let percussion = part.channel == PERCUSSION_CHANNEL;
+    writeln!(xml, r#"  <part id="P{}">"#, part.channel).unwrap();
+    for measure in 0..layout.measure_count {
+      writeln!(xml, r#"    <measure number="{}">"#, measure + 1).unwrap();
+
+      // 第一个小节写入 divisions、调号、拍号和谱号，之后的小节只在转调时写入调号，打击乐没有调号
+      let key_change = key_change(&info.key_signatures, measure, measure_ticks).filter(|_| !percussion);
+      if measure == 0 {
+        let clef = match (percussion, part.is_low()) {
+          (true, _) => "<sign>percussion</sign>",
+          (false, true) => "<sign>F</sign><line>4</line>",
+          (false, false) => "<sign>G</sign><line>2</line>",
+        };
+        writeln!(xml, "      <attributes>").unwrap();
+        writeln!(xml, "        <divisions>{}</divisions>", info.ppq).unwrap();
+        if let Some((fifths, mode)) = key_change {
+          writeln!(xml, "        <key><fifths>{fifths}</fifths><mode>{}</mode></key>", key_mode_name(mode)).unwrap();
+        }
+        writeln!(xml, "        <time><beats>{}</beats><beat-type>{}</beat-type></time>", info.time_sig_numerator, info.time_sig_denominator).unwrap();
+        writeln!(xml, "        <clef>{clef}</clef>").unwrap();
+        writeln!(xml, "      </attributes>").unwrap();
+        if let Some(tempo) = info.tempo && i == 0 {
+          writeln!(xml, r#"      <direction placement="above">"#).unwrap();
+          writeln!(xml, "        <direction-type><metronome><beat-unit>quarter</beat-unit><per-minute>{tempo}</per-minute></metronome></direction-type>").unwrap();
+          writeln!(xml, r#"        <sound tempo="{tempo}"/>"#).unwrap();
+          writeln!(xml, "      </direction>").unwrap();
+        }
+      } else if let Some((fifths, mode)) = key_change {
+        writeln!(xml, "      <attributes><key><fifths>{fifths}</fifths><mode>{}</mode></key></attributes>", key_mode_name(mode)).unwrap();
+      }
+      let fifths = fifths_at(&info.key_signatures, measure * measure_ticks);
+
+      for (v, (voice, segments)) in measure_voices(&part.voices, measure, measure_ticks, info.ppq).into_iter().enumerate() {
+        if v > 0 {
+          writeln!(xml, "      <backup><duration>{measure_ticks}</duration></backup>").unwrap();
+        }
+        let voice = voice + 1;
+        for segment in segments {
+          match segment {
+            Segment::MeasureRest => {
+              writeln!(xml, r#"      <note><rest measure="yes"/><duration>{measure_ticks}</duration><voice>{voice}</voice></note>"#).unwrap();
+            },
+            Segment::Rest( duration ) => {
+              write!(xml, "      <note><rest/><duration>{}</duration><voice>{voice}</voice>", duration.ticks).unwrap();
+              write_note_type(&mut xml, duration);
+              writeln!(xml, "</note>").unwrap();
+            },
+            Segment::Chord{notes, duration, tie_stop, tie_start, syllable} => {
+              for (n, note) in notes.iter().enumerate() {
+                let (step, alter, octave) = spell(*note, fifths);
+                write!(xml, "      <note>").unwrap();
+                if n > 0 {
+                  write!(xml, "<chord/>").unwrap();
+                }
+                match (percussion, alter) {
+                  (true, _) => write!(xml, "<unpitched><display-step>{step}</display-step><display-octave>{octave}</display-octave></unpitched>").unwrap(),
+                  (false, 0) => write!(xml, "<pitch><step>{step}</step><octave>{octave}</octave></pitch>").unwrap(),
+                  (false, alter) => write!(xml, "<pitch><step>{step}</step><alter>{alter}</alter><octave>{octave}</octave></pitch>").unwrap(),
+                }
+                write!(xml, "<duration>{}</duration>", duration.ticks).unwrap();
+                if tie_stop {
+                  write!(xml, r#"<tie type="stop"/>"#).unwrap();
+                }
+                if tie_start {
+                  write!(xml, r#"<tie type="start"/>"#).unwrap();
+                }
+                write!(xml, "<voice>{voice}</voice>").unwrap();
+                write_note_type(&mut xml, duration);
+                if tie_stop || tie_start {
+                  write!(xml, "<notations>").unwrap();
+                  if tie_stop {
+                    write!(xml, r#"<tied type="stop"/>"#).unwrap();
+                  }
+                  if tie_start {
+                    write!(xml, r#"<tied type="start"/>"#).unwrap();
+                  }
+                  write!(xml, "</notations>").unwrap();
+                }
+                if let Some(syllable) = syllable && n == 0 {
+                  write!(xml, "<lyric><text>{}</text></lyric>", escape(syllable)).unwrap();
+                }
+                writeln!(xml, "</note>").unwrap();
+              }
+            },
+          }
+        }
+      }
+      writeln!(xml, "    </measure>").unwrap();
+    }
+    writeln!(xml, "  </part>").unwrap();
+  }
+  writeln!(xml, "</score-partwise>").unwrap();
+  Ok(xml)
+}
+
+/// MusicXML 中调式的名称
+fn key_mode_name(mode: KeyMode) -> &'static str {
+  match mode {
+    KeyMode::Major => "major",
+    KeyMode::Minor => "minor",
+  }
+}
+
+/// 写入音符类型及附点
+fn write_note_type(xml: &mut String, duration: Duration) {
+  if let Some((note_type, dots)) = duration.note_type {
+    write!(xml, "<type>{}</type>", NOTE_TYPES[note_type as usize]).unwrap();
+    for _ in 0..dots {
+      write!(xml, "<dot/>").unwrap();
+    }
+  }
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+
+use crate::{ast::score::KeyMode, error::Error, gm};
+
+use super::timeline::{ChannelTimeline, Timeline};
+
+/// 按五度圈排列的升号顺序
+const SHARP_ORDER: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+
+/// 按五度圈排列的降号顺序
+const FLAT_ORDER: [char; 7] = ['B', 'E', 'A', 'D', 'G', 'C', 'F'];
+
+/// 自然音的音名及其音级
+const NATURALS: [(char, i32); 7] = [('C', 0), ('D', 2), ('E', 4), ('F', 5), ('G', 7), ('A', 9), ('B', 11)];
+
+/// 调外音在升号调中的拼写，(音名, 变音)
+const SHARP_SPELLING: [(char, i8); 12] = [
+  ('C', 0), ('C', 1), ('D', 0), ('D', 1), ('E', 0), ('F', 0),
+  ('F', 1), ('G', 0), ('G', 1), ('A', 0), ('A', 1), ('B', 0),
+];
+
+/// 调外音在降号调中的拼写，(音名, 变音)
+const FLAT_SPELLING: [(char, i8); 12] = [
+  ('C', 0), ('D', -1), ('D', 0), ('E', -1), ('E', 0), ('F', 0),
+  ('G', -1), ('G', 0), ('A', -1), ('A', 0), ('B', -1), ('B', 0),
+];
+
+/// 最短的音符类型为 128 分音符，即全音符减半 7 次
+const SHORTEST_NOTE_TYPE: u32 = 7;
+
+/// 乐谱的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+  MusicXml,
+  LilyPond,
+  Abc,
+}
+
+/// 记谱所需的与 channel 无关的乐曲信息
+pub(super) struct ScoreInfo<'a> {
+  pub(super) title: Option<&'a str>,
+
+  /// 每个四分音符所占的 tick 数
+  pub(super) ppq: u16,
+
+  pub(super) time_sig_numerator: i32,
+  pub(super) time_sig_denominator: i32,
+
+  /// 每分钟的四分音符数
+  pub(super) tempo: Option<u8>,
+
+  /// 调号的绝对 tick、升降号数以及调式，按 tick 排序
+  pub(super) key_signatures: Vec<(u32, i8, KeyMode)>,
+}
+
+impl<'a> ScoreInfo<'a> {
+  pub(super) fn new(timeline: &'a Timeline) -> Self {
+    let (time_sig_numerator, time_sig_denominator) = timeline.time_signature();
+    Self {
+      title: timeline.title.as_deref(),
+      ppq: timeline.ppq,
+      time_sig_numerator,
+      time_sig_denominator,
+      tempo: timeline.tempo,
+      key_signatures: timeline.key_signatures(),
+    }
+  }
+}
+
+/// 同时开始、同时结束的一组音符
+pub(super) struct Chord {
+  start: u32,
+  end: u32,
+  pub(super) notes: Vec<u8>,
+  syllable: Option<String>,
+}
+
+/// 乐谱中的一个声部(part)，即一个 channel
+pub(super) struct Part<'a> {
+  pub(super) channel: u8,
+  pub(super) timeline: &'a ChannelTimeline,
+
+  /// 各个声部(voice)中互不重叠的和弦
+  pub(super) voices: Vec<Vec<Chord>>,
+}
+
+/// 按拍号划分小节后的乐谱
+pub(super) struct Layout<'a> {
+  pub(super) parts: Vec<Part<'a>>,
+  pub(super) measure_ticks: u32,
+  pub(super) measure_count: u32,
+}
+
+/// 可以记谱的时值，note_type 为 (全音符减半的次数, 附点数)，无法用音符类型表示时为 None
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Duration {
+  pub(super) ticks: u32,
+  pub(super) note_type: Option<(u32, u32)>,
+}
+
+/// 一个小节中某个声部的内容
+pub(super) enum Segment<'a> {
+  /// 整小节休止
+  MeasureRest,
+
+  Rest(Duration),
+
+  Chord {
+    notes: &'a [u8],
+    duration: Duration,
+
+    /// 是否与前一个音符用连音线连接
+    tie_stop: bool,
+
+    /// 是否与后一个音符用连音线连接
+    tie_start: bool,
+
+    syllable: Option<&'a str>,
+  },
+}
+
+impl Part<'_> {
+  /// 声部名称，依次取 channel 的 track 名称、乐器名称、General MIDI 乐器名称
+  pub(super) fn name(&self) -> String {
+    let timeline = self.timeline;
+    timeline.name.clone()
+      .or(timeline.instrument_name.clone())
+      .or(timeline.instrument.map(|instrument| gm::INSTRUMENTS[instrument as usize].to_string()))
+      .unwrap_or(format!("Channel {}", self.channel))
+  }
+
+  /// 音符的平均音高低于中央 C 时使用低音谱号
+  pub(super) fn is_low(&self) -> bool {
+    let pitches: Vec<u32> = self.voices.iter()
+      .flatten()
+      .flat_map(|chord| chord.notes.iter().map(|note| *note as u32))
+      .collect();
+    pitches.iter().sum::<u32>() < 60 * pitches.len() as u32
+  }
+}
+
+/// 由 MIDI 音高得到音名、变音和八度。
+/// 调内音按调号拼写(如升 F 大调中的 E#)，调外音在降号调中拼写为降号，否则拼写为升号
+pub(super) fn spell(note: u8, fifths: i8) -> (char, i8, i32) {
+  let pitch_class = note as i32 % 12;
+  let in_key = NATURALS.iter().find_map(|(step, natural)| {
+    let alter = match fifths {
+      f if f > 0 && SHARP_ORDER[..f as usize].contains(step) => 1,
+      f if f < 0 && FLAT_ORDER[..(-f) as usize].contains(step) => -1,
+      _ => 0,
+    };
+    ((natural + alter).rem_euclid(12) == pitch_class).then_some((*step, alter as i8))
+  });
+  let (step, alter) = in_key.unwrap_or(match fifths < 0 {
+    true => FLAT_SPELLING[pitch_class as usize],
+    false => SHARP_SPELLING[pitch_class as usize],
+  });
+  (step, alter, (note as i32 - alter as i32).div_euclid(12) - 1)
+}
+
+/// 调号对应的主音拼写
+pub(super) fn key_tonic(fifths: i8, mode: KeyMode) -> (char, i8) {
+  // 大调主音相对 C 的半音数为 7 * fifths，小调主音在其下方小三度
+  let offset = match mode {
+    KeyMode::Major => 0,
+    KeyMode::Minor => 9,
+  };
+  let (step, alter, _) = spell((60 + (7 * fifths as i32 + offset).rem_euclid(12)) as u8, fifths);
+  (step, alter)
+}
+
+/// 将 ticks 拆分为若干个可以用音符类型(最多一个附点)表示的时值，无法表示的剩余部分没有音符类型
+pub(super) fn split_duration(mut ticks: u32, ppq: u16) -> Vec<Duration> {
+  let whole = 4 * ppq as u32;
+  let mut durations = vec![];
+  while ticks > 0 {
+    let duration = (0..=SHORTEST_NOTE_TYPE)
+      .filter(|i| whole.is_multiple_of(1 << i))
+      .find_map(|i| {
+        let base = whole >> i;
+        match base {
+          base if base.is_multiple_of(2) && ticks >= base + base / 2 => Some(Duration{ticks: base + base / 2, note_type: Some((i, 1))}),
+          base if ticks >= base => Some(Duration{ticks: base, note_type: Some((i, 0))}),
+          _ => None,
+        }
+      });
+    let duration = duration.unwrap_or(Duration{ticks, note_type: None});
+    ticks -= duration.ticks;
+    durations.push(duration);
+  }
+  durations
+}
+
+/// 在 tick 处生效的调号的升降号数
+pub(super) fn fifths_at(key_signatures: &[(u32, i8, KeyMode)], tick: u32) -> i8 {
+  key_signatures.iter()
+    .rev()
+    .find(|(key_tick, _, _)| *key_tick <= tick)
+    .map(|(_, fifths, _)| *fifths)
+    .unwrap_or(0)
+}
+
+/// 需要在小节开头写出的调号。第一个小节总是有调号，之后的小节只在该小节内转调时有调号，
+/// 小节中间的转调提前到小节开头
+pub(super) fn key_change(key_signatures: &[(u32, i8, KeyMode)], measure: u32, measure_ticks: u32) -> Option<(i8, KeyMode)> {
+  let measure_start = measure * measure_ticks;
+  let measure_end = measure_start + measure_ticks;
+  let key = key_signatures.iter()
+    .rev()
+    .find(|(tick, _, _)| *tick < measure_end && (measure == 0 || *tick >= measure_start))
+    .map(|(_, fifths, mode)| (*fifths, *mode));
+  match measure {
+    0 => Some(key.unwrap_or((0, KeyMode::Major))),
+    _ => key,
+  }
+}
+
+/// 将一个 channel 的音符按 (开始, 结束, 音高) 排序，再将同时开始、同时结束的音符合并为和弦
+fn channel_chords(timeline: &ChannelTimeline) -> Vec<Chord> {
+  let mut notes: Vec<(u32, u32, u8)> = timeline.notes.iter()
+    .map(|note| (note.tick, note.tick + note.duration, note.key))
+    .collect();
+  notes.sort();
+  notes.dedup();
+
+  let mut chords: Vec<Chord> = vec![];
+  for (start, end, note) in notes {
+    match chords.last_mut() {
+      Some( chord ) if chord.start == start && chord.end == end => chord.notes.push(note),
+      _ => chords.push(Chord{start, end, notes: vec![note], syllable: None}),
+    }
+  }
+
+  // 歌词附在该 tick 开始的第一个和弦上
+  for (tick, syllable) in &timeline.lyrics {
+    if let Some(chord) = chords.iter_mut().find(|chord| chord.start == *tick && chord.syllable.is_none()) {
+      chord.syllable = Some(syllable.clone());
+    }
+  }
+  chords
+}
+
+/// 将和弦分配到各个声部，同一声部中的和弦互不重叠
+fn assign_voices(chords: Vec<Chord>) -> Vec<Vec<Chord>> {
+  let mut voices: Vec<Vec<Chord>> = vec![];
+  for chord in chords {
+    match voices.iter_mut().find(|voice| voice.last().is_none_or(|last| last.end <= chord.start)) {
+      Some( voice ) => voice.push(chord),
+      None => voices.push(vec![chord]),
+    }
+  }
+  voices
+}
+
+/// 按拍号将各 channel 的音符划分为小节。每个有音符的 channel 为一个 part，
+/// 同时开始、同时结束的音符为和弦，重叠的音符放入不同的声部，所有 part 的小节数相同
+pub(super) fn layout<'a>(info: &ScoreInfo, timelines: &'a BTreeMap<u8, ChannelTimeline>) -> Result<Layout<'a>, Error> {
+  let measure_ticks = info.time_sig_numerator as u32 * 4 * info.ppq as u32 / info.time_sig_denominator as u32;
+  if measure_ticks == 0 {
+    return Err(Error::RuntimeError(
+      "can not write notation with a measure of zero length".to_string()
+    ));
+  }
+
+  let parts: Vec<Part> = timelines.iter()
+    .filter(|(_, timeline)| !timeline.notes.is_empty())
+    .map(|(channel, timeline)| Part {
+      channel: *channel,
+      timeline,
+      voices: assign_voices(channel_chords(timeline)),
+    })
+    .collect();
+  let end = parts.iter()
+    .flat_map(|part| part.voices.iter().flatten())
+    .map(|chord| chord.end)
+    .max()
+    .unwrap_or(0);
+  let measure_count = end.div_ceil(measure_ticks).max(1);
+  Ok(Layout{parts, measure_ticks, measure_count})
+}
+
+/// 一个小节中各个声部的内容，返回 (声部下标, 内容)。
+/// 第一个声部总是有内容，空小节为整小节休止，其余声部在小节中没有音符时省略；
+/// 跨小节或无法用一个音符类型表示的音符拆分为用连音线连接的多个音符
+pub(super) fn measure_voices<'a>(voices: &'a [Vec<Chord>], measure: u32, measure_ticks: u32, ppq: u16) -> Vec<(usize, Vec<Segment<'a>>)> {
+  let measure_start = measure * measure_ticks;
+  let measure_end = measure_start + measure_ticks;
+  let mut measure_voices = vec![];
+  for (v, voice) in voices.iter().enumerate() {
+    let chords: Vec<&Chord> = voice.iter()
+      .filter(|chord| chord.start < measure_end && chord.end > measure_start)
+      .collect();
+    if chords.is_empty() {
+      if v == 0 {
+        measure_voices.push((v, vec![Segment::MeasureRest]));
+      }
+      continue;
+    }
+
+    let mut segments = vec![];
+    let mut cursor = measure_start;
+    for chord in chords {
+      let start = chord.start.max(measure_start);
+      if start > cursor {
+        segments.extend(split_duration(start - cursor, ppq).into_iter().map(Segment::Rest));
+      }
+      let end = chord.end.min(measure_end);
+      let durations = split_duration(end - start, ppq);
+      let count = durations.len();
+      for (i, duration) in durations.into_iter().enumerate() {
+        segments.push(Segment::Chord {
+          notes: &chord.notes,
+          duration,
+          tie_stop: i > 0 || chord.start < measure_start,
+          tie_start: i + 1 < count || chord.end > measure_end,
+          syllable: chord.syllable.as_deref().filter(|_| i == 0 && chord.start >= measure_start),
+        });
+      }
+      cursor = end;
+    }
+    if cursor < measure_end {
+      segments.extend(split_duration(measure_end - cursor, ppq).into_iter().map(Segment::Rest));
+    }
+    measure_voices.push((v, segments));
+  }
+  measure_voices
+}
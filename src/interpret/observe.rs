@@ -0,0 +1,131 @@
+use std::rc::Rc;
+
+use crate::ast::span::Span;
+use crate::ast::stmt::Stmt;
+use crate::ast::val::{RVal, Value};
+use crate::error::Error;
+
+use super::ctr::RetVal;
+use super::Interpreter;
+
+/// 调用栈中的一帧。最底下一帧为全局常量和变量的初始化，乐谱和测试各自为一帧，每次调用用户定义的函数为一帧
+#[derive(Debug)]
+pub struct Frame {
+  /// 函数名，或 `<global>`、`@score`、`test "name"`
+  pub name: String,
+
+  /// 调用函数时的实参
+  pub args: Vec<Value>,
+
+  /// 在上一帧中调用这一帧的函数调用的位置，不是函数调用时为 None
+  pub call_span: Option<Span>,
+
+  /// 各层 Block 中到目前为止已经声明的常量、变量和函数参数，外层的 Block 在前
+  pub scopes: Vec<Vec<(String, Rc<RVal>)>>,
+}
+
+impl Frame {
+  fn new(name: String, args: Vec<Value>, call_span: Option<Span>) -> Self {
+    Self { name, args, call_span, scopes: vec![vec![]] }
+  }
+
+  /// 按从内层到外层的顺序查找这一帧中的常量或变量
+  pub fn lookup(&self, ident: &str) -> Option<&Rc<RVal>> {
+    self.scopes.iter().rev()
+      .flat_map(|scope| scope.iter().rev())
+      .find(|(name, _)| name == ident)
+      .map(|(_, rval)| rval)
+  }
+
+  /// 这一帧中所有可见的常量和变量，内层的同名符号遮蔽外层的符号
+  pub fn variables(&self) -> Vec<(&str, &Rc<RVal>)> {
+    let mut variables: Vec<(&str, &Rc<RVal>)> = vec![];
+    for (name, rval) in self.scopes.iter().flatten() {
+      match variables.iter_mut().find(|(seen, _)| seen == name) {
+        Some( variable ) => variable.1 = rval,
+        None => variables.push((name, rval)),
+      }
+    }
+    variables
+  }
+}
+
+/// 执行过程的观察者，供调试器和执行跟踪使用。返回错误时停止执行
+pub trait Observer {
+  /// 执行一条语句之前调用。frames 为调用栈，最后一帧为当前执行的函数，span 为该语句的位置
+  fn stmt(&mut self, frames: &[Frame], stmt: &Stmt, span: Span) -> Result<(), Error>;
+
+  /// 调用函数时，实参都已求值后调用。对于用户定义的函数，frames 的最后一帧为调用者
+  fn call(&mut self, frames: &[Frame], ident: &str, args: &[Value], span: Span) -> Result<(), Error>;
+
+  /// 用户定义的函数返回时调用，frames 的最后一帧仍为该函数
+  fn ret(&mut self, frames: &[Frame], ident: &str, ret_val: &RetVal) -> Result<(), Error>;
+}
+
+impl Interpreter {
+  /// 设置执行过程的观察者，为 None 时不维护调用栈
+  pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+    self.observer = observer;
+    self.frames.clear();
+  }
+
+  /// 进入新的一帧
+  pub(super) fn push_frame(&mut self, name: &str, args: Vec<Value>, call_span: Option<Span>) {
+    if self.observer.is_some() {
+      self.frames.push(Frame::new(name.to_string(), args, call_span));
+    }
+  }
+
+  pub(super) fn pop_frame(&mut self) {
+    if self.observer.is_some() {
+      self.frames.pop();
+    }
+  }
+
+  /// 进入一个 Block
+  pub(super) fn push_scope(&mut self) {
+    if let (Some(_), Some( frame )) = (&self.observer, self.frames.last_mut()) {
+      frame.scopes.push(vec![]);
+    }
+  }
+
+  pub(super) fn pop_scope(&mut self) {
+    if let (Some(_), Some( frame )) = (&self.observer, self.frames.last_mut()) {
+      frame.scopes.pop();
+    }
+  }
+
+  /// 在当前的 Block 中记录声明的常量、变量或函数参数
+  pub(super) fn declare(&mut self, ident: &str, rval: &Rc<RVal>) {
+    if let (Some(_), Some( frame )) = (&self.observer, self.frames.last_mut())
+      && let Some( scope ) = frame.scopes.last_mut() {
+      scope.push((ident.to_string(), rval.clone()));
+    }
+  }
+
+  pub(super) fn observe_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    match &mut self.observer {
+      Some( observer ) => observer.stmt(&self.frames, stmt, self.current_span),
+      None => Ok(()),
+    }
+  }
+
+  pub(super) fn observe_call(&mut self, ident: &str, args: &[Value], span: Span) -> Result<(), Error> {
+    match &mut self.observer {
+      Some( observer ) => observer.call(&self.frames, ident, args, span),
+      None => Ok(()),
+    }
+  }
+
+  pub(super) fn observe_return(&mut self, ident: &str, ret_val: &RetVal) -> Result<(), Error> {
+    match &mut self.observer {
+      Some( observer ) => observer.ret(&self.frames, ident, ret_val),
+      None => Ok(()),
+    }
+  }
+
+  /// 是否有执行过程的观察者
+  pub(super) fn observed(&self) -> bool {
+    self.observer.is_some()
+  }
+}
@@ -1,41 +1,83 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
 
-use crate::{ast::{measure::MeasureUnitValue, score::{Score, ScoreStmt, SetChannelInstrument, SetChannelTrack, SetTimeSignature}, track::TrackRVal, val::Value}, error::Error, interpret::ctr::RetVal};
+use crate::{ast::{measure::MeasureUnitValue, score::{AddText, Beat, Instrument, Score, TuningKind, ScoreStmt, SetChannelControl, SetChannelInstrument, SetChannelName, SetChannelTrack, SetTimeSignature, TrackPosition}, track::TrackValue, val::Value}, error::Error, interpret::ctr::RetVal, tuning::{scala, Tuning}};
 
-use midi_file::{core::GeneralMidi, MidiFile};
-use midi_file::core::{Channel, Clocks, DurationName, NoteNumber, Velocity};
-use midi_file::file::QuartersPerMinute;
-use midi_file::file::Track as MidiTrack;
+use midi_file::MidiFile;
 
 use super:: Interpreter;
+use super::abc::score_abc;
+use super::audio::render_wav;
+use super::instrument::{general_midi, SynthInstrument};
+use super::lilypond::score_lilypond;
+use super::musicxml::score_partwise;
+use super::notation::Notation;
+use super::source_map::SourceMap;
+use super::timeline::{ChannelTimeline, Note, ScoreEvent, Timeline, DEFAULT_TIME_SIGNATURE_DENOMINATOR, DEFAULT_TIME_SIGNATURE_NUMERATOR};
 
-/// 每一个小节4个四分音符算,每个四分音符所占tick默认为Divison(PPQ)=1024
-const MEASURE_TICKS: u32 = 4 * 1024;
+/// 每个四分音符所占 tick 的默认值，即 Divison(PPQ)=1024
+pub const DEFAULT_PPQ: u16 = 1024;
 
-/// 默认音符开启/关闭力度
-const DEFAULT_VELOCITY: Velocity = Velocity::new(72);
+/// PPQ 的取值范围，由 SMF 的 division 字段决定
+const MAX_PPQ: i32 = 16383;
 
-/// 默认是X/4拍,四分音符为单位
-const DEFAULT_TIME_SIGNATURE_DENOMINATOR: i32 = 4;
+/// 默认音符开启力度
+const DEFAULT_VELOCITY: u8 = 72;
 
-const DEFAULT_TIME_SIGNATURE_CLOCKS: Clocks = Clocks::Quarter;
+/// 音律的音级默认从中央 C 开始计算
+pub(super) const DEFAULT_TUNING_ROOT: i32 = 60;
+
+/// General MIDI 的打击乐 channel，不受音律影响
+pub(super) const PERCUSSION_CHANNEL: u8 = 9;
 
 impl Interpreter {
-  /// 翻译 Score 块最终生成 midi
+  /// 翻译 Score 块最终生成 midi，需要时同时生成乐谱或音频
   pub fn interpret_score(&mut self, score: &Score) -> Result<MidiFile, Error> {
+    let timeline = self.score_timeline(score)?;
+
+    if self.source_mapping {
+      self.source_map = Some(SourceMap::new(&timeline));
+    }
+
+    // 记谱输出使用未经音律处理的音符
+    if let Some(notation) = self.notation {
+      self.score_text = Some(match notation {
+        Notation::MusicXml => score_partwise(&timeline)?,
+        Notation::LilyPond => score_lilypond(&timeline)?,
+        Notation::Abc => score_abc(&timeline)?,
+      });
+    }
+
+    // 音频直接按音律计算音高
+    if let Some(wav_format) = self.wav_format {
+      self.wav = Some(render_wav(&timeline, self.soundfont.as_ref(), wav_format));
+    }
+
+    self.timeline_midi(&timeline)
+  }
+
+  /// 执行 Score 块，将各 channel 的 Track 和乐曲的设置计算为以绝对 tick 计时的 Timeline
+  pub(super) fn score_timeline(&mut self, score: &Score) -> Result<Timeline, Error> {
     let block = score.block.clone();
+    self.push_frame("@score", vec![], None);
     let res = self.interpret_block(block);
     if res.is_err() {
       return Err(res.err().unwrap());
     }
 
-    let mut midi_file = MidiFile::new();
-    let mut tracks: HashMap<u8, MidiTrack> = HashMap::new();
-    let mut deltas: HashMap<u8, u32> = HashMap::new();  // 继承上一次 SetChannelTrack 可能留下来的下一次开启音符的 delta
-    let mut meta_track = MidiTrack::default();
+    let mut timelines: BTreeMap<u8, ChannelTimeline> = BTreeMap::new();
+    let ppq = self.score_ppq(score)?;  // ticks per quarter note
     let mut time_sig_denominator = DEFAULT_TIME_SIGNATURE_DENOMINATOR;  // denominator of time signature, default 4
+    let mut time_sig_numerator = DEFAULT_TIME_SIGNATURE_NUMERATOR;  // numerator of time signature, default 4
+    let mut time_signature_ = None;
+    let mut score_events: Vec<(u32, ScoreEvent)> = vec![];  // 位于绝对 tick 的 marker、cue point、调号
+    let mut title_ = None;
+    let mut tuning_: Option<Tuning> = None;
+    let mut tempo_ = None;
 
-    for stmt in &score.channel_stmts {
+    for (stmt, span) in score.channel_stmts.iter().zip(&score.channel_spans) {
+      self.current_span = *span;
       match stmt {
         ScoreStmt::SetChannelInstrument(SetChannelInstrument{channel, instrument}) => {
           // 计算并检查 channel
@@ -55,39 +97,89 @@ impl Interpreter {
               "channel must between 0 and 15",
             )))
           };
-          let ch = Channel::new(ch_u8);
-          
-          // 计算并检查 instrument
-          let res = self.calc_expr(instrument);
-          if res.is_err() {
-            return Err(res.err().unwrap());
+
+          // 计算并检查 instrument，乐谱中定义的乐器优先于同名的 General MIDI 乐器
+          let timeline = timelines.entry(ch_u8).or_default();
+          let instrument = match instrument {
+            Instrument::Program( program ) => self.calc_expr(program)?,
+            Instrument::Name( name ) => RetVal::Value(Value::Str(name.clone())),
+          };
+          match instrument {
+            RetVal::Value(Value::Str( name )) if self.instruments.contains_key(&name) => {
+              let SynthInstrument{patch, program} = self.instruments[&name];
+              timeline.instrument = Some(program);
+              timeline.patch = Some(patch);
+            },
+            instrument => {
+              // 设置 midi 乐器，program change 写在各 channel 自己的 track 开头
+              timeline.instrument = Some(general_midi(instrument)?);
+              timeline.patch = None;
+            },
           }
-          let instr_i32 = match res.unwrap() {
+        },
+
+        ScoreStmt::SetChannelTrack(SetChannelTrack{channel, tracks: track_rvals, position}) => {
+          // 计算并检查 channel
+          let channel_i32 = match self.calc_expr(channel)? {
             RetVal::Value(Value::Int( int )) => int,
             val => return Err(Error::RuntimeError(format!(
               "expect i32, but found {val}",
             )))
           };
-          let instr_u8 = match u8::try_from(instr_i32).is_ok_and(|v| v<128) {
-            true => u8::try_from(instr_i32).unwrap(),
-            false => return Err(Error::RuntimeError(format!(
-              "channel must between 0 and 15",
-            )))
+          let channel_u8 = match u8::try_from(channel_i32).is_ok_and(|v| v<16) {
+            true => u8::try_from(channel_i32).unwrap(),
+            false => return Err(Error::RuntimeError(
+              "channel must between 0 and 15".to_string(),
+            ))
           };
-          let instr = GeneralMidi::from(instr_u8);
 
-          // 设置 midi 乐器
-          meta_track.set_general_midi(ch, instr)
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+          // 所有 Track 都从指定的绝对位置，或者该 channel 上一次 SetChannelTrack 结束的位置同时开始
+          // 每一个小节4个四分音符算，每个单元所占 tick 由拍号分母决定
+          let tick_step = 4 * ppq as u32 / time_sig_denominator as u32;
+          let start = match position {
+            Some( position ) => Some(self.calc_track_position(position, tick_step, tick_step * time_sig_numerator as u32)?),
+            None => None,
+          };
+          let timeline = timelines.entry(channel_u8).or_default();
+          let start = start.unwrap_or(timeline.end);
+          for track_rval in track_rvals {
+            let track_val = self.interpret_track_rval(track_rval)?;
+            let end = render_track(&track_val, start, tick_step, timeline);
+            timeline.end = timeline.end.max(end);
+          }
         },
 
-        ScoreStmt::SetChannelTrack(SetChannelTrack{channel, track: track_rval}) => {
-          // 计算并检查 channel
-          let res = self.calc_expr(channel);
-          if res.is_err() {
-            return Err(res.err().unwrap());
+        ScoreStmt::SetChannelName(SetChannelName{channel, name}) |
+        ScoreStmt::SetChannelInstrumentName(SetChannelName{channel, name}) => {
+          let channel_i32 = match self.calc_expr(channel)? {
+            RetVal::Value(Value::Int( int )) => int,
+            val => return Err(Error::RuntimeError(format!(
+              "expect i32, but found {val}",
+            )))
+          };
+          let channel_u8 = match u8::try_from(channel_i32).is_ok_and(|v| v<16) {
+            true => u8::try_from(channel_i32).unwrap(),
+            false => return Err(Error::RuntimeError(
+              "channel must between 0 and 15".to_string(),
+            ))
+          };
+          let name = match self.calc_expr(name)? {
+            RetVal::Value(Value::Str( s )) => s,
+            val => return Err(Error::RuntimeError(format!(
+              "expect string, but found {val}",
+            )))
+          };
+
+          let timeline = timelines.entry(channel_u8).or_default();
+          match stmt {
+            ScoreStmt::SetChannelName(_) => timeline.name = Some(name),
+            _ => timeline.instrument_name = Some(name),
           }
-          let channel_i32 = match res.unwrap() {
+        },
+
+        ScoreStmt::SetChannelVolume(SetChannelControl{channel, value}) |
+        ScoreStmt::SetChannelPan(SetChannelControl{channel, value}) => {
+          let channel_i32 = match self.calc_expr(channel)? {
             RetVal::Value(Value::Int( int )) => int,
             val => return Err(Error::RuntimeError(format!(
               "expect i32, but found {val}",
@@ -95,132 +187,125 @@ impl Interpreter {
           };
           let channel_u8 = match u8::try_from(channel_i32).is_ok_and(|v| v<16) {
             true => u8::try_from(channel_i32).unwrap(),
-            false => return Err(Error::RuntimeError(format!(
-              "channel must between 0 and 15",
+            false => return Err(Error::RuntimeError(
+              "channel must between 0 and 15".to_string(),
+            ))
+          };
+          let value_u8 = match self.calc_expr(value)? {
+            RetVal::Value(Value::Int( int )) if (0..128).contains(&int) => int as u8,
+            RetVal::Value(Value::Int( int )) => return Err(Error::RuntimeError(format!(
+              "volume and pan must between 0 and 127, but found {int}"
+            ))),
+            val => return Err(Error::RuntimeError(format!(
+              "expect i32, but found {val}",
             )))
           };
-          let channel = Channel::new(channel_u8);
-
-          // 翻译 Track 为 TrackValue
-          let track_val = match track_rval {
-            TrackRVal::Track( track ) => {
-              let res = self.interpret_track(track);
-              if res.is_err() {
-                return Err(res.err().unwrap());
-              }
-              res.unwrap()
-            },
-            TrackRVal::LVal( lval ) => {
-              match lval.rval.borrow().as_ref().clone().unwrap().get_value() {
-                Value::Track( v ) => v,
-                val => return Err(Error::RuntimeError(format!(
-                  "expect track, but found {val}",
-                )))
-              }
+
+          let timeline = timelines.entry(channel_u8).or_default();
+          match stmt {
+            ScoreStmt::SetChannelVolume(_) => timeline.volume = Some(value_u8),
+            _ => timeline.pan = Some(value_u8),
+          }
+        },
+
+        ScoreStmt::SetTitle( expr ) => {
+          title_ = match self.calc_expr(expr)? {
+            RetVal::Value(Value::Str( s )) => Some(s),
+            val => return Err(Error::RuntimeError(format!(
+              "expect string, but found {val}",
+            )))
+          };
+        },
+
+        ScoreStmt::AddMarker(AddText{text, position}) |
+        ScoreStmt::AddCuePoint(AddText{text, position}) => {
+          let text = match self.calc_expr(text)? {
+            RetVal::Value(Value::Str( s )) => s,
+            val => return Err(Error::RuntimeError(format!(
+              "expect string, but found {val}",
+            )))
+          };
+          let tick_step = 4 * ppq as u32 / time_sig_denominator as u32;
+          let tick = self.calc_track_position(position, tick_step, tick_step * time_sig_numerator as u32)?;
+          score_events.push(match stmt {
+            ScoreStmt::AddMarker(_) => (tick, ScoreEvent::Marker(text)),
+            _ => (tick, ScoreEvent::CuePoint(text)),
+          });
+        },
+
+        ScoreStmt::SetTuning( set_tuning ) => {
+          if tuning_.is_some() {
+            return Err(Error::RuntimeError(
+              "@tuning can only be set once".to_string()
+            ));
+          }
+          let root = match &set_tuning.root {
+            Some( expr ) => match self.calc_expr(expr)? {
+              RetVal::Value(Value::Int( int )) => int,
+              val => return Err(Error::RuntimeError(format!(
+                "expect i32, but found {val}",
+              )))
             },
-            TrackRVal::FuncCall( func_call ) => {
-              let res = self.call_func(func_call);
-              if res.is_err() {
-                return Err(res.err().unwrap());
-              };
-              match res.unwrap() {
-                RetVal::Void => return Err(Error::RuntimeError(format!(
-                  "expect track, but found void",
-                ))),
-                RetVal::Value( v ) => match v {
-                  Value::Track( v ) => v,
+            None => DEFAULT_TUNING_ROOT,
+          };
+          if !(0..128).contains(&root) {
+            return Err(Error::RuntimeError(format!(
+              "root of tuning must between 0 and 127, but found {root}"
+            )));
+          }
+
+          let kind = set_tuning.kind().ok_or(Error::InternalError(format!(
+            "tuning '{}' was not checked in semantic check", set_tuning.name
+          )))?;
+          tuning_ = Some(match kind {
+            TuningKind::Equal => Tuning::equal(root),
+            TuningKind::Just => Tuning::just(root),
+            TuningKind::Meantone => Tuning::meantone(root),
+            TuningKind::Cents => {
+              let mut scale = vec![];
+              for arg in &set_tuning.args {
+                match self.calc_expr(arg)? {
+                  RetVal::Value(Value::Int( int )) => scale.push(int as f64),
                   val => return Err(Error::RuntimeError(format!(
-                    "expect track, but found {val}",
+                    "expect i32, but found {val}",
                   )))
                 }
               }
+              Tuning::from_scale(&scale, root).map_err(Error::RuntimeError)?
             },
-          };
+            TuningKind::Scala => {
+              let path = match self.calc_expr(&set_tuning.args[0])? {
+                RetVal::Value(Value::Str( s )) => s,
+                val => return Err(Error::RuntimeError(format!(
+                  "expect string, but found {val}",
+                )))
+              };
+              // 相对路径相对源文件所在的目录
+              let file = match &self.source_dir {
+                Some( dir ) => dir.join(&path),
+                None => PathBuf::from(&path),
+              };
+              let text = read_to_string(&file).map_err(|e| Error::RuntimeError(format!(
+                "can not read scala file '{}': {e}", file.display()
+              )))?;
+              let scale = scala::parse(&text).map_err(|e| Error::RuntimeError(format!("{path}: {e}")))?;
+              Tuning::from_scale(&scale, root).map_err(|e| Error::RuntimeError(format!("{path}: {e}")))?
+            },
+          });
+        },
 
-          let track_ = tracks.get(&channel_u8).cloned();
-          let track_is_new = track_.is_none();
-          let mut track = match track_ {
-            None => MidiTrack::default(),
-            Some(t) => t.clone()
-          };
-          let mut delta = match track_is_new {
-            true => 0,
-            false => *deltas.get(&channel_u8).unwrap(),
-          };
-          let mut notes_to_off: Vec<(u32, u32)> = vec![];
-          let mut elapsed_ticks = 0;
-          let mut tick_step = MEASURE_TICKS / time_sig_denominator as u32;
-
-          // 模拟midi经过了tick_step个tick,在此期间可能有标记了延长的音符需要关闭
-          let elapse = |track: &mut MidiTrack, tick_step: u32, notes_to_off: &mut Vec<(u32, u32)>, elapsed_ticks: &mut u32, delta: &mut u32| -> Result<(), Error> {
-            notes_to_off.sort();  // 升序
-            let term = *elapsed_ticks + tick_step;
-            let mut count = 0;  // 记录需要删掉多少个 note_to_off 记录
-            for i in 0..notes_to_off.len() {
-              let (target_elapsed_ticks, note) = &notes_to_off[i];
-              if *target_elapsed_ticks > term {
-                break;
-              }
-              let delta = *target_elapsed_ticks - *elapsed_ticks;
-              track.push_note_off(
-                delta,
-                channel,
-                NoteNumber::new(*note as u8),
-                DEFAULT_VELOCITY
-              ).map_err(|e| Error::RuntimeError(e.to_string()))?;
-              count += 1;
-              *elapsed_ticks += delta;
-            }
-            notes_to_off.drain(0..count);
-            *delta += term - *elapsed_ticks;
-            *elapsed_ticks = term;
-            Ok(())
+        ScoreStmt::SetKeySignature( key_sig ) => {
+          let fifths = key_sig.fifths().ok_or(Error::InternalError(format!(
+            "key signature '{}' was not checked in semantic check", key_sig.tonic
+          )))?;
+          let tick = match &key_sig.position {
+            Some( position ) => {
+              let tick_step = 4 * ppq as u32 / time_sig_denominator as u32;
+              self.calc_track_position(position, tick_step, tick_step * time_sig_numerator as u32)?
+            },
+            None => 0,
           };
-
-          for phrase_val in &track_val.content {
-            for measure_val in &phrase_val.content {
-              for measure_unit in &measure_val.content {
-                match measure_unit {
-                  MeasureUnitValue::TimeDilation => tick_step /= 2,
-                  MeasureUnitValue::TimeCompression => tick_step *= 2,
-                  MeasureUnitValue::Rest => {
-                    elapse(&mut track, tick_step, &mut notes_to_off, &mut elapsed_ticks, &mut delta)?;
-                  },
-                  MeasureUnitValue::NoteValue( note ) => {
-                    let mut note_ticks = tick_step;
-                    if note.len.is_some() {
-                      note_ticks *= note.len.unwrap() as u32;
-                    }
-                    let target_elapsed_ticks = elapsed_ticks + note_ticks;
-
-                    for note in &note.notes {
-                      track.push_note_on(
-                        delta,
-                        channel,
-                        NoteNumber::new(*note as u8),
-                        DEFAULT_VELOCITY
-                      ).map_err(|e| Error::RuntimeError(e.to_string()))?;
-                      
-                      notes_to_off.push((target_elapsed_ticks, *note as u32));
-
-                      if delta > 0 {
-                        delta = 0;
-                      }
-                    }
-
-                    elapse(&mut track, tick_step, &mut notes_to_off, &mut elapsed_ticks, &mut delta)?;
-                  }
-                }
-              }
-            }
-          }
-          if track_is_new {
-            tracks.insert(channel_u8, track);
-            deltas.insert(channel_u8, delta);
-          } else {
-            *tracks.get_mut(&channel_u8).unwrap() = track;
-            *deltas.get_mut(&channel_u8).unwrap() = delta;
-          }
+          score_events.push((tick, ScoreEvent::KeySignature(fifths as i8, key_sig.mode)));
         },
 
         ScoreStmt::SetTimeSignature(SetTimeSignature{top_num, bottom_num}) => {
@@ -234,36 +319,26 @@ impl Interpreter {
           ).map_err(|_| Error::RuntimeError(format!(
             "numerator of time signature must between 0 and 255"
           )))?;
+          time_sig_numerator = numerator as i32;
 
-          let denominator = match match self.calc_expr(bottom_num)? {
-            RetVal::Value(Value::Int( int )) => {
-              time_sig_denominator = int;
-              int
-            },
+          let denominator = match self.calc_expr(bottom_num)? {
+            RetVal::Value(Value::Int( int )) => int,
             val => return Err(Error::RuntimeError(format!(
               "expect i32, but found {val}",
             )))
-          } {
-            1 => DurationName::Whole,
-            2 => DurationName::Half,
-            4 => DurationName::Quarter,
-            8 => DurationName::Eighth,
-            16 => DurationName::Sixteenth,
-            32 => DurationName::D32,
-            64 => DurationName::D64,
-            128 => DurationName::D128,
-            256 => DurationName::D256,
-            512 => DurationName::D512,
-            1024 => DurationName::D1024,
-            _ => return Err(Error::RuntimeError(format!(
-              "denominator of time signature must be one of 1,2,4,8,16,32,64,128,256,512,1024"
-            ))),
           };
-
-          meta_track.push_time_signature(0, numerator, denominator, DEFAULT_TIME_SIGNATURE_CLOCKS)
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+          time_sig_denominator = denominator;
+          if !(1..=1024).contains(&denominator) || !(denominator as u32).is_power_of_two() {
+            return Err(Error::RuntimeError(format!(
+              "denominator of time signature must be one of 1,2,4,8,16,32,64,128,256,512,1024"
+            )));
+          }
+          time_signature_ = Some((numerator, denominator));
         },
 
+        // 已在 score_ppq 中计算
+        ScoreStmt::SetPpq(_) => {},
+
         ScoreStmt::SetTempo( expr ) => {
           
           let tempo_u8 = u8::try_from(
@@ -276,21 +351,160 @@ impl Interpreter {
           ).map_err(|_| Error::RuntimeError(format!(
             "tempo must between 0 and 255"
           )))?;
-          let tempo = QuartersPerMinute::new(tempo_u8);
-
-          meta_track.push_tempo(0, tempo)
-            .map_err(|e| Error::RuntimeError(e.to_string()))?;
+          tempo_ = Some(tempo_u8);
         }
       }
     }
 
-    midi_file.push_track(meta_track)
-      .map_err(|e| Error::RuntimeError(e.to_string()))?;
-    for (_, track) in tracks {
-      midi_file.push_track(track)
-        .map_err(|e| Error::RuntimeError(e.to_string()))?;
+    self.pop_frame();
+
+    // 同一 tick 上保持乐谱中的先后顺序
+    score_events.sort_by_key(|(tick, _)| *tick);
+    Ok(Timeline {
+      ppq,
+      title: title_,
+      tempo: tempo_,
+      time_signature: time_signature_,
+      events: score_events,
+      tuning: tuning_,
+      channels: timelines,
+    })
+  }
+
+  /// 计算乐谱的 ppq，命令行设置的值优先于乐谱中的 @ppq。
+  /// @ppq 必须在放置任何 track 之前设置，并且先于其他设置计算，之前的 marker、cue point 和调号的位置也按最终的 ppq 计算
+  fn score_ppq(&mut self, score: &Score) -> Result<u16, Error> {
+    let mut ppq = self.ppq.unwrap_or(DEFAULT_PPQ);
+    let mut track_placed = false;
+    for (stmt, span) in score.channel_stmts.iter().zip(&score.channel_spans) {
+      self.current_span = *span;
+      match stmt {
+        ScoreStmt::SetChannelTrack(_) => track_placed = true,
+        ScoreStmt::SetPpq( expr ) => {
+          let ppq_i32 = match self.calc_expr(expr)? {
+            RetVal::Value(Value::Int( int )) => int,
+            val => return Err(Error::RuntimeError(format!(
+              "expect i32, but found {val}",
+            )))
+          };
+          if !(1..=MAX_PPQ).contains(&ppq_i32) {
+            return Err(Error::RuntimeError(format!(
+              "ppq must between 1 and {MAX_PPQ}"
+            )));
+          }
+          if track_placed {
+            return Err(Error::RuntimeError(
+              "@ppq must be set before any track".to_string()
+            ));
+          }
+          if self.ppq.is_none() {
+            ppq = ppq_i32 as u16;
+          }
+        },
+        _ => {},
+      }
+    }
+    Ok(ppq)
+  }
+
+  /// 计算 Track 的绝对位置所对应的 tick。
+  /// 一拍为拍号分母所决定的音符长度，一小节为拍号分子个拍。
+  fn calc_track_position(&mut self, position: &TrackPosition, beat_ticks: u32, bar_ticks: u32) -> Result<u32, Error> {
+    let (bar_offset, beat_) = match position {
+      TrackPosition::Bar(bar, beat_) => {
+        let bar = match self.calc_expr(bar)? {
+          RetVal::Value(Value::Int( int )) => int,
+          val => return Err(Error::RuntimeError(format!(
+            "expect i32, but found {val}",
+          )))
+        };
+        if bar < 1 {
+          return Err(Error::RuntimeError(format!(
+            "bar must be at least 1, but found {bar}"
+          )));
+        }
+        ((bar as u64 - 1) * bar_ticks as u64, beat_.as_ref())
+      },
+      TrackPosition::Beat( beat ) => (0, Some(beat)),
+    };
+
+    // 拍以 (分子, 分母) 的分数形式计算
+    let (num, den) = match beat_ {
+      None => (1, 1),
+      Some(Beat::Decimal(num, den)) => (*num, *den),
+      Some(Beat::Expr( expr )) => match self.calc_expr(expr)? {
+        RetVal::Value(Value::Int( int )) => (int as i64, 1),
+        val => return Err(Error::RuntimeError(format!(
+          "expect i32, but found {val}",
+        )))
+      },
+    };
+    if num < den {
+      return Err(Error::RuntimeError(
+        "beat must be at least 1".to_string()
+      ));
     }
+    let beat_offset = (num - den) as u128 * beat_ticks as u128 / den as u128;
 
-    Ok(midi_file)
+    u32::try_from(bar_offset as u128 + beat_offset).map_err(|_| Error::RuntimeError(
+      "track position is out of range".to_string()
+    ))
+  }
+}
+
+/// 从绝对 tick `start` 开始排布一个 TrackValue，返回其结束的绝对 tick。
+/// 小节内的每个声部都从小节开头开始，小节长度取最长的声部；
+/// 时值的变化 '<' '>' 以第一个声部为准延续到之后的小节。
+fn render_track(track_val: &TrackValue, start: u32, mut tick_step: u32, timeline: &mut ChannelTimeline) -> u32 {
+  let mut tick = start;
+  for phrase_val in &track_val.content {
+    for measure_val in &phrase_val.content {
+      let mut measure_end = tick;
+      let mut next_tick_step = tick_step;
+      for (i, voice) in measure_val.voices().into_iter().enumerate() {
+        let (voice_end, voice_tick_step) = render_voice(voice, tick, tick_step, timeline);
+        measure_end = measure_end.max(voice_end);
+        if i == 0 {
+          next_tick_step = voice_tick_step;
+        }
+      }
+      tick = measure_end;
+      tick_step = next_tick_step;
+    }
+  }
+  tick
+}
+
+/// 从绝对 tick `start` 开始排布小节的一个声部，返回声部结束的绝对 tick 以及结束时的 tick_step
+fn render_voice(voice: &[MeasureUnitValue], start: u32, mut tick_step: u32, timeline: &mut ChannelTimeline) -> (u32, u32) {
+  let mut tick = start;
+  for measure_unit in voice {
+    match measure_unit {
+      MeasureUnitValue::TimeDilation => tick_step /= 2,
+      MeasureUnitValue::TimeCompression => tick_step *= 2,
+      MeasureUnitValue::VoiceSeparator => {},
+      MeasureUnitValue::Rest => tick += tick_step,
+      MeasureUnitValue::NoteValue( note ) => {
+        let note_ticks = tick_step * note.len.unwrap_or(1) as u32;
+        // 力度为 0 的 note on 相当于 note off，故力度至少为 1
+        let velocity = match note.velocity {
+          Some( velocity ) => velocity.clamp(1, 127) as u8,
+          None => DEFAULT_VELOCITY,
+        };
+        if let Some(syllable) = &note.syllable {
+          timeline.lyrics.push((tick, syllable.clone()));
+        }
+        // 时值为 0 的音符不发声
+        if note_ticks > 0 {
+          let cents = note.cents.unwrap_or(0);
+          let span = note.span;
+          for note in &note.notes {
+            timeline.notes.push(Note{tick, duration: note_ticks, key: *note as u8, velocity, cents, span});
+          }
+        }
+        tick += tick_step;
+      }
+    }
   }
-}
\ No newline at end of file
+  (tick, tick_step)
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+use crate::ast::span::Span;
+
+use super::timeline::Timeline;
+
+/// 乐谱没有设置速度时 midi 缺省的每分钟四分音符数
+const DEFAULT_TEMPO: u8 = 120;
+
+/// 一个音符与产生它的源代码的对应关系
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+  /// 音符开始的绝对 tick
+  tick: u32,
+
+  /// 持续的 tick 数
+  duration: u32,
+
+  channel: u8,
+
+  key: u8,
+
+  span: Span,
+}
+
+/// 输出的各个音符到 yam 源文件位置的映射，编辑器可以据此在播放时高亮正在演奏的代码
+#[derive(Debug)]
+pub struct SourceMap {
+  /// 每个四分音符所占的 tick 数
+  ppq: u16,
+
+  /// 每分钟的四分音符数
+  tempo: u8,
+
+  /// 按 tick 排序的映射，同一 tick 上按 channel 排列
+  mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+  /// 收集 Timeline 中所有带有源文件位置的音符
+  pub(super) fn new(timeline: &Timeline) -> Self {
+    let mut mappings: Vec<Mapping> = timeline.channels.iter()
+      .flat_map(|(channel, channel_timeline)| channel_timeline.notes.iter()
+        .filter_map(|note| note.span.map(|span| Mapping{
+          tick: note.tick,
+          duration: note.duration,
+          channel: *channel,
+          key: note.key,
+          span,
+        })))
+      .collect();
+    mappings.sort_by_key(|mapping| (mapping.tick, mapping.channel));
+    Self {
+      ppq: timeline.ppq,
+      tempo: timeline.tempo.unwrap_or(DEFAULT_TEMPO),
+      mappings,
+    }
+  }
+
+  /// 以 JSON 输出映射，file 为源文件路径，source 为源文件内容，用于把字节偏移换算为从 1 开始的行号和列号
+  pub fn to_json(&self, file: &str, source: &str) -> String {
+    let lines = LineIndex::new(source);
+    let mut json = String::new();
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"file\": {},", json_string(file)).unwrap();
+    writeln!(json, "  \"ppq\": {},", self.ppq).unwrap();
+    writeln!(json, "  \"tempo\": {},", self.tempo).unwrap();
+    write!(json, "  \"mappings\": [").unwrap();
+    for (i, mapping) in self.mappings.iter().enumerate() {
+      let (line, column) = lines.position(mapping.span.start);
+      let (end_line, end_column) = lines.position(mapping.span.end);
+      if i > 0 {
+        write!(json, ",").unwrap();
+      }
+      write!(json,
+        "\n    {{\"tick\": {}, \"duration\": {}, \"channel\": {}, \"key\": {}, \"line\": {line}, \"column\": {column}, \"endLine\": {end_line}, \"endColumn\": {end_column}}}",
+        mapping.tick, mapping.duration, mapping.channel, mapping.key,
+      ).unwrap();
+    }
+    if !self.mappings.is_empty() {
+      write!(json, "\n  ").unwrap();
+    }
+    writeln!(json, "]").unwrap();
+    writeln!(json, "}}").unwrap();
+    json
+  }
+}
+
+/// 源文件中每一行开始的字节偏移
+pub(crate) struct LineIndex<'a> {
+  source: &'a str,
+  starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+  pub(crate) fn new(source: &'a str) -> Self {
+    let starts = std::iter::once(0)
+      .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+    Self { source, starts }
+  }
+
+  /// 字节偏移所在的行号和列号，都从 1 开始，列号按字符计
+  pub(crate) fn position(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.source.len());
+    let line = self.starts.partition_point(|start| *start <= offset) - 1;
+    let column = self.source[self.starts[line]..offset].chars().count();
+    (line + 1, column + 1)
+  }
+}
+
+/// 将字符串转换为 JSON 字符串字面量
+fn json_string(text: &str) -> String {
+  let mut json = String::from("\"");
+  for c in text.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+  json
+}
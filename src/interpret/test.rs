@@ -0,0 +1,41 @@
+use crate::ast::comp_unit::{CompUnit, TestDef};
+use crate::ast::span::Span;
+use crate::error::Error;
+
+use super::Interpreter;
+
+/// 一个测试的执行结果
+#[derive(Debug)]
+pub struct TestOutcome {
+  pub name: String,
+
+  /// 测试的名称在源文件中的位置
+  pub span: Span,
+
+  /// 测试失败时的错误，以及出错的语句在源文件中的位置
+  pub failure: Option<(Error, Span)>,
+}
+
+impl Interpreter {
+  /// 依次执行 comp_unit 中的所有测试，断言失败或执行出错时测试失败。
+  /// 每个测试执行前重新初始化全局常量和变量，测试之间互不影响
+  pub fn interpret_tests(&mut self, comp_unit: &CompUnit) -> Vec<TestOutcome> {
+    self.testing = true;
+    let mut outcomes = vec![];
+    for test_def in &comp_unit.tests {
+      self.current_span = test_def.span;
+      let failure = self.interpret_test(comp_unit, test_def).err().map(|err| (err, self.current_span));
+      outcomes.push(TestOutcome{name: test_def.name.clone(), span: test_def.span, failure});
+    }
+    self.testing = false;
+    outcomes
+  }
+
+  fn interpret_test(&mut self, comp_unit: &CompUnit, test_def: &TestDef) -> Result<(), Error> {
+    self.interpret_globals(comp_unit)?;
+    self.push_frame(&format!("test \"{}\"", test_def.name), vec![], None);
+    self.interpret_block(test_def.block.clone())?;
+    self.pop_frame();
+    Ok(())
+  }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+
+use crate::ast::{score::KeyMode, span::Span};
+use crate::synth::Patch;
+use crate::tuning::Tuning;
+
+/// 默认是X/4拍,四分音符为单位
+pub(super) const DEFAULT_TIME_SIGNATURE_DENOMINATOR: i32 = 4;
+
+/// 默认是4/X拍,每小节4拍
+pub(super) const DEFAULT_TIME_SIGNATURE_NUMERATOR: i32 = 4;
+
+/// 以绝对 tick 计时的一个音符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Note {
+  /// 开始的绝对 tick
+  pub(super) tick: u32,
+
+  /// 持续的 tick 数，总是大于 0
+  pub(super) duration: u32,
+
+  pub(super) key: u8,
+
+  /// 力度，1 到 127
+  pub(super) velocity: u8,
+
+  /// 音高偏移的音分
+  pub(super) cents: i32,
+
+  /// 产生该音符的音符字面量或函数调用在源文件中的位置
+  pub(super) span: Option<Span>,
+}
+
+/// 位于乐曲绝对 tick 上、与 channel 无关的事件
+#[derive(Debug, Clone)]
+pub(super) enum ScoreEvent {
+  /// 排练记号
+  Marker(String),
+
+  /// 提示点
+  CuePoint(String),
+
+  /// 调号，fifths 为升号(正)或降号(负)的个数
+  KeySignature(i8, KeyMode),
+}
+
+/// 一个 channel 的所有音符及其设置
+#[derive(Debug, Default)]
+pub(super) struct ChannelTimeline {
+  /// 按排布的先后顺序保存的音符
+  pub(super) notes: Vec<Note>,
+
+  /// 该 channel 当前内容结束的绝对 tick，下一次 SetChannelTrack 从这里开始
+  pub(super) end: u32,
+
+  /// 该 channel 的 General MIDI 乐器，即 SMF 中 program change 的 program，从 0 开始。
+  /// midi、记谱和音频的输出都按这一编号选择乐器
+  pub(super) instrument: Option<u8>,
+
+  /// 该 channel 的 track 名称
+  pub(super) name: Option<String>,
+
+  /// 该 channel 显示的乐器名称
+  pub(super) instrument_name: Option<String>,
+
+  /// 附在音符上的歌词音节及其绝对 tick
+  pub(super) lyrics: Vec<(u32, String)>,
+
+  /// 该 channel 的音量(CC 7)
+  pub(super) volume: Option<u8>,
+
+  /// 该 channel 的声像(CC 10)
+  pub(super) pan: Option<u8>,
+
+  /// 该 channel 绑定的乐谱中定义的乐器的音色，渲染音频时代替 General MIDI 乐器
+  pub(super) patch: Option<Patch>,
+}
+
+/// 由 Score 计算得到、与输出格式无关的中间表示，所有内容都以绝对 tick 计时。
+/// midi、音频和乐谱都由它生成，音律也只记录在这里，由各输出格式自行实现
+#[derive(Debug)]
+pub(super) struct Timeline {
+  /// 每个四分音符所占的 tick 数
+  pub(super) ppq: u16,
+
+  /// 乐曲名称
+  pub(super) title: Option<String>,
+
+  /// 每分钟的四分音符数
+  pub(super) tempo: Option<u8>,
+
+  /// 拍号 (分子, 分母)
+  pub(super) time_signature: Option<(u8, i32)>,
+
+  /// 按 tick 排序的排练记号、提示点和调号，同一 tick 上保持乐谱中的先后顺序
+  pub(super) events: Vec<(u32, ScoreEvent)>,
+
+  pub(super) tuning: Option<Tuning>,
+
+  /// 按 channel 编号排列的各 channel 的内容
+  pub(super) channels: BTreeMap<u8, ChannelTimeline>,
+}
+
+impl Timeline {
+  /// 拍号的分子和分母，没有设置时为 4/4
+  pub(super) fn time_signature(&self) -> (i32, i32) {
+    match self.time_signature {
+      Some((numerator, denominator)) => (numerator as i32, denominator),
+      None => (DEFAULT_TIME_SIGNATURE_NUMERATOR, DEFAULT_TIME_SIGNATURE_DENOMINATOR),
+    }
+  }
+
+  /// 调号的绝对 tick、升降号数以及调式，按 tick 排序
+  pub(super) fn key_signatures(&self) -> Vec<(u32, i8, KeyMode)> {
+    self.events.iter()
+      .filter_map(|(tick, event)| match event {
+        ScoreEvent::KeySignature(fifths, mode) => Some((*tick, *fifths, *mode)),
+        _ => None,
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::error::Error;
+use crate::tuning::Tuning;
+
+use super::midi::{mix_controls, program_change, raw_event, MidiChannel};
+use super::score::PERCUSSION_CHANNEL;
+use super::timeline::Timeline;
+
+/// 弯音范围(半音)，在 channel 第一次弯音前通过 RPN 0 设置
+const PITCH_BEND_RANGE: u8 = 2;
+
+/// 弯音的中间值，即不弯音
+const PITCH_BEND_CENTER: i32 = 8192;
+
+/// 一个输出 channel 的状态
+#[derive(Debug, Default)]
+struct BendChannel {
+  /// 当前的弯音值
+  bend: i32,
+
+  /// 正在发声的音符个数
+  sounding: usize,
+
+  /// 最近一次在该 channel 上发声的源 channel，决定了它当前的乐器
+  owner: Option<u8>,
+
+  /// 最后一个音符关闭的 tick，用于选择最久未使用的 channel
+  released: u32,
+
+  /// 是否已经设置过弯音范围
+  ready: bool,
+}
+
+/// 用弯音实现音律和音符的音分偏移(打击乐 channel 除外)。
+/// 弯音作用于整个 channel，因此音高偏移不同的同时发声的音符需要分配到不同的 channel 上：
+/// 与 MPE 类似，每个音符优先使用所属的 channel，被占用时轮换到乐谱中没有使用的空闲 channel，
+/// 并在开启音符前发送所需的 program change 和弯音。
+pub(super) fn apply_pitch_bend(timeline: &Timeline, timelines: &mut BTreeMap<u8, MidiChannel>, tuning: &Tuning) -> Result<(), Error> {
+  let tuned = |(channel_u8, _): &(&u8, &MidiChannel)| **channel_u8 != PERCUSSION_CHANNEL;
+  let has_cents = timelines.iter()
+    .filter(tuned)
+    .any(|(_, timeline)| timeline.events.iter().any(|e| e.cents != 0));
+  if tuning.is_equal() && !has_cents {
+    return Ok(());
+  }
+
+  let spare: Vec<u8> = (0..16)
+    .filter(|channel_u8| *channel_u8 != PERCUSSION_CHANNEL && !timelines.contains_key(channel_u8))
+    .collect();
+  let mut channels: HashMap<u8, BendChannel> = HashMap::new();
+  for channel_u8 in timelines.keys().copied().chain(spare.iter().copied()) {
+    channels.insert(channel_u8, BendChannel{
+      bend: PITCH_BEND_CENTER,
+      owner: timelines.contains_key(&channel_u8).then_some(channel_u8),
+      ..Default::default()
+    });
+  }
+
+  // 所有 channel 的音符事件按时间统一排序，同一 tick 上先关闭音符
+  let mut order = vec![];
+  for (channel_u8, timeline) in timelines.iter().filter(tuned) {
+    timeline.events.iter().enumerate()
+      .for_each(|(i, e)| order.push((e.tick, e.on, *channel_u8, i)));
+  }
+  order.sort();
+
+  // 正在发声的音符所在的输出 channel 和实际的音符，按开启的先后排列
+  let mut sounding: HashMap<(u8, u8), VecDeque<(u8, u8)>> = HashMap::new();
+  for (tick, on, source, i) in order {
+    let event = timelines[&source].events[i];
+
+    if !on {
+      let (channel_u8, key) = sounding.get_mut(&(source, event.note))
+        .and_then(|queue| queue.pop_front())
+        .ok_or(Error::InternalError("note off without note on".to_string()))?;
+      let state = channels.get_mut(&channel_u8).unwrap();
+      state.sounding -= 1;
+      if state.sounding == 0 {
+        state.released = tick;
+      }
+      let e = &mut timelines.get_mut(&source).unwrap().events[i];
+      e.note = key;
+      e.channel = Some(channel_u8);
+      continue;
+    }
+
+    // 实际发声的音符取最接近的半音，剩余部分用弯音实现
+    let pitch = tuning.pitch(event.note as i32) + event.cents as f64;
+    // 超出 midi 音域时弯音也无法补偿，报错而不是截断
+    let key = (pitch / 100.0).round();
+    if !(0.0..=127.0).contains(&key) {
+      return Err(Error::RuntimeError(format!(
+        "note {} with {} cents is out of the midi range after tuning", event.note, event.cents
+      )));
+    }
+    let bend_cents = pitch - key * 100.0;
+    let bend = PITCH_BEND_CENTER + (bend_cents / (PITCH_BEND_RANGE as f64 * 100.0) * 8192.0).round() as i32;
+    let key = key as u8;
+
+    // 优先使用乐器和弯音都相同的 channel，其次是空闲的所属 channel，最后是最久未使用的空闲 channel
+    let candidates: Vec<u8> = std::iter::once(source).chain(spare.iter().copied()).collect();
+    let shared = candidates.iter().copied()
+      .find(|c| channels[c].owner == Some(source) && channels[c].bend == bend);
+    let idle = || match channels[&source].sounding {
+      0 => Some(source),
+      _ => spare.iter().copied()
+        .filter(|c| channels[c].sounding == 0)
+        .min_by_key(|c| channels[c].released),
+    };
+    let channel_u8 = shared.or_else(idle).ok_or(Error::RuntimeError(format!(
+      "too many simultaneous notes with different pitch bends on channel {source}, no free channel left"
+    )))?;
+
+    let instrument = timeline.channels[&source].instrument;
+    let state = channels.get_mut(&channel_u8).unwrap();
+    let mut controls = vec![];
+    if state.owner != Some(source) {
+      controls.push(program_change(channel_u8, instrument.unwrap_or(0))?);
+      controls.extend(mix_controls(&timeline.channels[&source], channel_u8)?);
+      state.owner = Some(source);
+    }
+    if state.bend != bend {
+      if !state.ready {
+        // RPN 0: 弯音范围
+        for (control, value) in [(101, 0), (100, 0), (6, PITCH_BEND_RANGE), (38, 0), (101, 127), (100, 127)] {
+          controls.push(raw_event(&[0xb0 | channel_u8, control, value])?);
+        }
+        state.ready = true;
+      }
+      controls.push(raw_event(&[0xe0 | channel_u8, (bend & 0x7f) as u8, (bend >> 7) as u8])?);
+      state.bend = bend;
+    }
+    state.sounding += 1;
+    sounding.entry((source, event.note)).or_default().push_back((channel_u8, key));
+
+    let channel = timelines.get_mut(&source).unwrap();
+    channel.controls.extend(controls.into_iter().map(|control| (tick, control)));
+    let e = &mut channel.events[i];
+    e.note = key;
+    e.channel = Some(channel_u8);
+  }
+  Ok(())
+}
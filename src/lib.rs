@@ -3,7 +3,27 @@ mod syntactic;
 mod semantic;
 mod interpret;
 mod error;
+mod gm;
+mod builtin;
+mod tuning;
+mod import;
+mod synth;
+mod format;
+mod lsp;
+mod repl;
+mod testing;
+mod debug;
 
 pub use syntactic::Analyzer as SyntacticAnalyzer;
 pub use semantic::Analyzer as SemanticAnalyzer;
-pub use interpret::Interpreter as Interpreter;
\ No newline at end of file
+pub use interpret::Interpreter as Interpreter;
+pub use interpret::notation::Notation as Notation;
+pub use interpret::source_map::SourceMap as SourceMap;
+pub use import::Importer as Importer;
+pub use format::Formatter as Formatter;
+pub use lsp::LanguageServer as LanguageServer;
+pub use repl::Repl as Repl;
+pub use testing::TestRunner as TestRunner;
+pub use debug::Debugger as Debugger;
+pub use synth::wav::{WavFormat, SampleFormat};
+pub use synth::soundfont::SoundFont;
\ No newline at end of file
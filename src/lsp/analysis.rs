@@ -0,0 +1,599 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::block::{Block, BlockId};
+use crate::ast::comp_unit::CompUnit;
+use crate::ast::drum::DrumGrid;
+use crate::ast::expr::{Expr, PrimaryExpr};
+use crate::ast::func::{FuncCall, FuncDef, FuncType};
+use crate::ast::measure::{Measure, MeasureRVal, MeasureUnit};
+use crate::ast::note::Note;
+use crate::ast::phrase::{Phrase, PhraseRVal};
+use crate::ast::score::{AddText, Beat, Instrument, ScoreStmt, SetChannelControl, SetChannelInstrument, SetChannelName, SetChannelTrack, SetKeySignature, SetTimeSignature, SetTuning, TrackPosition};
+use crate::ast::span::Span;
+use crate::ast::stmt::{AsgnRVal, Assert, Stmt};
+use crate::ast::track::{Track, TrackRVal};
+use crate::ast::val::{LVal, Value};
+use crate::builtin::{Builtin, BUILTINS};
+use crate::gm;
+use crate::semantic::Analyzer as SemanticAnalyzer;
+use crate::semantic::symbol::Symbol;
+use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+/// 关键字，作为补全的候选项
+const KEYWORDS: [&str; 17] = [
+  "int", "note", "measure", "phrase", "track", "string", "void", "const",
+  "if", "else", "while", "break", "continue", "return", "instrument", "test", "assert",
+];
+
+/// 补全候选项的种类，取值与 LSP 的 CompletionItemKind 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+  Function = 3,
+  Variable = 6,
+  Keyword = 14,
+  Constant = 21,
+}
+
+/// 一个补全候选项
+#[derive(Debug)]
+pub struct Completion {
+  pub label: String,
+  pub kind: CompletionKind,
+  pub detail: String,
+}
+
+/// 标识符的一次出现所指向的对象
+#[derive(Debug)]
+enum Target {
+  /// 源文件中定义的常量、变量、参数、函数或乐器，值为定义的标识
+  Symbol(usize),
+
+  /// 内置函数、General MIDI 名称等没有定义位置的符号，值为悬停时显示的说明
+  Builtin(String),
+}
+
+/// 标识符在源文件中的一次出现
+#[derive(Debug)]
+struct Occurrence {
+  span: Span,
+  target: Target,
+
+  /// 是否为定义处
+  declaration: bool,
+}
+
+/// 源文件中定义的符号
+#[derive(Debug)]
+struct Definition {
+  span: Span,
+
+  /// 悬停时显示的说明，变量为其类型，函数为其签名
+  hover: String,
+}
+
+/// 一个 Block 的作用域，符号取自语义检查得到的 BlockScope
+struct Scope {
+  span: Span,
+  parent: Option<BlockId>,
+  symbols: Vec<(String, Symbol)>,
+}
+
+/// 一个源文件的分析结果：语法和语义检查的错误、标识符的定义与引用、各个作用域中的符号
+pub struct Analysis {
+  source: String,
+
+  /// 每一行开始的字节偏移
+  line_starts: Vec<usize>,
+
+  /// 语法分析或语义检查遇到的第一个错误及其位置
+  pub diagnostic: Option<(Span, String)>,
+
+  occurrences: Vec<Occurrence>,
+  definitions: HashMap<usize, Definition>,
+  scopes: HashMap<BlockId, Scope>,
+  global_block_id: Option<BlockId>,
+}
+
+impl Analysis {
+  /// 分析源文件。语义检查出错时，出错之前已经绑定的标识符仍然可以跳转和查找引用
+  pub fn new(parser: &SyntacticAnalyzer, source: String) -> Self {
+    let line_starts = std::iter::once(0)
+      .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+    let mut analysis = Analysis {
+      source,
+      line_starts,
+      diagnostic: None,
+      occurrences: vec![],
+      definitions: HashMap::new(),
+      scopes: HashMap::new(),
+      global_block_id: None,
+    };
+
+    let comp_unit = match parser.parse_with_span(&analysis.source) {
+      Ok( comp_unit ) => comp_unit,
+      Err( (err, span) ) => {
+        analysis.diagnostic = Some((span, err.to_string()));
+        return analysis;
+      },
+    };
+    let mut semantic_analyzer = SemanticAnalyzer::new();
+    if let Err( err ) = semantic_analyzer.check(&comp_unit).and_then(|_| semantic_analyzer.tests_check(&comp_unit)) {
+      analysis.diagnostic = Some((semantic_analyzer.get_current_span(), err.to_string()));
+    }
+
+    let mut indexer = Indexer {
+      analysis: &mut analysis,
+      semantic_analyzer: &mut semantic_analyzer,
+      instruments: HashMap::new(),
+    };
+    indexer.comp_unit(&comp_unit);
+    analysis
+  }
+
+  /// 字节偏移对应的 LSP 位置：从 0 开始的行号和以 UTF-16 编码单元计的列号
+  pub fn position(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.source.len());
+    let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+    let character = self.source[self.line_starts[line]..offset].encode_utf16().count();
+    (line, character)
+  }
+
+  /// LSP 位置对应的字节偏移，超出行尾时为行尾
+  pub fn offset(&self, line: usize, character: usize) -> usize {
+    let Some( start ) = self.line_starts.get(line) else { return self.source.len() };
+    let text = &self.source[*start..];
+    let text = &text[..text.find('\n').unwrap_or(text.len())];
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+      if units >= character {
+        return start + i;
+      }
+      units += c.len_utf16();
+    }
+    start + text.len()
+  }
+
+  /// 位于 offset 处的标识符
+  fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+    self.occurrences.iter().find(|occurrence| occurrence.span.start <= offset && offset <= occurrence.span.end)
+  }
+
+  /// 位于 offset 处的标识符所指向符号的定义位置
+  pub fn definition(&self, offset: usize) -> Option<Span> {
+    match self.occurrence_at(offset)?.target {
+      Target::Symbol( key ) => self.definitions.get(&key).map(|definition| definition.span),
+      Target::Builtin(_) => None,
+    }
+  }
+
+  /// 位于 offset 处的标识符所指向符号的所有出现位置，按在源文件中的顺序排列
+  pub fn references(&self, offset: usize, include_declaration: bool) -> Vec<Span> {
+    let Some(Occurrence{target: Target::Symbol( key ), ..}) = self.occurrence_at(offset) else { return vec![] };
+    let mut spans: Vec<_> = self.occurrences.iter()
+      .filter(|occurrence| matches!(occurrence.target, Target::Symbol( k ) if k == *key))
+      .filter(|occurrence| include_declaration || !occurrence.declaration)
+      .map(|occurrence| occurrence.span)
+      .collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+  }
+
+  /// 位于 offset 处的标识符的位置及其说明
+  pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+    let occurrence = self.occurrence_at(offset)?;
+    let hover = match &occurrence.target {
+      Target::Symbol( key ) => self.definitions.get(key)?.hover.clone(),
+      Target::Builtin( hover ) => hover.clone(),
+    };
+    Some((occurrence.span, hover))
+  }
+
+  /// offset 处可以使用的标识符：从包含它的最内层 Block 开始逐层向外，在它之前定义的符号，
+  /// 内层的符号遮蔽外层的同名符号；之后是内置函数、General MIDI 打击乐名称和关键字
+  pub fn completions(&self, offset: usize) -> Vec<Completion> {
+    let mut completions = vec![];
+    let mut seen = HashSet::new();
+
+    let mut block_id_ = self.scopes.iter()
+      .filter(|(_, scope)| scope.span.start < offset && offset < scope.span.end)
+      .min_by_key(|(_, scope)| scope.span.end - scope.span.start)
+      .map(|(block_id, _)| *block_id)
+      .or(self.global_block_id);
+    while let Some( scope ) = block_id_.and_then(|block_id| self.scopes.get(&block_id)) {
+      for (ident, symbol) in &scope.symbols {
+        let key = match &symbol.func_def {
+          Some( func_def ) => key(func_def),
+          None => key(symbol.rval.as_ref().unwrap()),
+        };
+        let defined = self.definitions.get(&key).is_none_or(|definition| definition.span.end <= offset);
+        if !defined || !seen.insert(ident.clone()) {
+          continue;
+        }
+        let detail = self.definitions.get(&key).map(|definition| definition.hover.clone()).unwrap_or_default();
+        let kind = match (&symbol.func_def, symbol.const_) {
+          (Some(_), _) => CompletionKind::Function,
+          (None, true) => CompletionKind::Constant,
+          (None, false) => CompletionKind::Variable,
+        };
+        completions.push(Completion{label: ident.clone(), kind, detail});
+      }
+      block_id_ = scope.parent;
+    }
+
+    for builtin in BUILTINS {
+      if seen.insert(builtin.ident().to_string()) {
+        completions.push(Completion{
+          label: builtin.ident().to_string(),
+          kind: CompletionKind::Function,
+          detail: builtin_signature(builtin),
+        });
+      }
+    }
+    for (name, note) in gm::PERCUSSION_ALIASES.iter().chain(gm::PERCUSSION.iter()) {
+      let ident = gm::to_ident(name);
+      if seen.insert(ident.clone()) {
+        completions.push(Completion{label: ident, kind: CompletionKind::Constant, detail: format!("int = {note}")});
+      }
+    }
+    for keyword in KEYWORDS {
+      completions.push(Completion{label: keyword.to_string(), kind: CompletionKind::Keyword, detail: String::new()});
+    }
+    completions
+  }
+}
+
+/// 符号的唯一标识：常量、变量和参数为其 RVal 的地址，函数为其 FuncDef 的地址
+fn key<T>(rc: &Rc<T>) -> usize {
+  Rc::as_ptr(rc) as usize
+}
+
+/// 函数的签名，如 `measure riff(int root, note n)`
+fn signature(func_def: &FuncDef) -> String {
+  let params: Vec<_> = func_def.func_fparams.iter()
+    .map(|param| format!("{} {}", param.rval.get_btype(), param.ident))
+    .collect();
+  format!("{} {}({})", func_type(&func_def.func_type), func_def.ident, params.join(", "))
+}
+
+/// 内置函数的签名
+fn builtin_signature(builtin: Builtin) -> String {
+  let params = match builtin {
+    Builtin::Euclid => "int hits, int steps, int rotation, note note",
+    Builtin::Poly => "measure, measure, ...",
+    Builtin::Print | Builtin::Debug => "value, ...",
+    Builtin::Len => "value",
+  };
+  format!("{} {}({params})", func_type(&builtin.func_type()), builtin.ident())
+}
+
+fn func_type(func_type: &FuncType) -> String {
+  match func_type {
+    FuncType::BType( btype ) => btype.to_string(),
+    FuncType::Void => "void".to_string(),
+  }
+}
+
+/// 遍历语义检查之后的 AST，记录所有标识符的定义和引用，以及各个 Block 的作用域
+struct Indexer<'a> {
+  analysis: &'a mut Analysis,
+  semantic_analyzer: &'a mut SemanticAnalyzer,
+
+  /// 乐器名称到其定义的标识
+  instruments: HashMap<String, usize>,
+}
+
+impl Indexer<'_> {
+  /// 记录一个定义
+  fn define(&mut self, span: Span, key: usize, hover: String) {
+    self.analysis.definitions.insert(key, Definition{span, hover});
+    self.analysis.occurrences.push(Occurrence{span, target: Target::Symbol(key), declaration: true});
+  }
+
+  /// 记录一次引用
+  fn refer(&mut self, span: Span, target: Target) {
+    self.analysis.occurrences.push(Occurrence{span, target, declaration: false});
+  }
+
+  fn comp_unit(&mut self, comp_unit: &CompUnit) {
+    self.analysis.global_block_id = Some(comp_unit.block.get_id());
+
+    // 乐器可以在定义之前被 @score 中的设置引用，先记录所有乐器
+    for instrument_def in &comp_unit.instruments {
+      let key = instrument_def as *const _ as usize;
+      self.instruments.insert(instrument_def.ident.clone(), key);
+      self.define(instrument_def.span, key, format!("instrument {}", instrument_def.ident));
+    }
+    self.block(&comp_unit.block);
+    for instrument_def in &comp_unit.instruments {
+      for param in &instrument_def.params {
+        self.expr(&param.value);
+      }
+    }
+    for test_def in &comp_unit.tests {
+      self.block(&test_def.block);
+    }
+
+    self.block(&comp_unit.score.block);
+    for stmt in &comp_unit.score.channel_stmts {
+      self.score_stmt(stmt);
+    }
+  }
+
+  fn block(&mut self, block: &Rc<Block>) {
+    if let Ok( scope ) = self.semantic_analyzer.get_scope_by_id(&block.get_id()) {
+      self.analysis.scopes.insert(block.get_id(), Scope{
+        span: block.span,
+        parent: block.get_parent_id(),
+        symbols: scope.symbols(),
+      });
+    }
+    for stmt in &block.stmts {
+      self.stmt(stmt);
+    }
+  }
+
+  fn stmt(&mut self, stmt: &Stmt) {
+    match stmt {
+      Stmt::FuncDef( func_def ) => {
+        self.define(func_def.span, key(func_def), signature(func_def));
+        for param in &func_def.func_fparams {
+          self.define(param.span, key(&param.rval), format!("{} {}", param.rval.get_btype(), param.ident));
+        }
+        self.block(&func_def.block);
+      },
+      Stmt::ConstDecl( const_decl ) => {
+        for (const_def, rval) in const_decl.const_defs.iter().zip(&const_decl.rvals) {
+          self.define(const_def.span, key(rval), format!("const {} {}", const_decl.btype, const_def.ident));
+          self.asgn_rval(&const_def.rval);
+        }
+      },
+      Stmt::VarDecl( var_decl ) => {
+        for (var_def, rval) in var_decl.var_defs.iter().zip(&var_decl.rvals) {
+          self.define(var_def.span, key(rval), format!("{} {}", var_decl.btype, var_def.ident));
+          if let Some( asgn_rval ) = &var_def.rval_ {
+            self.asgn_rval(asgn_rval);
+          }
+        }
+      },
+      Stmt::Asgn( asgn ) => {
+        self.lval(&asgn.lval);
+        self.asgn_rval(&asgn.rval);
+      },
+      Stmt::Block( block ) => self.block(block),
+      Stmt::IfElse( ifelse ) => {
+        self.expr(&ifelse.cond);
+        self.stmt(&ifelse.if_);
+        if let Some( else_ ) = &ifelse.else_ {
+          self.stmt(else_);
+        }
+      },
+      Stmt::While( while_ ) => {
+        self.expr(&while_.cond);
+        self.stmt(&while_.body);
+      },
+      Stmt::Expr(Some( expr )) | Stmt::Return(Some( expr )) | Stmt::Assert(Assert{cond: expr, ..}) => self.expr(expr),
+      Stmt::Expr(None) | Stmt::Return(None) | Stmt::Break | Stmt::Continue => (),
+    }
+  }
+
+  fn score_stmt(&mut self, stmt: &ScoreStmt) {
+    match stmt {
+      ScoreStmt::SetChannelTrack( SetChannelTrack{channel, tracks, position} ) => {
+        self.expr(channel);
+        for track in tracks {
+          self.track_rval(track);
+        }
+        if let Some( position ) = position {
+          self.track_position(position);
+        }
+      },
+      ScoreStmt::SetChannelInstrument( SetChannelInstrument{channel, instrument} ) => {
+        self.expr(channel);
+        if let Instrument::Program( program ) = instrument {
+          self.expr(program);
+        }
+      },
+      ScoreStmt::SetTimeSignature( SetTimeSignature{top_num, bottom_num} ) => {
+        self.expr(top_num);
+        self.expr(bottom_num);
+      },
+      ScoreStmt::SetChannelName( SetChannelName{channel, name} ) |
+      ScoreStmt::SetChannelInstrumentName( SetChannelName{channel, name} ) => {
+        self.expr(channel);
+        self.expr(name);
+      },
+      ScoreStmt::SetChannelVolume( SetChannelControl{channel, value} ) |
+      ScoreStmt::SetChannelPan( SetChannelControl{channel, value} ) => {
+        self.expr(channel);
+        self.expr(value);
+      },
+      ScoreStmt::SetTempo( expr ) | ScoreStmt::SetPpq( expr ) | ScoreStmt::SetTitle( expr ) => self.expr(expr),
+      ScoreStmt::AddMarker( AddText{text, position} ) | ScoreStmt::AddCuePoint( AddText{text, position} ) => {
+        self.expr(text);
+        self.track_position(position);
+      },
+      ScoreStmt::SetKeySignature( SetKeySignature{position, ..} ) => {
+        if let Some( position ) = position {
+          self.track_position(position);
+        }
+      },
+      ScoreStmt::SetTuning( SetTuning{args, root, ..} ) => {
+        for arg in args.iter().chain(root) {
+          self.expr(arg);
+        }
+      },
+    }
+  }
+
+  fn track_position(&mut self, position: &TrackPosition) {
+    let (bar_, beat_) = match position {
+      TrackPosition::Bar( bar, beat_ ) => (Some(bar), beat_.as_ref()),
+      TrackPosition::Beat( beat ) => (None, Some(beat)),
+    };
+    if let Some( bar ) = bar_ {
+      self.expr(bar);
+    }
+    if let Some(Beat::Expr( expr )) = beat_ {
+      self.expr(expr);
+    }
+  }
+
+  fn asgn_rval(&mut self, asgn_rval: &AsgnRVal) {
+    match asgn_rval {
+      AsgnRVal::Expr( expr ) => self.expr(expr),
+      AsgnRVal::Note( note ) => self.note(note),
+      AsgnRVal::Measure( measure ) => self.measure(measure),
+      AsgnRVal::Phrase( phrase ) => self.phrase(phrase),
+      AsgnRVal::Track( track ) => self.track(track),
+      AsgnRVal::DrumGrid( drum_grid ) => self.drum_grid(drum_grid),
+    }
+  }
+
+  fn note(&mut self, note: &Note) {
+    for expr in note.notes.iter().chain(&note.len).chain(&note.cents) {
+      self.expr(expr);
+    }
+  }
+
+  fn measure(&mut self, measure: &Measure) {
+    for unit in &measure.content {
+      if let MeasureUnit::Note( note ) = unit {
+        self.note(note);
+      }
+    }
+  }
+
+  fn measure_rval(&mut self, measure_rval: &MeasureRVal) {
+    match measure_rval {
+      MeasureRVal::Measure( measure ) => self.measure(measure),
+      MeasureRVal::LVal( lval ) => self.lval(lval),
+      MeasureRVal::FuncCall( func_call ) => self.func_call(func_call),
+    }
+  }
+
+  fn phrase(&mut self, phrase: &Phrase) {
+    for measure_rval in &phrase.content {
+      self.measure_rval(measure_rval);
+    }
+  }
+
+  fn phrase_rval(&mut self, phrase_rval: &PhraseRVal) {
+    match phrase_rval {
+      PhraseRVal::Phrase( phrase ) => self.phrase(phrase),
+      PhraseRVal::DrumGrid( drum_grid ) => self.drum_grid(drum_grid),
+      PhraseRVal::LVal( lval ) => self.lval(lval),
+      PhraseRVal::FuncCall( func_call ) => self.func_call(func_call),
+    }
+  }
+
+  fn track(&mut self, track: &Track) {
+    for phrase_rval in &track.content {
+      self.phrase_rval(phrase_rval);
+    }
+  }
+
+  fn track_rval(&mut self, track_rval: &TrackRVal) {
+    match track_rval {
+      TrackRVal::Track( track ) => self.track(track),
+      TrackRVal::LVal( lval ) => self.lval(lval),
+      TrackRVal::FuncCall( func_call ) => self.func_call(func_call),
+    }
+  }
+
+  fn drum_grid(&mut self, drum_grid: &DrumGrid) {
+    for expr in drum_grid.steps.iter().chain(drum_grid.rows.iter().map(|row| &row.note)) {
+      self.expr(expr);
+    }
+  }
+
+  fn expr(&mut self, expr: &Expr) {
+    let unary_exps = expr.land_exps.iter()
+      .flat_map(|land_exp| &land_exp.eq_exps)
+      .flat_map(|eq_exp| &eq_exp.rel_exps)
+      .flat_map(|rel_exp| &rel_exp.add_exps)
+      .flat_map(|add_exp| &add_exp.mul_exps)
+      .flat_map(|mul_exp| &mul_exp.unary_exps);
+    for unary_exp in unary_exps {
+      match &unary_exp.primary_exp {
+        PrimaryExpr::Expr( expr ) => self.expr(expr),
+        PrimaryExpr::LVal( lval ) => self.lval(lval),
+        PrimaryExpr::FuncCall( func_call ) => self.func_call(func_call),
+        PrimaryExpr::Number(_) | PrimaryExpr::Str(_) => (),
+      }
+    }
+  }
+
+  /// 引用常量、变量或参数。没有定义位置的 General MIDI 名称、波形名称等只提供悬停说明
+  fn lval(&mut self, lval: &LVal) {
+    let Some( rval ) = lval.rval.borrow().clone() else { return };
+    let target = match (self.analysis.definitions.contains_key(&key(&rval)), rval.get_value()) {
+      (true, _) => Target::Symbol(key(&rval)),
+      (false, Value::Str( name )) if self.instruments.contains_key(&name) => Target::Symbol(self.instruments[&name]),
+      (false, Value::Int( n )) => Target::Builtin(format!("int {} = {n}", lval.ident)),
+      (false, _) => Target::Builtin(format!("{} {}", rval.get_btype(), lval.ident)),
+    };
+    self.refer(lval.span, target);
+  }
+
+  /// 调用函数，函数名的位置为函数调用的开头
+  fn func_call(&mut self, func_call: &FuncCall) {
+    let span = Span::new(func_call.span.start, func_call.span.start + func_call.ident.len());
+    if let Some( func_def ) = func_call.func_def.borrow().as_ref() {
+      self.refer(span, Target::Symbol(key(func_def)));
+    } else if let Some( builtin ) = func_call.get_builtin() {
+      self.refer(span, Target::Builtin(builtin_signature(builtin)));
+    }
+    for asgn_rval in &func_call.func_rparams {
+      self.asgn_rval(asgn_rval);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SOURCE: &str = "int base = 60;\nint up(int n) { return base + n; }\n@score {\n  int x = up(base);\n  @x name = \"lead\";\n}\n";
+
+  fn analysis(source: &str) -> Analysis {
+    Analysis::new(&SyntacticAnalyzer::new(), source.to_string())
+  }
+
+  #[test]
+  fn definition_and_references() {
+    let analysis = analysis(SOURCE);
+    assert!(analysis.diagnostic.is_none());
+    let use_ = SOURCE.find("base +").unwrap();
+    assert_eq!(analysis.definition(use_), Some(Span::new(4, 8)));
+    let starts: Vec<_> = analysis.references(use_, true).iter().map(|span| span.start).collect();
+    assert_eq!(starts, [4, use_, SOURCE.rfind("base").unwrap()]);
+    assert_eq!(analysis.references(use_, false).len(), 2);
+  }
+
+  #[test]
+  fn hover_shows_signature() {
+    let analysis = analysis(SOURCE);
+    let (_, hover) = analysis.hover(SOURCE.find("up(base)").unwrap()).unwrap();
+    assert_eq!(hover, "int up(int n)");
+  }
+
+  #[test]
+  fn completions_in_scope() {
+    let analysis = analysis(SOURCE);
+    let labels: Vec<_> = analysis.completions(SOURCE.find("+ n").unwrap()).into_iter().map(|completion| completion.label).collect();
+    for label in ["n", "up", "base", "len", "kick", "int"] {
+      assert!(labels.iter().any(|l| l == label), "{label} is missing in {labels:?}");
+    }
+    assert!(!labels.iter().any(|l| l == "x"));
+  }
+
+  #[test]
+  fn parse_error_diagnostic() {
+    let analysis = analysis("@score {\n  @0 nmae = \"lead\";\n}\n");
+    let (span, message) = analysis.diagnostic.unwrap();
+    assert_eq!(span, Span::new(14, 18));
+    assert!(message.contains("'nmae'"), "{message}");
+  }
+}
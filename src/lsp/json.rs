@@ -0,0 +1,312 @@
+use std::fmt::{self, Write};
+
+use crate::error::Error;
+
+/// JSON 值，只实现 LSP 消息需要的部分。对象保持字段的顺序
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  /// 由字段列表构造对象
+  pub fn object(fields: Vec<(&str, Json)>) -> Self {
+    Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+  }
+
+  /// 对象中名为 key 的字段，不是对象或没有该字段时返回 None
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::String(s) => Some(s),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Json::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+
+  /// 非负整数值
+  pub fn as_usize(&self) -> Option<usize> {
+    match self {
+      Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&[Json]> {
+    match self {
+      Json::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+
+  /// 解析一个完整的 JSON 文本
+  pub fn parse(text: &str) -> Result<Json, Error> {
+    let mut parser = Parser{text, pos: 0};
+    let json = parser.value()?;
+    parser.whitespace();
+    match parser.pos == text.len() {
+      true => Ok(json),
+      false => Err(parser.error()),
+    }
+  }
+}
+
+impl From<&str> for Json {
+  fn from(s: &str) -> Self {
+    Json::String(s.to_string())
+  }
+}
+
+impl From<String> for Json {
+  fn from(s: String) -> Self {
+    Json::String(s)
+  }
+}
+
+impl From<usize> for Json {
+  fn from(n: usize) -> Self {
+    Json::Number(n as f64)
+  }
+}
+
+impl From<bool> for Json {
+  fn from(b: bool) -> Self {
+    Json::Bool(b)
+  }
+}
+
+impl fmt::Display for Json {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Json::Null => write!(f, "null"),
+      Json::Bool(b) => write!(f, "{b}"),
+      Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+      Json::Number(n) => write!(f, "{n}"),
+      Json::String(s) => write_string(f, s),
+      Json::Array(items) => {
+        f.write_char('[')?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write!(f, "{item}")?;
+        }
+        f.write_char(']')
+      },
+      Json::Object(fields) => {
+        f.write_char('{')?;
+        for (i, (key, value)) in fields.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write_string(f, key)?;
+          write!(f, ":{value}")?;
+        }
+        f.write_char('}')
+      },
+    }
+  }
+}
+
+/// 输出 JSON 字符串字面量
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+  f.write_char('"')?;
+  for c in s.chars() {
+    match c {
+      '"' => f.write_str("\\\"")?,
+      '\\' => f.write_str("\\\\")?,
+      '\n' => f.write_str("\\n")?,
+      '\r' => f.write_str("\\r")?,
+      '\t' => f.write_str("\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => f.write_char(c)?,
+    }
+  }
+  f.write_char('"')
+}
+
+/// 递归下降的 JSON 解析器，pos 为下一个字符的字节偏移
+struct Parser<'a> {
+  text: &'a str,
+  pos: usize,
+}
+
+impl Parser<'_> {
+  fn error(&self) -> Error {
+    Error::ParseError(format!("invalid JSON at {}", self.pos))
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.text[self.pos..].chars().next()
+  }
+
+  fn whitespace(&mut self) {
+    while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+      self.pos += 1;
+    }
+  }
+
+  /// 跳过空白后期望字符 c
+  fn expect(&mut self, c: char) -> Result<(), Error> {
+    self.whitespace();
+    match self.peek() == Some(c) {
+      true => {
+        self.pos += 1;
+        Ok(())
+      },
+      false => Err(self.error()),
+    }
+  }
+
+  /// 期望关键字 word，返回 value
+  fn keyword(&mut self, word: &str, value: Json) -> Result<Json, Error> {
+    match self.text[self.pos..].starts_with(word) {
+      true => {
+        self.pos += word.len();
+        Ok(value)
+      },
+      false => Err(self.error()),
+    }
+  }
+
+  fn value(&mut self) -> Result<Json, Error> {
+    self.whitespace();
+    match self.peek() {
+      Some( '{' ) => self.object(),
+      Some( '[' ) => self.array(),
+      Some( '"' ) => Ok(Json::String(self.string()?)),
+      Some( 't' ) => self.keyword("true", Json::Bool(true)),
+      Some( 'f' ) => self.keyword("false", Json::Bool(false)),
+      Some( 'n' ) => self.keyword("null", Json::Null),
+      Some( '-' | '0'..='9' ) => self.number(),
+      _ => Err(self.error()),
+    }
+  }
+
+  fn object(&mut self) -> Result<Json, Error> {
+    self.expect('{')?;
+    let mut fields = vec![];
+    self.whitespace();
+    if self.peek() == Some('}') {
+      self.pos += 1;
+      return Ok(Json::Object(fields));
+    }
+    loop {
+      self.whitespace();
+      let key = self.string()?;
+      self.expect(':')?;
+      fields.push((key, self.value()?));
+      self.whitespace();
+      match self.peek() {
+        Some( ',' ) => self.pos += 1,
+        Some( '}' ) => {
+          self.pos += 1;
+          return Ok(Json::Object(fields));
+        },
+        _ => return Err(self.error()),
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Json, Error> {
+    self.expect('[')?;
+    let mut items = vec![];
+    self.whitespace();
+    if self.peek() == Some(']') {
+      self.pos += 1;
+      return Ok(Json::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.whitespace();
+      match self.peek() {
+        Some( ',' ) => self.pos += 1,
+        Some( ']' ) => {
+          self.pos += 1;
+          return Ok(Json::Array(items));
+        },
+        _ => return Err(self.error()),
+      }
+    }
+  }
+
+  fn number(&mut self) -> Result<Json, Error> {
+    let len = self.text[self.pos..]
+      .find(|c: char| !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+      .unwrap_or(self.text.len() - self.pos);
+    let n = self.text[self.pos..self.pos + len].parse().map_err(|_| self.error())?;
+    self.pos += len;
+    Ok(Json::Number(n))
+  }
+
+  fn string(&mut self) -> Result<String, Error> {
+    if self.peek() != Some('"') {
+      return Err(self.error());
+    }
+    self.pos += 1;
+    let mut s = String::new();
+    loop {
+      let c = self.peek().ok_or(self.error())?;
+      self.pos += c.len_utf8();
+      match c {
+        '"' => return Ok(s),
+        '\\' => {
+          let escape = self.peek().ok_or(self.error())?;
+          self.pos += 1;
+          match escape {
+            '"' | '\\' | '/' => s.push(escape),
+            'b' => s.push('\u{8}'),
+            'f' => s.push('\u{c}'),
+            'n' => s.push('\n'),
+            'r' => s.push('\r'),
+            't' => s.push('\t'),
+            'u' => s.push(self.unicode_escape()?),
+            _ => return Err(self.error()),
+          }
+        },
+        c => s.push(c),
+      }
+    }
+  }
+
+  /// `\u` 之后的 4 位十六进制数，UTF-16 的代理对由两个转义组成
+  fn unicode_escape(&mut self) -> Result<char, Error> {
+    let high = self.hex4()?;
+    if !(0xD800..0xDC00).contains(&high) {
+      return char::from_u32(high).ok_or(self.error());
+    }
+    if !self.text[self.pos..].starts_with("\\u") {
+      return Err(self.error());
+    }
+    self.pos += 2;
+    let low = self.hex4()?;
+    if !(0xDC00..0xE000).contains(&low) {
+      return Err(self.error());
+    }
+    char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or(self.error())
+  }
+
+  fn hex4(&mut self) -> Result<u32, Error> {
+    let hex = self.text.get(self.pos..self.pos + 4).ok_or(self.error())?;
+    let n = u32::from_str_radix(hex, 16).map_err(|_| self.error())?;
+    self.pos += 4;
+    Ok(n)
+  }
+}
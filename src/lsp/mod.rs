@@ -0,0 +1,246 @@
+mod json;
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use analysis::Analysis;
+use json::Json;
+
+use crate::ast::span::Span;
+use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+/// JSON-RPC 的错误码
+const PARSE_ERROR: i32 = -32700;
+const INVALID_PARAMS: i32 = -32602;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// 以 stdio 通信的 yam 语言服务器(Language Server Protocol)。
+/// 打开或修改文档时重新进行语法分析和语义检查并发布诊断信息，
+/// 提供跳转到定义、查找引用、悬停说明和补全
+pub struct LanguageServer {
+  parser: SyntacticAnalyzer,
+
+  /// 打开的文档的 uri 及其分析结果。文档以全量的方式同步
+  documents: HashMap<String, Analysis>,
+
+  /// 是否已经收到 shutdown 请求
+  shutdown: bool,
+}
+
+impl Default for LanguageServer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl LanguageServer {
+  pub fn new() -> Self {
+    Self {
+      parser: SyntacticAnalyzer::new(),
+      documents: HashMap::new(),
+      shutdown: false,
+    }
+  }
+
+  /// 从 input 读取消息并把响应和通知写入 output，直到收到 exit 通知或 input 结束。
+  /// 没有先收到 shutdown 请求就退出时返回错误
+  pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    while let Some( content ) = read_message(&mut input)? {
+      let message = match Json::parse(&content) {
+        Ok( message ) => message,
+        Err( err ) => {
+          send(&mut output, response_error(Json::Null, PARSE_ERROR, err.to_string()))?;
+          continue;
+        },
+      };
+      let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+      let params = message.get("params").unwrap_or(&Json::Null);
+      match message.get("id") {
+        Some( id ) => {
+          let response = match self.request(method, params) {
+            Ok( result ) => Json::object(vec![("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+            Err( (code, msg) ) => response_error(id.clone(), code, msg),
+          };
+          send(&mut output, response)?;
+        },
+        None if method == "exit" => break,
+        None => for notification in self.notification(method, params) {
+          send(&mut output, notification)?;
+        },
+      }
+    }
+    match self.shutdown {
+      true => Ok(()),
+      false => Err(io::Error::other("language server exited without shutdown")),
+    }
+  }
+
+  /// 处理请求，返回结果或错误码及错误信息
+  fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+    match method {
+      "initialize" => Ok(Json::object(vec![
+        ("capabilities", Json::object(vec![
+          ("textDocumentSync", 1.into()),
+          ("definitionProvider", true.into()),
+          ("referencesProvider", true.into()),
+          ("hoverProvider", true.into()),
+          ("completionProvider", Json::object(vec![])),
+        ])),
+        ("serverInfo", Json::object(vec![
+          ("name", "yam".into()),
+          ("version", env!("CARGO_PKG_VERSION").into()),
+        ])),
+      ])),
+      "shutdown" => {
+        self.shutdown = true;
+        Ok(Json::Null)
+      },
+      "textDocument/definition" => {
+        let (uri, analysis, offset) = self.document_position(params)?;
+        Ok(match analysis.definition(offset) {
+          Some( span ) => location(uri, analysis, span),
+          None => Json::Null,
+        })
+      },
+      "textDocument/references" => {
+        let (uri, analysis, offset) = self.document_position(params)?;
+        let include_declaration = params.get("context")
+          .and_then(|context| context.get("includeDeclaration"))
+          .and_then(Json::as_bool)
+          .unwrap_or(true);
+        let locations = analysis.references(offset, include_declaration).into_iter()
+          .map(|span| location(uri, analysis, span))
+          .collect();
+        Ok(Json::Array(locations))
+      },
+      "textDocument/hover" => {
+        let (_, analysis, offset) = self.document_position(params)?;
+        Ok(match analysis.hover(offset) {
+          Some( (span, hover) ) => Json::object(vec![
+            ("contents", Json::object(vec![
+              ("kind", "markdown".into()),
+              ("value", format!("```yam\n{hover}\n```").into()),
+            ])),
+            ("range", range(analysis, span)),
+          ]),
+          None => Json::Null,
+        })
+      },
+      "textDocument/completion" => {
+        let (_, analysis, offset) = self.document_position(params)?;
+        let items = analysis.completions(offset).into_iter()
+          .map(|completion| Json::object(vec![
+            ("label", completion.label.into()),
+            ("kind", (completion.kind as usize).into()),
+            ("detail", completion.detail.into()),
+          ]))
+          .collect();
+        Ok(Json::Array(items))
+      },
+      _ => Err((METHOD_NOT_FOUND, format!("method {method} is not supported"))),
+    }
+  }
+
+  /// 处理通知，返回需要发送的通知
+  fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+    let uri_ = params.get("textDocument").and_then(|document| document.get("uri")).and_then(Json::as_str);
+    let text_ = match method {
+      "textDocument/didOpen" => params.get("textDocument").and_then(|document| document.get("text")),
+      "textDocument/didChange" => params.get("contentChanges")
+        .and_then(Json::as_array)
+        .and_then(|changes| changes.last())
+        .and_then(|change| change.get("text")),
+      "textDocument/didClose" => {
+        if let Some( uri ) = uri_ {
+          self.documents.remove(uri);
+          return vec![publish_diagnostics(uri, vec![])];
+        }
+        None
+      },
+      _ => None,
+    };
+    let (Some( uri ), Some( text )) = (uri_, text_.and_then(Json::as_str)) else { return vec![] };
+
+    let analysis = Analysis::new(&self.parser, text.to_string());
+    let diagnostics = analysis.diagnostic.iter()
+      .map(|(span, message)| Json::object(vec![
+        ("range", range(&analysis, *span)),
+        ("severity", 1.into()),
+        ("source", "yam".into()),
+        ("message", message.clone().into()),
+      ]))
+      .collect();
+    self.documents.insert(uri.to_string(), analysis);
+    vec![publish_diagnostics(uri, diagnostics)]
+  }
+
+  /// 请求参数中的文档及位置对应的字节偏移
+  fn document_position<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Analysis, usize), (i32, String)> {
+    let invalid = || (INVALID_PARAMS, "expect textDocument and position".to_string());
+    let uri = params.get("textDocument").and_then(|document| document.get("uri")).and_then(Json::as_str).ok_or_else(invalid)?;
+    let position = params.get("position").ok_or_else(invalid)?;
+    let line = position.get("line").and_then(Json::as_usize).ok_or_else(invalid)?;
+    let character = position.get("character").and_then(Json::as_usize).ok_or_else(invalid)?;
+    let analysis = self.documents.get(uri).ok_or((INVALID_PARAMS, format!("document {uri} is not opened")))?;
+    Ok((uri, analysis, analysis.offset(line, character)))
+  }
+}
+
+/// 读取一条消息的内容，input 结束时返回 None
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+  let mut content_length_ = None;
+  loop {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some( (name, value) ) = line.split_once(':') && name.eq_ignore_ascii_case("Content-Length") {
+      content_length_ = value.trim().parse::<usize>().ok();
+    }
+  }
+  let content_length = content_length_.ok_or(io::Error::other("missing Content-Length header"))?;
+  let mut content = vec![0; content_length];
+  input.read_exact(&mut content)?;
+  String::from_utf8(content).map(Some).map_err(io::Error::other)
+}
+
+/// 加上 Content-Length 头部发送一条消息
+fn send(output: &mut impl Write, message: Json) -> io::Result<()> {
+  let content = message.to_string();
+  write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+  output.flush()
+}
+
+fn response_error(id: Json, code: i32, message: String) -> Json {
+  Json::object(vec![
+    ("jsonrpc", "2.0".into()),
+    ("id", id),
+    ("error", Json::object(vec![("code", Json::Number(code as f64)), ("message", message.into())])),
+  ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+  Json::object(vec![
+    ("jsonrpc", "2.0".into()),
+    ("method", "textDocument/publishDiagnostics".into()),
+    ("params", Json::object(vec![("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))])),
+  ])
+}
+
+fn position(analysis: &Analysis, offset: usize) -> Json {
+  let (line, character) = analysis.position(offset);
+  Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+fn range(analysis: &Analysis, span: Span) -> Json {
+  Json::object(vec![("start", position(analysis, span.start)), ("end", position(analysis, span.end))])
+}
+
+fn location(uri: &str, analysis: &Analysis, span: Span) -> Json {
+  Json::object(vec![("uri", uri.into()), ("range", range(analysis, span))])
+}
@@ -1,29 +1,153 @@
-use std::fs::read_to_string;
-use std::io::{Result, Error, ErrorKind};
-use yam::{SyntacticAnalyzer, SemanticAnalyzer, Interpreter};
+use std::fs::{read, read_to_string, write};
+use std::path::Path;
+use std::io::{Result, Error, ErrorKind, stdin, stdout};
+use yam::{SyntacticAnalyzer, SemanticAnalyzer, Interpreter, Importer, Formatter, LanguageServer, Repl, TestRunner, Debugger, Notation, WavFormat, SampleFormat, SoundFont};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+  #[command(subcommand)]
+  command: Option<Command>,
+
   /// 输入文件路径
   #[arg(short = 'i', long = "input", required = true)]
-  input: String,
+  input: Option<String>,
 
   /// 输出文件路径
   #[arg(short = 'o', long = "output", required = true)]
-  output: String,
+  output: Option<String>,
+
+  /// 合并所有 track 输出为单轨的 SMF format 0 文件
+  #[arg(long = "single-track")]
+  single_track: bool,
+
+  /// 每个四分音符所占的 tick 数(PPQ)，覆盖乐谱中的 @ppq
+  #[arg(long = "ppq", value_parser = clap::value_parser!(u16).range(1..=16383))]
+  ppq: Option<u16>,
+
+  /// 以 MIDI Tuning Standard 的 bulk tuning dump 输出音律，写入与输出文件同名的 .syx 文件
+  #[arg(long = "mts")]
+  mts: bool,
+
+  /// 输出格式，默认由输出文件后缀决定
+  #[arg(long = "format", value_enum)]
+  format: Option<OutputFormat>,
+
+  /// 输出 WAV 时的采样率
+  #[arg(long = "sample-rate", value_parser = clap::value_parser!(u32).range(8000..=192000))]
+  sample_rate: Option<u32>,
+
+  /// 输出 WAV 时使用 32 位浮点数采样，默认为 16 位整数
+  #[arg(long = "float")]
+  float: bool,
+
+  /// 输出 WAV 时使用 SoundFont(.sf2)中的 General MIDI 乐器渲染，而不是内置合成器
+  #[arg(long = "soundfont")]
+  soundfont: Option<String>,
+
+  /// 将输出的各个音符到源文件行号、列号的映射以 JSON 写入该文件
+  #[arg(long = "source-map")]
+  source_map: Option<String>,
+}
+
+/// 输出格式
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+  /// Standard MIDI File
+  Mid,
+
+  /// MusicXML 乐谱
+  Musicxml,
+
+  /// LilyPond 乐谱
+  Ly,
+
+  /// ABC 记谱法
+  Abc,
+
+  /// 用内置合成器渲染的 WAV 音频
+  Wav,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+  /// 将 midi 文件或 ABC 记谱法的乐曲转换为 yam 源文件
+  Import {
+    /// 输入的 midi 文件或 .abc 文件路径
+    input: String,
+
+    /// 输出的 yam 文件路径
+    #[arg(short = 'o', long = "output")]
+    output: String,
+
+    /// 量化网格，以全音符的几分之一表示，须为 2 的幂，默认为 16 即十六分音符
+    #[arg(long = "grid", value_parser = clap::value_parser!(u32).range(1..=1024))]
+    grid: Option<u32>,
+  },
+
+  /// 按统一的风格格式化 yam 源文件，直接改写文件
+  Fmt {
+    /// 要格式化的 yam 文件路径
+    #[arg(required = true)]
+    files: Vec<String>,
+
+    /// 只检查文件是否已经格式化而不改写，有未格式化的文件时以错误退出
+    #[arg(long = "check")]
+    check: bool,
+  },
+
+  /// 以 stdio 通信的语言服务器(LSP)，供编辑器提供诊断、跳转、悬停和补全
+  Lsp,
+
+  /// 交互式解释器，逐步定义函数和变量、求值表达式，并可以把乐谱写入 midi 文件
+  Repl,
+
+  /// 执行 yam 源文件中以 test "name" { ... } 定义的测试，报告每个测试是否通过
+  Test {
+    /// 要测试的 yam 文件路径
+    input: String,
+  },
+
+  /// 调试 yam 源文件：在断点或单步执行时暂停，查看变量和调用栈；或用 --trace 跟踪执行
+  Debug {
+    /// 要调试的 yam 文件路径
+    input: String,
+
+    /// 执行完成后把 midi 文件写入该路径
+    #[arg(short = 'o', long = "output")]
+    output: Option<String>,
+
+    /// 在该行设置断点，可以设置多个，没有断点时在第一条语句暂停
+    #[arg(short = 'b', long = "break")]
+    breakpoints: Vec<usize>,
+
+    /// 不交互，记录执行的每条语句以及每次函数调用的实参和返回值
+    #[arg(long = "trace", conflicts_with = "breakpoints")]
+    trace: bool,
+  },
 }
 
 fn main() -> Result<()> {
   let args = Args::parse();
-  let input = args.input;
-  let output = args.output;
+  match args.command {
+    Some(Command::Import{input, output, grid}) => return import(&input, &output, grid),
+    Some(Command::Fmt{files, check}) => return fmt(&files, check),
+    Some(Command::Lsp) => return LanguageServer::new().run(stdin().lock(), stdout().lock()),
+    Some(Command::Repl) => return Repl::new().run(stdin().lock(), stdout().lock()),
+    Some(Command::Test{input}) => return test(&input),
+    Some(Command::Debug{input, output, breakpoints, trace}) => return debug(&input, output, &breakpoints, trace),
+    None => (),
+  }
+  // 没有子命令时 clap 保证输入输出路径都已给出
+  let input_path = args.input.unwrap();
+  let output = args.output.unwrap();
 
   // 读取输入文件
-  let input = read_to_string(input)?;
+  let input = read_to_string(&input_path)?;
 
   // 创建词法&语法分析器
   let parser = SyntacticAnalyzer::new();
@@ -41,13 +165,160 @@ fn main() -> Result<()> {
   
   // 创建解释器
   let mut interpreter = Interpreter::new();
+  interpreter.set_single_track(args.single_track);
+  interpreter.set_karaoke(output.to_lowercase().ends_with(".kar"));
+  interpreter.set_mts(args.mts);
+  let format = args.format.unwrap_or(output_format(&output));
+  interpreter.set_notation(match format {
+    OutputFormat::Mid | OutputFormat::Wav => None,
+    OutputFormat::Musicxml => Some(Notation::MusicXml),
+    OutputFormat::Ly => Some(Notation::LilyPond),
+    OutputFormat::Abc => Some(Notation::Abc),
+  });
+  if format == OutputFormat::Wav {
+    let mut wav_format = WavFormat::default();
+    if let Some(sample_rate) = args.sample_rate {
+      wav_format.sample_rate = sample_rate;
+    }
+    if args.float {
+      wav_format.sample_format = SampleFormat::Float32;
+    }
+    interpreter.set_wav_format(Some(wav_format));
+  }
+  if let Some(path) = args.soundfont {
+    if format != OutputFormat::Wav {
+      return Err(Error::other("--soundfont can only be used with WAV output"));
+    }
+    let soundfont = SoundFont::parse(&read(&path)?).map_err(|e| Error::other(format!("{path}: {e}")))?;
+    interpreter.set_soundfont(Some(soundfont));
+  }
+  if let Some(ppq) = args.ppq {
+    interpreter.set_ppq(ppq);
+  }
+  interpreter.set_source_dir(Path::new(&input_path).parent().map(Path::to_path_buf));
+  interpreter.set_source_mapping(args.source_map.is_some());
 
   // 执行翻译
   let midi_file = interpreter.interpret(&comp_unit)?;
   println!("Interpret successflly");
 
+  // 保存音符到源文件位置的映射
+  if let (Some(path), Some(source_map)) = (&args.source_map, interpreter.source_map()) {
+    write(path, source_map.to_json(&input_path, &input))?;
+  }
+
+  // 保存音律的 sysex 文件
+  if let Some(tuning_dump) = interpreter.tuning_dump() {
+    write(Path::new(&output).with_extension("syx"), tuning_dump)?;
+  }
+
+  // 输出乐谱或音频时保存乐谱或音频而不是 midi 文件
+  if let Some(score_text) = interpreter.score_text() {
+    return write(output, score_text);
+  }
+  if let Some(wav) = interpreter.wav() {
+    return write(output, wav);
+  }
+
   // 保存 midi 文件
   midi_file.save(output).map_err(|e|
     Error::new(ErrorKind::Other, e.to_string())
   )
 }
+
+/// 由输出文件后缀推断输出格式，.musicxml 或 .xml 为 MusicXML，.ly 为 LilyPond，.abc 为 ABC，.wav 为音频，其余为 midi
+fn output_format(output: &str) -> OutputFormat {
+  let output = output.to_lowercase();
+  if output.ends_with(".musicxml") || output.ends_with(".xml") {
+    OutputFormat::Musicxml
+  } else if output.ends_with(".ly") {
+    OutputFormat::Ly
+  } else if output.ends_with(".abc") {
+    OutputFormat::Abc
+  } else if output.ends_with(".wav") {
+    OutputFormat::Wav
+  } else {
+    OutputFormat::Mid
+  }
+}
+
+/// 读取 midi 文件或 .abc 文件并转换为 yam 源文件
+fn import(input: &str, output: &str, grid: Option<u32>) -> Result<()> {
+  let mut importer = Importer::new();
+  if let Some(grid) = grid {
+    importer.set_grid(grid);
+  }
+  let source = match input.to_lowercase().ends_with(".abc") {
+    true => importer.import_abc(&read_to_string(input)?)?,
+    false => importer.import_smf(&read(input)?)?,
+  };
+  println!("Import successflly");
+
+  write(output, source)
+}
+
+/// 格式化 yam 源文件，check 为真时只检查而不改写
+fn fmt(files: &[String], check: bool) -> Result<()> {
+  let formatter = Formatter::new();
+  let mut unformatted = 0;
+  for file in files {
+    let source = read_to_string(file)?;
+    let formatted = formatter.format(&source).map_err(|e| Error::other(format!("{file}: {e}")))?;
+    if formatted == source {
+      continue;
+    }
+    match check {
+      true => {
+        println!("{file} is not formatted");
+        unformatted += 1;
+      },
+      false => write(file, formatted)?,
+    }
+  }
+
+  match unformatted {
+    0 => Ok(()),
+    count => Err(Error::other(format!("{count} file(s) are not formatted"))),
+  }
+}
+
+/// 执行 yam 源文件中的测试，有测试失败时以错误退出
+fn test(input: &str) -> Result<()> {
+  let source = read_to_string(input)?;
+  let results = TestRunner::new().run(&source).map_err(|located|
+    Error::other(format!("{input}:{}:{}: {}", located.line, located.column, located.error))
+  )?;
+
+  println!("running {} test(s)", results.len());
+  let mut failed = 0;
+  for result in &results {
+    match &result.failure {
+      None => println!("test \"{}\" ... ok", result.name),
+      Some( located ) => {
+        println!("test \"{}\" ... FAILED", result.name);
+        println!("  {input}:{}:{}: {}", located.line, located.column, located.error);
+        failed += 1;
+      },
+    }
+  }
+  println!("{} passed; {failed} failed", results.len() - failed);
+
+  match failed {
+    0 => Ok(()),
+    count => Err(Error::other(format!("{count} test(s) failed"))),
+  }
+}
+
+/// 调试或跟踪执行 yam 源文件，执行完成且给出输出路径时保存 midi 文件
+fn debug(input: &str, output: Option<String>, breakpoints: &[usize], trace: bool) -> Result<()> {
+  let source = read_to_string(input)?;
+  let mut debugger = Debugger::new();
+  debugger.set_breakpoints(breakpoints);
+  debugger.set_trace(trace);
+  let midi_file_ = debugger.run(input, &source, stdin().lock(), stdout().lock())?;
+
+  match (midi_file_, output) {
+    (Some( midi_file ), Some( output )) => midi_file.save(output).map_err(|e| Error::other(e.to_string())),
+    _ => Ok(()),
+  }
+}
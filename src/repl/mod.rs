@@ -0,0 +1,170 @@
+mod show;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use show::show_as;
+
+use crate::ast::expr::PrimaryExpr;
+use crate::ast::func::FuncType;
+use crate::ast::repl::{ReplItem, ReplUnit};
+use crate::ast::score::Score;
+use crate::ast::stmt::{AsgnRVal, Stmt};
+use crate::ast::val::BType;
+use crate::error::Error;
+use crate::interpret::ctr::RetVal;
+use crate::interpret::Interpreter;
+use crate::semantic::Analyzer as SemanticAnalyzer;
+use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+/// 输入提示符
+const PROMPT: &str = "yam> ";
+
+/// 输入没有结束时，继续输入的提示符
+const CONTINUATION_PROMPT: &str = "...> ";
+
+const HELP: &str = "\
+输入以分号结尾的声明和语句，或者函数定义、乐器定义，在全局作用域中依次执行；
+输入不以分号结尾的表达式或音乐字面量，显示它的值；
+输入 @score { ... } 设置当前的乐谱。
+  :write <file.mid>  执行当前的乐谱并写入 midi 文件
+  :help              显示这段说明
+  :quit              退出";
+
+/// 交互式解释器。全局作用域在多次输入之间保持，可以逐步定义函数和变量、求值表达式，
+/// 并把当前的乐谱写入 midi 文件
+pub struct Repl {
+  parser: SyntacticAnalyzer,
+  semantic_analyzer: SemanticAnalyzer,
+  interpreter: Interpreter,
+
+  /// 最近一次输入的乐谱
+  score: Option<Score>,
+
+  /// 全局常量和变量声明的类型。执行时的值不带声明的类型，如 note 可以保存为整数音高
+  btypes: HashMap<String, BType>,
+}
+
+impl Default for Repl {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Repl {
+  pub fn new() -> Self {
+    Self {
+      parser: SyntacticAnalyzer::new(),
+      semantic_analyzer: SemanticAnalyzer::new(),
+      interpreter: Interpreter::new(),
+      score: None,
+      btypes: HashMap::new(),
+    }
+  }
+
+  /// 从 input 逐行读取输入并把结果和错误写入 output，直到 input 结束或输入 :quit。
+  /// 输入在一行内没有结束时继续读取下一行，空行结束多行的输入
+  pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut source = String::new();
+    loop {
+      write!(output, "{}", if source.is_empty() { PROMPT } else { CONTINUATION_PROMPT })?;
+      output.flush()?;
+      let mut line = String::new();
+      if input.read_line(&mut line)? == 0 {
+        return Ok(());
+      }
+
+      if source.is_empty() {
+        match line.trim() {
+          "" => continue,
+          ":quit" => return Ok(()),
+          command if command.starts_with(':') => {
+            match self.command(command) {
+              Ok( text ) => writeln!(output, "{text}")?,
+              Err( err ) => writeln!(output, "{err}")?,
+            }
+            continue;
+          },
+          _ => (),
+        }
+      }
+
+      let blank = line.trim().is_empty();
+      source += &line;
+      match self.parser.parse_repl(&source) {
+        Err( (_, span) ) if !blank && span.start >= source.trim_end().len() => continue,
+        Err( (err, _) ) => writeln!(output, "{err}")?,
+        Ok( repl_unit ) => match self.execute(repl_unit) {
+          Ok(Some( text )) => writeln!(output, "{text}")?,
+          Ok(None) => (),
+          Err( err ) => writeln!(output, "{err}")?,
+        },
+      }
+      source.clear();
+    }
+  }
+
+  /// 检查并执行一次完整的输入，求值时返回值的显示形式
+  pub fn eval(&mut self, source: &str) -> Result<Option<String>, Error> {
+    let repl_unit = self.parser.parse_repl(source).map_err(|(err, _)| err)?;
+    self.execute(repl_unit)
+  }
+
+  fn execute(&mut self, repl_unit: ReplUnit) -> Result<Option<String>, Error> {
+    self.semantic_analyzer.repl_check(&repl_unit)?;
+    let btype_ = self.declared_btype(&repl_unit);
+    let ret_val = self.interpreter.interpret_repl(&repl_unit)?;
+    match repl_unit {
+      ReplUnit::Items( items ) => for (item, _) in &items {
+        match item {
+          ReplItem::Stmt(Stmt::ConstDecl( const_decl )) => for const_def in &const_decl.const_defs {
+            self.btypes.insert(const_def.ident.clone(), const_decl.btype);
+          },
+          ReplItem::Stmt(Stmt::VarDecl( var_decl )) => for var_def in &var_decl.var_defs {
+            self.btypes.insert(var_def.ident.clone(), var_decl.btype);
+          },
+          _ => (),
+        }
+      },
+      ReplUnit::Score( score ) => self.score = Some(score),
+      ReplUnit::Eval(_) => (),
+    }
+    Ok(match ret_val {
+      RetVal::Value( value ) => Some(show_as(&value, btype_)),
+      RetVal::Void => None,
+    })
+  }
+
+  /// 求值的只是一个全局常量、变量或函数调用时，它声明的类型
+  fn declared_btype(&self, repl_unit: &ReplUnit) -> Option<BType> {
+    let ReplUnit::Eval(AsgnRVal::Expr( expr )) = repl_unit else {
+      return None;
+    };
+    match expr.as_primary_expr()? {
+      PrimaryExpr::LVal( lval ) => self.btypes.get(&lval.ident).copied(),
+      PrimaryExpr::FuncCall( func_call ) => match func_call.func_def.borrow().as_ref()?.func_type {
+        FuncType::BType( btype ) => Some(btype),
+        FuncType::Void => None,
+      },
+      _ => None,
+    }
+  }
+
+  /// 执行以 `:` 开头的命令，返回需要显示的文字
+  fn command(&mut self, command: &str) -> Result<String, Error> {
+    let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    match (name, arg.trim()) {
+      (":help", _) => Ok(HELP.to_string()),
+      (":write", "") => Err(Error::RuntimeError("expect a file name, e.g. :write song.mid".to_string())),
+      (":write", path) => {
+        let score = self.score.as_ref().ok_or(Error::RuntimeError(
+          "there is no score yet, input @score { ... } first".to_string()
+        ))?;
+        let midi_file = self.interpreter.interpret_score(score)?;
+        midi_file.save(path).map_err(|e| Error::RuntimeError(format!("{path}: {e}")))?;
+        Ok(format!("wrote {path}"))
+      },
+      _ => Err(Error::RuntimeError(format!("unknown command {name}, input :help for help"))),
+    }
+  }
+}
@@ -0,0 +1,77 @@
+use crate::ast::measure::{MeasureUnitValue, MeasureValue};
+use crate::ast::note::NoteValue;
+use crate::ast::phrase::PhraseValue;
+use crate::ast::track::TrackValue;
+use crate::ast::val::{BType, Value};
+
+/// 一个八度中各个音级的名称，黑键用升号拼写
+const PITCH_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// 以便于阅读的形式显示值：音符显示为音名，小节、phrase 和 track 显示为对应的字面量形式，
+/// 例如 `[ | C4, E4'G4=2 & . , A3 | ]`
+pub(super) fn show(value: &Value) -> String {
+  match value {
+    Value::Int( int ) => int.to_string(),
+    Value::Str( s ) => format!("\"{s}\""),
+    Value::Note( note ) => show_note(note),
+    Value::Measure( measure ) => show_measure(measure),
+    Value::Phrase( phrase ) => show_phrase(phrase),
+    Value::Track( track ) => show_track(track),
+  }
+}
+
+/// 按声明的类型显示值。声明为 note 的变量或函数返回值保存为整数音高时，同样显示为音名
+pub(super) fn show_as(value: &Value, btype_: Option<BType>) -> String {
+  match (value, btype_) {
+    (Value::Int( pitch ), Some(BType::Note)) => pitch_name(*pitch),
+    _ => show(value),
+  }
+}
+
+/// 音高的音名，如 60 为 C4，超出 midi 范围时显示为数字
+fn pitch_name(pitch: i32) -> String {
+  match pitch {
+    0..=127 => format!("{}{}", PITCH_NAMES[pitch as usize % 12], pitch / 12 - 1),
+    _ => pitch.to_string(),
+  }
+}
+
+fn show_note(note: &NoteValue) -> String {
+  let mut text = note.notes.iter().map(|pitch| pitch_name(*pitch)).collect::<Vec<_>>().join("'");
+  if let Some( cents ) = note.cents {
+    text += &format!(" ~ {cents}");
+  }
+  if let Some( len ) = note.len {
+    text += &format!("={len}");
+  }
+  if let Some( syllable ) = &note.syllable {
+    text += &format!(" \"{syllable}\"");
+  }
+  if let Some( velocity ) = note.velocity {
+    text += &format!(" (velocity {velocity})");
+  }
+  text
+}
+
+fn show_measure(measure: &MeasureValue) -> String {
+  let voices: Vec<_> = measure.voices().iter().map(|voice| {
+    voice.iter().map(|unit| match unit {
+      MeasureUnitValue::TimeDilation => "<".to_string(),
+      MeasureUnitValue::TimeCompression => ">".to_string(),
+      MeasureUnitValue::Rest => ".".to_string(),
+      MeasureUnitValue::VoiceSeparator => "&".to_string(),
+      MeasureUnitValue::NoteValue( note ) => show_note(note),
+    }).collect::<Vec<_>>().join(", ")
+  }).collect();
+  format!("| {} |", voices.join(" & "))
+}
+
+fn show_phrase(phrase: &PhraseValue) -> String {
+  let measures: Vec<_> = phrase.content.iter().map(show_measure).collect();
+  format!("[ {} ]", measures.join(" "))
+}
+
+fn show_track(track: &TrackValue) -> String {
+  let phrases: Vec<_> = track.content.iter().map(show_phrase).collect();
+  format!("{{ {} }}", phrases.join(" "))
+}
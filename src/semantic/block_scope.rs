@@ -52,6 +52,30 @@ impl BlockScope {
     }
   }
 
+  /// 删除一个符号，使其可以被重新定义
+  pub fn remove(&self, ident: &str) {
+    self.symbol_table.borrow_mut().remove(ident);
+  }
+
+  /// 保存当前的符号表，供出错时恢复
+  pub fn save(&self) -> HashMap<String, Symbol> {
+    self.symbol_table.borrow().clone()
+  }
+
+  /// 恢复之前保存的符号表
+  pub fn restore(&self, symbol_table: HashMap<String, Symbol>) {
+    *self.symbol_table.borrow_mut() = symbol_table;
+  }
+
+  /// 本 Block 中定义的所有符号，按名称排序
+  pub fn symbols(&self) -> Vec<(String, Symbol)> {
+    let mut symbols: Vec<_> = self.symbol_table.borrow().iter()
+      .map(|(ident, symbol)| (ident.clone(), symbol.clone()))
+      .collect();
+    symbols.sort_by(|a, b| a.0.cmp(&b.0));
+    symbols
+  }
+
   /// 检查对一个变量 LVal 的赋值是否合法。若合法则绑定该 LVal 的 RVal。
   /// 若这一级 Block 中不存在该变量的符号，返回值为假，上层 Block 还需要继续检查。
   /// 由于目前 Base Type 只有 int(i32)，不需要赋值的类型检查。
@@ -1,9 +1,11 @@
+use crate::ast::drum::DrumGrid;
+use crate::ast::expr::PrimaryExpr;
 use crate::ast::func::FuncType;
 use crate::ast::measure::{Measure, MeasureRVal, MeasureUnit};
 use crate::ast::note::Note;
 use crate::ast::phrase::{Phrase, PhraseRVal};
 use crate::ast::stmt::AsgnRVal;
-use crate::ast::track::Track;
+use crate::ast::track::{Track, TrackRVal};
 use crate::ast::val::BType;
 use crate::error::Error;
 use crate::semantic::check::expr_check::type_check;
@@ -16,6 +18,9 @@ impl Analyzer {
     if note.len.is_some() {
       self.expr_check(note.len.as_ref().unwrap(), Some(BType::Int))?;
     }
+    if let Some( cents ) = &note.cents {
+      self.expr_check(cents, Some(BType::Int))?;
+    }
     for expr in &note.notes {
       self.expr_check(expr, Some(BType::Int))?;
     }
@@ -32,6 +37,21 @@ impl Analyzer {
     Ok(())
   }
   
+  pub fn drum_grid_check(&mut self, drum_grid: &DrumGrid) -> Result<(), Error> {
+    if let Some( steps ) = &drum_grid.steps {
+      self.expr_check(steps, Some(BType::Int))?;
+    }
+    for row in &drum_grid.rows {
+      self.expr_check(&row.note, Some(BType::Int))?;
+      if let Err(c) = row.measures() {
+        return Err(Error::SemanticError(format!(
+          "invalid drum step '{c}' in \"{}\", expect one of x X o . - |", row.pattern
+        )));
+      }
+    }
+    Ok(())
+  }
+
   pub fn phrase_check(&mut self, phrase: &Phrase) -> Result<(), Error> {
     for measure_rval in &phrase.content {
       match measure_rval {
@@ -65,6 +85,7 @@ impl Analyzer {
     for phrase_rval in &track.content {
       match phrase_rval {
         PhraseRVal::Phrase( phrase ) => self.phrase_check(phrase)?,
+        PhraseRVal::DrumGrid( drum_grid ) => self.drum_grid_check(drum_grid)?,
         PhraseRVal::LVal( lval ) => {
           self.lval_check(lval)?;
 
@@ -90,11 +111,45 @@ impl Analyzer {
     Ok(())
   }
 
+  pub fn track_rval_check(&mut self, track_rval: &TrackRVal) -> Result<(), Error> {
+    match track_rval {
+      TrackRVal::Track( track ) => self.track_check(track),
+      TrackRVal::LVal( lval ) => {
+        self.lval_check(lval)?;
+
+        let ret_type = lval.rval.borrow().as_ref().unwrap().get_btype();
+        if ret_type != BType::Track {
+          return Err(Error::SemanticError(format!("expect track, but found {ret_type}")));
+        }
+        Ok(())
+      },
+      TrackRVal::FuncCall( func_call ) => {
+        let ret_type = self.func_call_check(func_call)?;
+
+        match ret_type {
+          FuncType::Void => Err(Error::SemanticError("expect track, but found void".to_string())),
+          FuncType::BType( ret_type ) => {
+            if ret_type != BType::Track {
+              return Err(Error::SemanticError(format!("expect track, but found {ret_type}")));
+            }
+            Ok(())
+          }
+        }
+      }
+    }
+  }
+
   pub fn asgn_rval_check(&mut self, asgn_rval: &AsgnRVal, expect_type: BType) -> Result<(), Error> {
     let ret_type = match asgn_rval {
       AsgnRVal::Expr( expr ) => {
         self.expr_check(expr, Some(expect_type))?;
-        BType::Int
+        match expr.as_primary_expr() {
+          Some(PrimaryExpr::LVal( lval )) => lval.rval.borrow().clone().unwrap().get_btype(),
+          // 函数的返回类型已在 expr_check 中检查过
+          Some(PrimaryExpr::FuncCall( _ )) => expect_type,
+          Some(PrimaryExpr::Str( _ )) => BType::Str,
+          _ => BType::Int,
+        }
       }
       AsgnRVal::Note( note ) => {
         self.note_check(note)?;
@@ -112,7 +167,30 @@ impl Analyzer {
         self.track_check(track)?;
         BType::Track
       }
+      AsgnRVal::DrumGrid( drum_grid ) => {
+        self.drum_grid_check(drum_grid)?;
+        BType::Phrase
+      }
     };
     type_check(ret_type, expect_type)
   }
+
+  /// 不限定类型的右值检查。单独的标识符、函数调用和字符串可以是任意类型，有运算的表达式必须为 int/bool
+  pub fn any_asgn_rval_check(&mut self, asgn_rval: &AsgnRVal) -> Result<(), Error> {
+    match asgn_rval {
+      AsgnRVal::Expr( expr ) => match expr.as_primary_expr() {
+        Some(PrimaryExpr::LVal( lval )) => self.lval_check(lval),
+        Some(PrimaryExpr::FuncCall( func_call )) => match self.func_call_check(func_call)? {
+          FuncType::Void => Err(Error::SemanticError(format!("{} returns void, which has no value", func_call.ident))),
+          FuncType::BType(_) => Ok(()),
+        },
+        Some(PrimaryExpr::Str(_)) => Ok(()),
+        _ => self.expr_check(expr, Some(BType::Int)),
+      },
+      AsgnRVal::Note(_) => self.asgn_rval_check(asgn_rval, BType::Note),
+      AsgnRVal::Measure(_) => self.asgn_rval_check(asgn_rval, BType::Measure),
+      AsgnRVal::Phrase(_) | AsgnRVal::DrumGrid(_) => self.asgn_rval_check(asgn_rval, BType::Phrase),
+      AsgnRVal::Track(_) => self.asgn_rval_check(asgn_rval, BType::Track),
+    }
+  }
 }
\ No newline at end of file
@@ -29,11 +29,13 @@ impl Analyzer {
     // 进入 Block，必须先添加到 Blocks 和 Scopes 表再进入该 Block
     self.set_current_block(block.get_id())?;
 
-    // 遍历并检查所有 stmt
-    let stmts = &block.stmts;
-    for stmt in stmts {
+    // 遍历并检查所有 stmt，之后恢复为包含这一 Block 的语句的位置
+    let cur_span = self.current_span;
+    for (stmt, span) in block.stmts.iter().zip(&block.spans) {
+      self.current_span = *span;
       self.stmt_check(&stmt)?;
     }
+    self.current_span = cur_span;
 
     // 恢复当前 Block Id
     self.set_current_block(cur_block_id)
@@ -62,8 +64,8 @@ impl Analyzer {
     self.set_current_block(block_id)?;
 
     // 遍历并检查所有 stmt
-    let stmts = &block.stmts;
-    for stmt in stmts {
+    for (stmt, span) in block.stmts.iter().zip(&block.spans) {
+      self.current_span = *span;
       self.stmt_check(&stmt)?;
     }
     Ok(())
@@ -105,13 +107,13 @@ impl Analyzer {
 
     // 函数参数视为声明的变量，进行声明检查
     for param in &func_def.func_fparams {
-      let FuncFParam{ident, rval} = param;
+      let FuncFParam{ident, rval, ..} = param;
       scope.decl(ident, false, rval.clone())?;  /* 函数没有父级 Block，无需检查上层 */
     }
 
     // 遍历并检查所有 stmt
-    let stmts = &block.stmts;
-    for stmt in stmts {
+    for (stmt, span) in block.stmts.iter().zip(&block.spans) {
+      self.current_span = *span;
       self.stmt_check(&stmt)?;
     }
     
@@ -140,14 +142,14 @@ impl Analyzer {
     self.set_current_block(block.get_id())?;
 
     // 遍历并检查所有 stmt
-    let stmts = &block.stmts;
-    for stmt in stmts {
+    for (stmt, span) in block.stmts.iter().zip(&block.spans) {
+      self.current_span = *span;
       self.stmt_check(&stmt)?;
     }
 
     // 遍历检查所有 channel stmt
-    let stmts = &score.channel_stmts;
-    for stmt in stmts {
+    for (stmt, span) in score.channel_stmts.iter().zip(&score.channel_spans) {
+      self.current_span = *span;
       self.channel_stmt_check(&stmt)?;
     }
     
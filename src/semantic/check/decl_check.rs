@@ -8,7 +8,7 @@ impl Analyzer {
   pub fn const_decl_check(&mut self, const_decl: &ConstDecl) -> Result<(), Error> {
     let len = const_decl.const_defs.len();
     for i in 0..len {
-      let ConstDef{ident, rval: asgn_rval} = &const_decl.const_defs[i];
+      let ConstDef{ident, rval: asgn_rval, ..} = &const_decl.const_defs[i];
 
       self.get_current_scope().decl(
         ident,
@@ -28,7 +28,7 @@ impl Analyzer {
   pub fn var_decl_check(&mut self, var_decl: &VarDecl) -> Result<(), Error> {
     let len = var_decl.var_defs.len();
     for i in 0..len {
-      let VarDef{ident, rval_} =  &var_decl.var_defs[i];
+      let VarDef{ident, rval_, ..} =  &var_decl.var_defs[i];
 
       self.get_current_scope().decl(
         ident,
@@ -62,12 +62,11 @@ impl Analyzer {
                   PrimaryExpr::LVal( lval ) => {
                     self.lval_check(lval)?;
 
-                    match flag2 && expect_type_.is_some() {
-                      true => Ok(()),  // 表达式只有这一个 unary_expr 且没有任何运算,不需要检查类型
-                      false => type_check(
-                        lval.rval.borrow().clone().unwrap().get_btype(),
-                        expect_type_.unwrap()
-                      ),
+                    let btype = lval.rval.borrow().clone().unwrap().get_btype();
+                    match (flag2, expect_type_) {
+                      (true, _) => Ok(()),  // 表达式只有这一个 unary_expr 且没有任何运算,不需要检查类型
+                      (false, Some( expect_type )) => type_check(btype, expect_type),
+                      (false, None) => type_check(btype, BType::Int),  // 有运算时必须为 int/bool
                     }?;
                   },
                   PrimaryExpr::FuncCall( func_call ) => {
@@ -118,6 +117,13 @@ impl Analyzer {
                     }?;
                   },
                   PrimaryExpr::Number( _ ) => {}
+                  PrimaryExpr::Str( _ ) => match (flag2, expect_type_) {
+                    (true, Some( expect_type )) => type_check(BType::Str, expect_type)?,
+                    (true, None) => {},
+                    (false, _) => return Err(Error::SemanticError(
+                      "calculating string is not supported yet".to_string()
+                    )),
+                  },
                 }
               }
             }
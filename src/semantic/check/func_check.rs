@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::{ast::{expr::LOrExpr, func::{FuncCall, FuncDef, FuncType}}, error::Error};
+use crate::{ast::{expr::LOrExpr, func::{FuncCall, FuncDef, FuncType}}, builtin::Builtin, error::Error};
 
 use super::Analyzer;
 
@@ -11,7 +11,7 @@ impl Analyzer {
   pub fn func_call_check(&mut self, func_call: &FuncCall) -> Result<FuncType, Error> {
     let cur_block_id = self.current_block_id;
     
-    let scope = self.get_current_scope();
+    let mut scope = self.get_current_scope();
 
     let mut func_def_ = scope.func_call_check(func_call)?;
     
@@ -20,13 +20,18 @@ impl Analyzer {
 
       let parent_id_ = block.get_parent_id();
       if parent_id_.is_none() {
-        // 已经找遍所有父级 Block 了，函数不存在
-        return Err(Error::SemanticError(format!("{} is not defined", func_call.ident)));
+        // 已经找遍所有父级 Block 了，尝试内置函数
+        self.set_current_block(cur_block_id)?;
+        return match Builtin::from_ident(&func_call.ident) {
+          Some( builtin ) => self.builtin_call_check(func_call, builtin),
+          None => Err(Error::SemanticError(format!("{} is not defined", func_call.ident))),
+        };
       }
       let parent_id = parent_id_.unwrap();
       
       // 进入父级 Block
       self.set_current_block(parent_id)?;
+      scope = self.get_current_scope();
       
       func_def_ = scope.func_call_check(func_call)?;
     }
@@ -46,6 +51,27 @@ impl Analyzer {
     Ok(func_def_.clone().unwrap().func_type)
   }
 
+  /// 内置函数调用的检查，绑定内置函数并检查参数数量和类型
+  fn builtin_call_check(&mut self, func_call: &FuncCall, builtin: Builtin) -> Result<FuncType, Error> {
+    let argc = func_call.func_rparams.len();
+    let param_types = match builtin.param_types(argc) {
+      Some( param_types ) => param_types,
+      None => return Err(Error::SemanticError(format!(
+        "builtin function {} can not be called with {argc} params", builtin.ident()
+      ))),
+    };
+
+    for (asgn_rval, expect_type_) in func_call.func_rparams.iter().zip(param_types) {
+      match expect_type_ {
+        Some( expect_type ) => self.asgn_rval_check(asgn_rval, expect_type)?,
+        None => self.any_asgn_rval_check(asgn_rval)?,
+      }
+    }
+
+    func_call.bind_builtin(builtin);
+    Ok(builtin.func_type())
+  }
+
   /// 以 FuncDef 为单位进行语义检查。
   pub fn func_def_check(&mut self, func_def: Rc<FuncDef>) -> Result<(), Error> {
     // 获取当前作用域
@@ -60,12 +86,11 @@ impl Analyzer {
   /// return 类型是否符合函数定义的检查
   pub fn return_check(&mut self, expr_: &Option<LOrExpr>) -> Result<(), Error> {
     let mut block = self.get_current_block();
-    let mut parent_id_ = block.get_parent_id();
     let mut func_def_ = block.func.clone().borrow().clone();
 
-    while func_def_.is_none() && parent_id_.is_some() {
-      block = self.get_current_block();
-      parent_id_ = block.get_parent_id();
+    // 逐层向上找到所属的函数定义 Block
+    while let (None, Some( parent_id )) = (&func_def_, block.get_parent_id()) {
+      block = self.get_block_by_id(&parent_id)?.clone();
       func_def_ = block.func.clone().borrow().clone();
     }
 
@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::ast::expr::{Expr, PrimaryExpr};
+use crate::ast::instrument::InstrumentDef;
+use crate::ast::val::{BType, RVal, Value};
+use crate::error::Error;
+use crate::gm;
+use crate::synth::Waveform;
+
+use super::Analyzer;
+
+impl Analyzer {
+  /// 检查乐器定义：名称不能重复，参数不能重复，且只能是
+  /// wave(波形名称)、program(General MIDI 乐器)和 attack、decay、sustain、release、cutoff(整数)
+  pub fn instrument_def_check(&mut self, instrument_def: &InstrumentDef) -> Result<(), Error> {
+    let InstrumentDef{ident, params, ..} = instrument_def;
+    if !self.instruments.insert(ident.clone()) {
+      return Err(Error::SemanticError(format!("instrument '{ident}' is defined more than once")));
+    }
+
+    let mut names = HashSet::new();
+    for param in params {
+      let name = param.name.as_str();
+      if !names.insert(name) {
+        return Err(Error::SemanticError(format!(
+          "parameter '{name}' is set more than once in instrument '{ident}'"
+        )));
+      }
+      match name {
+        "wave" => self.waveform_check(&param.value)?,
+        "program" => self.instrument_name_check(&param.value, false)?,
+        "attack" | "decay" | "sustain" | "release" | "cutoff" => self.expr_check(&param.value, Some(BType::Int))?,
+        _ => return Err(Error::SemanticError(format!(
+          "unknown parameter '{name}' in instrument '{ident}', expect wave, attack, decay, sustain, release, cutoff or program"
+        ))),
+      }
+    }
+    Ok(())
+  }
+
+  /// 检查乐器表达式。字符串或未定义的标识符(如 violin)作为乐器名称解析：
+  /// synth 为 true 时先查找乐谱中定义的乐器，绑定为其名称的字符串常量；
+  /// 否则查找 General MIDI 乐器，绑定为对应编号的常量。
+  /// 打击乐名称(如 kick)在这里不是乐器，同样报告无法识别的乐器
+  pub fn instrument_name_check(&mut self, instrument: &Expr, synth: bool) -> Result<(), Error> {
+    match instrument.as_primary_expr() {
+      Some(PrimaryExpr::Str( name )) => self.instrument_str_check(name, synth),
+      Some(PrimaryExpr::LVal( lval )) if !self.scoped_lval_check(lval)? => {
+        if synth && self.instruments.contains(&lval.ident) {
+          lval.bind_rval(Rc::new(RVal::new_with_value(Value::Str(lval.ident.clone()))));
+          return Ok(());
+        }
+        match gm::instrument_by_name(&lval.ident) {
+          Some( program ) => {
+            lval.bind_rval(Rc::new(RVal::new_with_value(Value::Int(program))));
+            Ok(())
+          },
+          None => Err(unknown_instrument(&lval.ident)),
+        }
+      },
+      _ => self.expr_check(instrument, Some(BType::Int)),
+    }
+  }
+
+  /// 检查字符串给出的乐器名称，synth 为 true 时可以是乐谱中定义的乐器
+  pub fn instrument_str_check(&self, name: &str, synth: bool) -> Result<(), Error> {
+    match synth && self.instruments.contains(name) {
+      true => Ok(()),
+      false => gm::instrument_by_name(name).map(|_| ()).ok_or_else(|| unknown_instrument(name)),
+    }
+  }
+
+  /// 检查波形，可以是字符串或未定义的标识符(如 saw)，后者绑定为波形名称的字符串常量
+  fn waveform_check(&mut self, wave: &Expr) -> Result<(), Error> {
+    match wave.as_primary_expr() {
+      Some(PrimaryExpr::LVal( lval )) if self.lval_check(lval).is_err() => match Waveform::from_name(&lval.ident) {
+        Some(_) => {
+          lval.bind_rval(Rc::new(RVal::new_with_value(Value::Str(lval.ident.clone()))));
+          Ok(())
+        },
+        None => Err(unknown_waveform(&lval.ident)),
+      },
+      Some(PrimaryExpr::Str( name )) if Waveform::from_name(name).is_none() => Err(unknown_waveform(name)),
+      _ => self.expr_check(wave, Some(BType::Str)),
+    }
+  }
+}
+
+/// 无法识别的乐器名称，附带相近名称的提示
+fn unknown_instrument(name: &str) -> Error {
+  Error::SemanticError(format!(
+    "unknown instrument '{name}'{}", gm::did_you_mean(&gm::suggest_instruments(name))
+  ))
+}
+
+/// 无法识别的波形名称
+fn unknown_waveform(name: &str) -> Error {
+  Error::SemanticError(format!(
+    "unknown waveform '{name}', expect sine, square, saw, triangle or noise"
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::semantic::Analyzer as SemanticAnalyzer;
+  use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+  use super::*;
+
+  fn check_instrument(instrument: &str) -> Result<(), Error> {
+    let source = format!("int bass = 33;\n@score {{\n  @0 -> {instrument};\n}}\n");
+    let comp_unit = SyntacticAnalyzer::new().parse(&source)?;
+    SemanticAnalyzer::new().check(&comp_unit)
+  }
+
+  #[test]
+  fn instrument_names_and_variables() {
+    assert!(check_instrument("violin").is_ok());
+    assert!(check_instrument("\"Acoustic Grand Piano\"").is_ok());
+    assert!(check_instrument("bass").is_ok());
+  }
+
+  #[test]
+  fn percussion_names_are_unknown_instruments() {
+    for name in ["cowbell", "kick", "closed_hat"] {
+      match check_instrument(name) {
+        Err(Error::SemanticError( msg )) => assert!(msg.starts_with(&format!("unknown instrument '{name}'")), "{msg}"),
+        res => panic!("expect unknown instrument {name}, but found {res:?}"),
+      }
+    }
+  }
+}
@@ -1,12 +1,32 @@
-use crate::{ast::val::LVal, error::Error};
+use std::rc::Rc;
+
+use crate::{ast::val::{LVal, RVal, Value}, error::Error, gm};
 
 use super::Analyzer;
 
 
 impl Analyzer {
   /// 变量或常量调用的检查。
-  /// 从这一级 Block 开始不断往上层 Block 检查符号是否存在。
+  /// 从这一级 Block 开始不断往上层 Block 检查符号是否存在，
+  /// 都不存在时最后尝试作为内置的 General MIDI 打击乐名称。
   pub fn lval_check(&mut self, lval: &LVal) -> Result<(), Error> {
+    if self.scoped_lval_check(lval)? {
+      return Ok(());
+    }
+    match gm::percussion_by_name(&lval.ident) {
+      Some( note ) => {
+        lval.bind_rval(Rc::new(RVal::new_with_value(Value::Int(note))));
+        Ok(())
+      },
+      None => Err(Error::SemanticError(format!(
+        "{} is not defined{}", lval.ident, gm::did_you_mean(&gm::suggest_percussion(&lval.ident))
+      ))),
+    }
+  }
+
+  /// 只在各级 Block 中查找变量或常量，找到时绑定其 RVal 并返回 true。
+  /// 从这一级 Block 开始不断往上层 Block 检查符号是否存在。
+  pub fn scoped_lval_check(&mut self, lval: &LVal) -> Result<bool, Error> {
     let cur_block_id = self.current_block_id;
     
     let mut scope = self.get_current_scope();
@@ -18,8 +38,9 @@ impl Analyzer {
 
       let parent_id_ = block.get_parent_id();
       if parent_id_.is_none() {
-        // 已经找遍所有父级 Block 了，该 LVal 不存在
-        return Err(Error::SemanticError(format!("{} is not defined", lval.ident)));
+        // 已经找遍所有父级 Block 了
+        self.set_current_block(cur_block_id)?;
+        return Ok(false);
       }
       let parent_id = parent_id_.unwrap();
       
@@ -37,6 +58,6 @@ impl Analyzer {
     // 绑定 RVal
     lval.bind_rval(rval_.unwrap());
 
-    Ok(())
+    Ok(true)
   }
 }
\ No newline at end of file
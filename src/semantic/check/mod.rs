@@ -8,6 +8,10 @@ pub mod while_check;
 pub mod ifelse_check;
 pub mod block_check;
 pub mod asgn_rval_check;
+pub mod instrument_check;
+pub mod repl_check;
+
+use std::collections::HashSet;
 
 use crate::{ast::comp_unit::CompUnit, error::Error};
 
@@ -18,8 +22,29 @@ impl Analyzer {
   pub fn check(&mut self, comp_unit: &CompUnit) -> Result<(), Error> {
     // 进行 Block 为单位的语义检查
     self.global_block_check(comp_unit.block.clone())?;
-    
+
+    // 检查乐器定义，参数可以使用全局常量和变量
+    for instrument_def in &comp_unit.instruments {
+      self.current_span = instrument_def.span;
+      self.instrument_def_check(instrument_def)?;
+    }
+
     // 对 Score 进行语义检查
     self.score_check(&comp_unit.score)
   }
+
+  /// 检查 comp_unit 中的测试，须在 check 之后进行。测试的 Block 的父级 Block 为全局 Block，
+  /// 可以使用全局常量、变量和函数
+  pub fn tests_check(&mut self, comp_unit: &CompUnit) -> Result<(), Error> {
+    self.set_current_block(self.get_global_block())?;
+    let mut names = HashSet::new();
+    for test_def in &comp_unit.tests {
+      self.current_span = test_def.span;
+      if !names.insert(&test_def.name) {
+        return Err(Error::SemanticError(format!("test \"{}\" is defined more than once", test_def.name)));
+      }
+      self.block_check(test_def.block.clone())?;
+    }
+    Ok(())
+  }
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+use std::rc::Rc;
+
+use crate::ast::block::Block;
+use crate::ast::expr::PrimaryExpr;
+use crate::ast::repl::{ReplItem, ReplUnit};
+use crate::ast::span::Span;
+use crate::ast::stmt::{AsgnRVal, Stmt};
+use crate::error::Error;
+
+use super::Analyzer;
+
+impl Analyzer {
+  /// 以交互式解释器的一次输入为单位进行语义检查，全局作用域在多次输入之间保持。
+  /// 第一次检查前建立空的全局 Block；已经定义的全局符号和乐器可以在之后的输入中重新定义，
+  /// 之前的输入中对它的引用不受影响。检查出错时撤销这次输入对全局作用域的修改
+  pub fn repl_check(&mut self, repl_unit: &ReplUnit) -> Result<(), Error> {
+    if self.scope_table.is_empty() {
+      self.global_block_check(Rc::new(Block::new(vec![], Span::default())))?;
+    }
+    self.set_current_block(self.get_global_block())?;
+
+    let scope = self.get_current_scope();
+    let symbol_table = scope.save();
+    let instruments = self.instruments.clone();
+
+    let res = match repl_unit {
+      ReplUnit::Items( items ) => self.repl_items_check(items),
+      ReplUnit::Eval( asgn_rval ) => self.eval_check(asgn_rval),
+      ReplUnit::Score( score ) => self.score_check(score),
+    };
+    if res.is_err() {
+      scope.restore(symbol_table);
+      self.instruments = instruments;
+      self.set_current_block(self.get_global_block())?;
+    }
+    res
+  }
+
+  /// 检查一次输入中的各项，先删除这次输入重新定义的全局符号和乐器
+  fn repl_items_check(&mut self, items: &[(ReplItem, Span)]) -> Result<(), Error> {
+    let scope = self.get_current_scope();
+    for (item, _) in items {
+      match item {
+        ReplItem::Stmt(Stmt::ConstDecl( const_decl )) => for const_def in &const_decl.const_defs {
+          scope.remove(&const_def.ident);
+        },
+        ReplItem::Stmt(Stmt::VarDecl( var_decl )) => for var_def in &var_decl.var_defs {
+          scope.remove(&var_def.ident);
+        },
+        ReplItem::Stmt(Stmt::FuncDef( func_def )) => scope.remove(&func_def.ident),
+        ReplItem::InstrumentDef( instrument_def ) => {
+          self.instruments.remove(&instrument_def.ident);
+        },
+        ReplItem::Stmt(_) => (),
+      }
+    }
+
+    for (item, span) in items {
+      self.current_span = *span;
+      match item {
+        ReplItem::Stmt( stmt ) => self.stmt_check(stmt)?,
+        ReplItem::InstrumentDef( instrument_def ) => self.instrument_def_check(instrument_def)?,
+      }
+    }
+    Ok(())
+  }
+
+  /// 检查求值的右值，可以是任意类型；单独调用 void 函数时不显示值
+  fn eval_check(&mut self, asgn_rval: &AsgnRVal) -> Result<(), Error> {
+    if let AsgnRVal::Expr( expr ) = asgn_rval && let Some(PrimaryExpr::FuncCall( func_call )) = expr.as_primary_expr() {
+      return self.func_call_check(func_call).map(|_| ());
+    }
+    self.any_asgn_rval_check(asgn_rval)
+  }
+}
@@ -1,7 +1,5 @@
-use crate::ast::func::FuncType;
-use crate::ast::score::{ScoreStmt, SetChannelInstrument, SetChannelTrack, SetTimeSignature};
+use crate::ast::score::{AddText, Beat, KeyMode, ScoreStmt, TuningKind, Instrument, SetChannelControl, SetChannelInstrument, SetChannelName, SetChannelTrack, SetTimeSignature, TrackPosition};
 use crate::ast::stmt::Stmt;
-use crate::ast::track::TrackRVal;
 use crate::ast::val::BType;
 use crate::error::Error;
 
@@ -25,6 +23,7 @@ impl Analyzer {
         true => self.expr_check(expr_.as_ref().unwrap(), None),
         false => Ok(()),
       },
+      Stmt::Assert( assert ) => self.expr_check(&assert.cond, Some(BType::Int)),
     }
   }
 
@@ -32,46 +31,110 @@ impl Analyzer {
   pub fn channel_stmt_check(&mut self, stmt: &ScoreStmt) -> Result<(), Error> {
     match stmt {
       ScoreStmt::SetChannelInstrument(SetChannelInstrument{channel, instrument}) => {
-        match self.expr_check(channel, Some(BType::Int)) {
-          Ok(()) => self.expr_check(instrument, Some(BType::Int)),
-          Err(e) => Err(e)
+        self.expr_check(channel, Some(BType::Int))?;
+        match instrument {
+          Instrument::Program( program ) => self.instrument_name_check(program, true),
+          Instrument::Name( name ) => self.instrument_str_check(name, true),
         }
       },
-      ScoreStmt::SetChannelTrack( SetChannelTrack{channel, track} ) => {
-        match self.expr_check(channel, Some(BType::Int)) {
-          Ok(()) => match track {
-            TrackRVal::Track( track ) => self.track_check(track),
-            TrackRVal::LVal( lval ) => {
-              self.lval_check(lval)?;
-
-              let ret_type = lval.rval.borrow().as_ref().unwrap().get_btype();
-              match ret_type != BType::Track {
-                true => Err(Error::SemanticError(format!("expect track, but found {ret_type}"))),
-                false => Ok(())
-              }
-            },
-            TrackRVal::FuncCall( func_call ) => {
-              let ret_type = self.func_call_check(func_call)?;
-
-              match ret_type {
-                FuncType::Void => Err(Error::SemanticError(format!("expect track, but found void"))),
-                FuncType::BType( ret_type ) => {
-                  match ret_type != BType::Track {
-                    true => Err(Error::SemanticError(format!("expect track, but found {ret_type}"))),
-                    false => Ok(())
-                  }
-                }
-              }
-            }
-          },
-          Err(e) => Err(e)
+      ScoreStmt::SetChannelTrack( SetChannelTrack{channel, tracks, position} ) => {
+        self.expr_check(channel, Some(BType::Int))?;
+        for track in tracks {
+          self.track_rval_check(track)?;
+        }
+        match position {
+          Some( position ) => self.track_position_check(position),
+          None => Ok(()),
         }
       },
       ScoreStmt::SetTimeSignature( SetTimeSignature{top_num, bottom_num} ) => {
         self.expr_check(top_num, Some(BType::Int))?;
         self.expr_check(bottom_num, Some(BType::Int))
       },
-      ScoreStmt::SetTempo( expr ) => self.expr_check(expr, Some(BType::Int))
+      ScoreStmt::SetTempo( expr ) => self.expr_check(expr, Some(BType::Int)),
+      ScoreStmt::SetPpq( expr ) => self.expr_check(expr, Some(BType::Int)),
+      ScoreStmt::SetChannelName( SetChannelName{channel, name} ) |
+      ScoreStmt::SetChannelInstrumentName( SetChannelName{channel, name} ) => {
+        self.expr_check(channel, Some(BType::Int))?;
+        self.expr_check(name, Some(BType::Str))
+      },
+      ScoreStmt::SetChannelVolume( SetChannelControl{channel, value} ) |
+      ScoreStmt::SetChannelPan( SetChannelControl{channel, value} ) => {
+        self.expr_check(channel, Some(BType::Int))?;
+        self.expr_check(value, Some(BType::Int))
+      },
+      ScoreStmt::SetTitle( expr ) => self.expr_check(expr, Some(BType::Str)),
+      ScoreStmt::AddMarker( AddText{text, position} ) |
+      ScoreStmt::AddCuePoint( AddText{text, position} ) => {
+        self.expr_check(text, Some(BType::Str))?;
+        self.track_position_check(position)
+      },
+      ScoreStmt::SetTuning( tuning ) => {
+        let kind = tuning.kind().ok_or(Error::SemanticError(format!(
+          "unknown tuning '{}', expect one of equal, just, meantone, cents, scala", tuning.name
+        )))?;
+        match kind {
+          TuningKind::Equal | TuningKind::Just | TuningKind::Meantone => if !tuning.args.is_empty() {
+            return Err(Error::SemanticError(format!(
+              "tuning {} takes no params", tuning.name
+            )));
+          },
+          TuningKind::Cents => {
+            if tuning.args.is_empty() {
+              return Err(Error::SemanticError(
+                "tuning cents needs at least one param".to_string()
+              ));
+            }
+            for arg in &tuning.args {
+              self.expr_check(arg, Some(BType::Int))?;
+            }
+          },
+          TuningKind::Scala => match tuning.args.as_slice() {
+            [path] => self.expr_check(path, Some(BType::Str))?,
+            _ => return Err(Error::SemanticError(
+              "tuning scala takes exactly one param, the path of .scl file".to_string()
+            )),
+          },
+        }
+        match &tuning.root {
+          Some( root ) => self.expr_check(root, Some(BType::Int)),
+          None => Ok(()),
+        }
+      },
+      ScoreStmt::SetKeySignature( key_sig ) => {
+        let mode = match key_sig.mode {
+          KeyMode::Major => "major",
+          KeyMode::Minor => "minor",
+        };
+        match key_sig.fifths() {
+          Some( fifths ) if (-7..=7).contains(&fifths) => {},
+          Some(_) => return Err(Error::SemanticError(format!(
+            "key signature {} {mode} needs more than 7 sharps or flats", key_sig.tonic
+          ))),
+          None => return Err(Error::SemanticError(format!(
+            "invalid tonic '{}' of key signature, expect A-G optionally followed by b or #", key_sig.tonic
+          ))),
+        }
+        match &key_sig.position {
+          Some( position ) => self.track_position_check(position),
+          None => Ok(()),
+        }
+      },
+    }
+  }
+
+  /// 检查绝对位置中的小节和拍
+  fn track_position_check(&mut self, position: &TrackPosition) -> Result<(), Error> {
+    match position {
+      TrackPosition::Bar(bar, beat_) => {
+        self.expr_check(bar, Some(BType::Int))?;
+        match beat_ {
+          Some(Beat::Expr( beat )) => self.expr_check(beat, Some(BType::Int)),
+          _ => Ok(()),
+        }
+      },
+      TrackPosition::Beat(Beat::Expr( beat )) => self.expr_check(beat, Some(BType::Int)),
+      _ => Ok(()),
     }
   }
-}
\ No newline at end of file
+}
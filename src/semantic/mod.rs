@@ -4,11 +4,12 @@ pub mod scope_table;
 pub mod symbol;
 pub mod check;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use block_scope::BlockScope;
 
 use crate::ast::block::{Block, BlockId};
+use crate::ast::span::Span;
 use crate::error::Error;
 
 /// 语义分析器
@@ -34,6 +35,12 @@ pub struct Analyzer {
 
   /// Block 表。关于它的函数方法在同名文件实现。
   block_table: HashMap<BlockId, Rc<Block>>,
+
+  /// 用内置合成器定义的乐器名称
+  instruments: HashSet<String>,
+
+  /// 当前分析检查到的语句在源文件中的位置，检查出错时即为出错的语句的位置
+  current_span: Span,
 }
 
 impl Analyzer {
@@ -46,6 +53,8 @@ impl Analyzer {
       current_loop: 0,
       scope_table: HashMap::new(),
       block_table: HashMap::new(),
+      instruments: HashSet::new(),
+      current_span: Span::default(),
     }
   }
 
@@ -77,6 +86,11 @@ impl Analyzer {
     self.current_block.clone().unwrap()
   }
 
+  /// 获取当前分析检查到的语句在源文件中的位置，检查出错后即为出错的语句的位置
+  pub fn get_current_span(&self) -> Span {
+    self.current_span
+  }
+
   /// 设置当前分析检查到的 Block 的 Id
   pub fn get_current_scope(&self) -> Rc<BlockScope> {
     self.current_scope.clone().unwrap()
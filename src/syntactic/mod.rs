@@ -1,11 +1,99 @@
+use std::fmt::Display;
+
 use lalrpop_util::{lalrpop_mod, ParseError};
 lalrpop_mod!(yam);
-use yam::CompUnitParser;
+use yam::{CompUnitParser, ReplUnitParser};
 use crate::ast::comp_unit::CompUnit;
+use crate::ast::expr::{Expr, PrimaryExpr};
+use crate::ast::repl::ReplUnit;
+use crate::ast::span::Span;
+use crate::ast::stmt::{AsgnRVal, Assert, Stmt};
 use crate::error::Error;
 
+/// 语法规则的动作中报告的错误及其位置，如拼错的上下文关键字
+#[derive(Debug)]
+pub struct ActionError {
+  message: String,
+  span: Span,
+}
+
+impl ActionError {
+  pub fn new(message: impl Into<String>, span: Span) -> Self {
+    Self { message: message.into(), span }
+  }
+}
+
+/// 语法规则的动作的结果
+type ActionResult<R, T> = Result<R, ParseError<usize, T, ActionError>>;
+
+/// 检查上下文关键字。上下文关键字只在特定位置有特殊含义，按标识符解析，其余位置仍可用作标识符
+pub(crate) fn expect_keyword<T>(keyword: &(String, Span), expected: &[&str]) -> ActionResult<(), T> {
+  if expected.contains(&keyword.0.as_str()) {
+    return Ok(());
+  }
+  let expected = expected.iter().map(|word| format!("'{word}'")).collect::<Vec<_>>();
+  let expected = match expected.as_slice() {
+    [init @ .., last] if !init.is_empty() => format!("{} or {last}", init.join(", ")),
+    _ => expected.join(""),
+  };
+  Err(ParseError::User { error: ActionError::new(format!("expect {expected}, but found '{}'", keyword.0), keyword.1) })
+}
+
+/// 调号的主音，如 C、Bb 或 F#，按表达式解析后检查是否只是一个标识符
+pub(crate) fn key_tonic<T>(tonic: Expr, sharp: bool, span: Span) -> ActionResult<String, T> {
+  match tonic.into_primary_expr() {
+    Some(PrimaryExpr::LVal( lval )) if sharp => Ok(format!("{}#", lval.ident)),
+    Some(PrimaryExpr::LVal( lval )) => Ok(lval.ident),
+    _ => Err(ParseError::User { error: ActionError::new("expect a tonic such as C, Bb or F#", span) }),
+  }
+}
+
+/// 音律的名称及参数，如 just 或 cents(0, 100, ...)，按表达式解析后检查是否为标识符或函数调用
+pub(crate) fn tuning<T>(tuning: Expr, span: Span) -> ActionResult<(String, Vec<Expr>), T> {
+  let error = || ParseError::User { error: ActionError::new("expect a tuning such as equal or cents(...)", span) };
+  match tuning.into_primary_expr() {
+    Some(PrimaryExpr::LVal( lval )) => Ok((lval.ident, vec![])),
+    Some(PrimaryExpr::FuncCall( func_call )) => {
+      let args = func_call.func_rparams.into_iter()
+        .map(|asgn_rval| match asgn_rval {
+          AsgnRVal::Expr( expr ) => Ok(expr),
+          _ => Err(error()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+      Ok((func_call.ident, args))
+    },
+    _ => Err(error()),
+  }
+}
+
+/// 表达式语句。assert 是上下文关键字，写法与函数调用相同的 assert(cond) 或 assert(cond, "说明") 语句是断言
+pub(crate) fn expr_stmt<T>(expr: Option<Expr>) -> ActionResult<Stmt, T> {
+  let expr = match expr {
+    Some( expr ) if matches!(expr.as_primary_expr(), Some(PrimaryExpr::FuncCall( call )) if call.ident == "assert") => expr,
+    expr => return Ok(Stmt::Expr( expr )),
+  };
+  let Some(PrimaryExpr::FuncCall( call )) = expr.into_primary_expr() else {
+    unreachable!("the statement is checked to be a call of assert");
+  };
+  let span = call.span;
+  let error = || ParseError::User { error: ActionError::new("assert takes a condition and an optional string message", span) };
+  let mut args = call.func_rparams.into_iter();
+  let (cond, message) = match (args.next(), args.next(), args.next()) {
+    (Some(AsgnRVal::Expr( cond )), None, None) => (cond, None),
+    (Some(AsgnRVal::Expr( cond )), Some(AsgnRVal::Expr( message )), None) => match message.into_primary_expr() {
+      Some(PrimaryExpr::Str( message )) => (cond, Some(message)),
+      _ => return Err(error()),
+    },
+    _ => return Err(error()),
+  };
+  Ok(Stmt::Assert( Assert{ cond, message } ))
+}
+
 pub struct Analyzer {
   parser: CompUnitParser,
+
+  /// 交互式解释器输入的语法解析器
+  repl_parser: ReplUnitParser,
 }
 
 /// 语法分析器
@@ -13,32 +101,129 @@ impl Analyzer {
   pub fn new() -> Self {
     Self {
       parser: CompUnitParser::new(),
+      repl_parser: ReplUnitParser::new(),
     }
   }
 
   pub fn parse<'input>(&self, input: &'input str) -> Result<CompUnit, Error> {
-    self.parser.parse(input).map_err(
-      |err| Error::ParseError(
-        match err {
-          ParseError::InvalidToken { location } => {
-            format!("Invalid token found at {}", location)
-          },
-          ParseError::UnrecognizedEof { location, expected } => {
-            format!("Unexpected EOF found at {}, expected tokens are:\n{:#?}", location, expected)
-          },
-          ParseError::UnrecognizedToken { token, .. } => {
-            let (location_start, t, location_end) = token;
-            format!("Token '{}' is not recognized, location: {} to {}", t, location_start, location_end)
-          },
-          ParseError::ExtraToken { token, .. } => {
-            let (location_start, t, location_end) = token;
-            format!("Token '{}' is extra, location: {} to {}", t, location_start, location_end)
-          },
-          ParseError::User { error } => {
-            error.to_string()
-          },
-        }
+    self.parse_with_span(input).map_err(|(err, _)| err)
+  }
+
+  /// 与 parse 相同，出错时同时给出出错的位置
+  pub fn parse_with_span(&self, input: &str) -> Result<CompUnit, (Error, Span)> {
+    self.parser.parse(input).map_err(parse_error)
+  }
+
+  /// 解析交互式解释器的一次输入，出错时同时给出出错的位置
+  pub fn parse_repl(&self, input: &str) -> Result<ReplUnit, (Error, Span)> {
+    self.repl_parser.parse(input).map_err(parse_error)
+  }
+}
+
+/// 将 lalrpop 的错误转换为 ParseError 及出错的位置
+fn parse_error<T: Display>(err: ParseError<usize, T, ActionError>) -> (Error, Span) {
+  match err {
+    ParseError::InvalidToken { location } => (
+      Error::ParseError(format!("Invalid token found at {}", location)),
+      Span::new(location, location),
+    ),
+    ParseError::UnrecognizedEof { location, expected } => (
+      Error::ParseError(format!("Unexpected EOF found at {}, expected tokens are:\n{:#?}", location, expected)),
+      Span::new(location, location),
+    ),
+    ParseError::UnrecognizedToken { token, .. } => {
+      let (location_start, t, location_end) = token;
+      (
+        Error::ParseError(format!("Token '{}' is not recognized, location: {} to {}", t, location_start, location_end)),
+        Span::new(location_start, location_end),
       )
-    )
+    },
+    ParseError::ExtraToken { token, .. } => {
+      let (location_start, t, location_end) = token;
+      (
+        Error::ParseError(format!("Token '{}' is extra, location: {} to {}", t, location_start, location_end)),
+        Span::new(location_start, location_end),
+      )
+    },
+    ParseError::User { error } => {
+      (Error::ParseError(error.message), error.span)
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse_beat(beat: &str) -> Result<CompUnit, Error> {
+    Analyzer::new().parse(&format!("@score {{\n  measure m = | 60 |;\n  @0 <- {{ [ @m ] }} at beat {beat};\n}}\n"))
+  }
+
+  #[test]
+  fn decimal_with_trailing_zeros() {
+    assert!(parse_beat("1.0000000000").is_ok());
+    assert!(parse_beat("1.0000000001").is_ok());
+    assert!(parse_beat("2.50000000000000000000").is_ok());
+  }
+
+  #[test]
+  fn decimal_out_of_range() {
+    for beat in ["1.0000000000000000001", "99999999999999999999.5"] {
+      match parse_beat(beat) {
+        Err(Error::ParseError( msg )) => assert_eq!(msg, "decimal literal is out of range"),
+        res => panic!("expect parse error for beat {beat}, but found {res:?}"),
+      }
+    }
+  }
+
+  #[test]
+  fn position_keywords_as_identifiers() {
+    let source = "int at = 1;\nint bar = 1;\nint beat = 2;\n@score {\n  measure m = | 60 |;\n  @0 <- { [ @m ] } at bar bar beat beat + at;\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+  }
+
+  #[test]
+  fn drums_as_identifier() {
+    let source = "int drums = 16;\n@score {\n  @9 <- { drums(drums) { kick: \"x...\"; } };\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+  }
+
+  #[test]
+  fn score_settings_as_identifiers() {
+    let source = "int title(int name) { return name; }\n@score {\n  int marker = title(1);\n  @0 name = \"piano\";\n  @title = \"song\";\n  @marker = \"intro\" at bar marker;\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+  }
+
+  #[test]
+  fn misspelled_contextual_keyword() {
+    match Analyzer::new().parse_with_span("@score {\n  @0 nmae = \"piano\";\n}\n") {
+      Err((Error::ParseError( msg ), span)) => {
+        assert_eq!(msg, "expect 'name', 'instrument', 'volume' or 'pan', but found 'nmae'");
+        assert_eq!(span, Span::new(14, 18));
+      },
+      res => panic!("expect parse error, but found {res:?}"),
+    }
+  }
+
+  #[test]
+  fn definition_keywords_as_identifiers() {
+    let source = "int instrument(int test) { return test; }\ninstrument pad { wave = \"sine\"; }\ntest \"instrument\" {\n  int test = instrument(1);\n}\n@score {\n  @0 -> pad;\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+  }
+
+  #[test]
+  fn string_as_identifier() {
+    let source = "int string(int x) { return x; }\n@score {\n  string s = \"piano\";\n  int n = string(1);\n  @n name = s;\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+  }
+
+  #[test]
+  fn assert_as_identifier() {
+    let source = "int assert = 1;\ntest \"assert\" {\n  assert(assert == 1, \"assert\");\n}\n@score {\n  @assert name = \"piano\";\n}\n";
+    assert!(Analyzer::new().parse(source).is_ok());
+    match Analyzer::new().parse("@score {\n  assert(1, 2);\n  @0 name = \"piano\";\n}\n") {
+      Err(Error::ParseError( msg )) => assert_eq!(msg, "assert takes a condition and an optional string message"),
+      res => panic!("expect parse error, but found {res:?}"),
+    }
   }
 }
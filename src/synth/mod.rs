@@ -0,0 +1,255 @@
+pub mod wav;
+pub mod soundfont;
+
+use std::f64::consts::PI;
+
+use soundfont::Region;
+
+/// 混音后整体的增益，避免多个声部叠加时过早削波
+const MASTER_GAIN: f64 = 0.25;
+
+/// 乐曲结束后额外保留的时间(秒)
+const TAIL_SECONDS: f64 = 0.1;
+
+/// 振荡器的波形
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+  Sine,
+  Square,
+  Saw,
+  Triangle,
+
+  /// 白噪声，与音高无关
+  Noise,
+}
+
+/// ADSR 包络，时间以秒为单位，sustain 为持续阶段的电平(0 到 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+  pub attack: f64,
+  pub decay: f64,
+  pub sustain: f64,
+  pub release: f64,
+}
+
+/// 合成一个音符所用的音色
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Patch {
+  pub waveform: Waveform,
+  pub envelope: Envelope,
+
+  /// 低通滤波器的截止频率(Hz)，为 None 时不滤波
+  pub cutoff: Option<f64>,
+}
+
+/// 音符的发声方式
+#[derive(Debug, Clone)]
+pub enum Sound {
+  /// 内置振荡器
+  Patch(Patch),
+
+  /// SoundFont 中的采样
+  Sample(Region),
+}
+
+/// 以秒计时的一个音符
+#[derive(Debug, Clone)]
+pub struct SynthNote {
+  /// 开始的时间(秒)
+  pub start: f64,
+
+  /// 松开的时间(秒)，之后进入包络的释放阶段
+  pub end: f64,
+
+  /// 频率(Hz)
+  pub frequency: f64,
+
+  /// 力度，0 到 1
+  pub velocity: f64,
+
+  pub sound: Sound,
+}
+
+/// 一个 channel 的所有音符及其混音设置
+#[derive(Debug, Clone)]
+pub struct SynthChannel {
+  pub notes: Vec<SynthNote>,
+
+  /// 音量，0 到 1
+  pub volume: f64,
+
+  /// 声像，-1 为最左，0 为中间，1 为最右
+  pub pan: f64,
+}
+
+impl Waveform {
+  /// 由名称 sine、square、saw、triangle、noise 查找波形
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "sine" => Some(Waveform::Sine),
+      "square" => Some(Waveform::Square),
+      "saw" => Some(Waveform::Saw),
+      "triangle" => Some(Waveform::Triangle),
+      "noise" => Some(Waveform::Noise),
+      _ => None,
+    }
+  }
+}
+
+impl Envelope {
+  pub const fn new(attack: f64, decay: f64, sustain: f64, release: f64) -> Self {
+    Self{attack, decay, sustain, release}
+  }
+
+  /// 音符开始 t 秒后、松开之前的电平
+  fn level(&self, t: f64) -> f64 {
+    if t < self.attack {
+      t / self.attack
+    } else if t < self.attack + self.decay {
+      1.0 - (1.0 - self.sustain) * (t - self.attack) / self.decay
+    } else {
+      self.sustain
+    }
+  }
+}
+
+impl Patch {
+  pub const fn new(waveform: Waveform, envelope: Envelope) -> Self {
+    Self{waveform, envelope, cutoff: None}
+  }
+
+  /// General MIDI 乐器(program 从 0 开始)对应的内置音色，按乐器的分类选择波形和包络
+  pub fn general_midi(program: u8) -> Self {
+    use Waveform::*;
+    match program / 8 {
+      // 钢琴
+      0 => Patch::new(Triangle, Envelope::new(0.005, 0.8, 0.3, 0.3)),
+      // 色彩打击乐器、打击乐器
+      1 | 14 => Patch::new(Sine, Envelope::new(0.002, 0.5, 0.0, 0.3)),
+      // 风琴
+      2 => Patch::new(Sine, Envelope::new(0.01, 0.0, 1.0, 0.05)),
+      // 吉他、民族乐器
+      3 | 13 => Patch::new(Triangle, Envelope::new(0.002, 0.6, 0.2, 0.2)),
+      // 贝斯
+      4 => Patch::new(Triangle, Envelope::new(0.005, 0.3, 0.6, 0.1)),
+      // 弦乐、合奏
+      5 | 6 => Patch::new(Saw, Envelope::new(0.1, 0.2, 0.8, 0.3)),
+      // 铜管
+      7 => Patch::new(Saw, Envelope::new(0.03, 0.1, 0.8, 0.1)),
+      // 簧管、合成主音
+      8 | 10 => Patch::new(Square, Envelope::new(0.02, 0.1, 0.8, 0.1)),
+      // 吹管
+      9 => Patch::new(Sine, Envelope::new(0.05, 0.1, 0.9, 0.1)),
+      // 合成音色垫
+      11 => Patch::new(Saw, Envelope::new(0.5, 0.5, 0.8, 0.8)),
+      // 合成效果
+      12 => Patch::new(Triangle, Envelope::new(0.2, 0.3, 0.7, 0.5)),
+      // 音效
+      _ => Patch::new(Noise, Envelope::new(0.01, 0.3, 0.5, 0.3)),
+    }
+  }
+
+  /// General MIDI 打击乐(音高为 note)的内置音色：底鼓为低频正弦波，镲为较长的噪声，其余为短促的噪声
+  pub fn percussion(note: u8) -> Self {
+    use Waveform::*;
+    match note {
+      35 | 36 => Patch::new(Sine, Envelope::new(0.001, 0.25, 0.0, 0.05)),
+      42 | 44 => Patch::new(Noise, Envelope::new(0.001, 0.08, 0.0, 0.03)),
+      49 | 51 | 52 | 55 | 57 | 59 => Patch::new(Noise, Envelope::new(0.001, 0.8, 0.0, 0.3)),
+      _ => Patch::new(Noise, Envelope::new(0.001, 0.2, 0.0, 0.05)),
+    }
+  }
+}
+
+/// 由 xorshift 生成的白噪声，种子固定以保证同一份乐谱每次渲染的结果相同
+struct Noise(u32);
+
+impl Noise {
+  fn next(&mut self) -> f64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 17;
+    self.0 ^= self.0 << 5;
+    self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0
+  }
+}
+
+/// 波形在相位 phase(0 到 1)处的值
+fn oscillate(waveform: Waveform, phase: f64, noise: &mut Noise) -> f64 {
+  match waveform {
+    Waveform::Sine => (2.0 * PI * phase).sin(),
+    Waveform::Square => match phase < 0.5 {
+      true => 1.0,
+      false => -1.0,
+    },
+    Waveform::Saw => 2.0 * phase - 1.0,
+    Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+    Waveform::Noise => noise.next(),
+  }
+}
+
+/// 用振荡器合成一个音符，返回从音符开始的单声道采样
+fn oscillate_note(note: &SynthNote, patch: Patch, rate: f64, noise: &mut Noise) -> Vec<f64> {
+  let Patch{waveform, envelope, cutoff} = patch;
+  let held = (note.end - note.start).max(0.0);
+  let release_level = envelope.level(held);
+  let frames = ((held + envelope.release) * rate).round() as usize;
+  // 一阶低通滤波器的系数，截止频率不低于 1 Hz 且不高于奈奎斯特频率
+  let alpha = cutoff.map(|cutoff| 1.0 - (-2.0 * PI * cutoff.clamp(1.0, rate / 2.0) / rate).exp());
+  let mut filtered = 0.0;
+  let mut phase = 0.0;
+  (0..frames).map(|frame| {
+    let t = frame as f64 / rate;
+    let level = match t < held {
+      true => envelope.level(t),
+      false if envelope.release > 0.0 => release_level * (1.0 - (t - held) / envelope.release).max(0.0),
+      false => 0.0,
+    };
+    let mut sample = oscillate(waveform, phase, noise);
+    if let Some( alpha ) = alpha {
+      filtered += alpha * (sample - filtered);
+      sample = filtered;
+    }
+    phase = (phase + note.frequency / rate).fract();
+    sample * level * note.velocity
+  }).collect()
+}
+
+/// 将各 channel 的音符合成并按音量和声像混合为交错的立体声采样，
+/// 混音结果超出 [-1, 1] 时整体缩小以免削波
+pub fn render(channels: &[SynthChannel], sample_rate: u32) -> Vec<f32> {
+  let rate = sample_rate as f64;
+  let duration = channels.iter()
+    .flat_map(|channel| channel.notes.iter())
+    .map(|note| note.end + match &note.sound {
+      Sound::Patch(patch) => patch.envelope.release,
+      Sound::Sample(region) => region.release(),
+    })
+    .fold(0.0, f64::max);
+  let frames = ((duration + TAIL_SECONDS) * rate).ceil() as usize;
+  let mut mix = vec![0.0_f64; frames * 2];
+  let mut noise = Noise(0x9e37_79b9);
+
+  for channel in channels {
+    for note in &channel.notes {
+      let (samples, pan) = match &note.sound {
+        Sound::Patch(patch) => (oscillate_note(note, *patch, rate, &mut noise), channel.pan),
+        Sound::Sample(region) => (region.play(note, rate), channel.pan + region.pan),
+      };
+      // 等功率声像
+      let angle = (pan.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+      let gains = [angle.cos() * channel.volume, angle.sin() * channel.volume];
+      let first = (note.start * rate).round() as usize;
+      for (frame, sample) in (first..frames).zip(samples) {
+        mix[frame * 2] += sample * MASTER_GAIN * gains[0];
+        mix[frame * 2 + 1] += sample * MASTER_GAIN * gains[1];
+      }
+    }
+  }
+
+  let peak = mix.iter().fold(0.0_f64, |peak, sample| peak.max(sample.abs()));
+  let scale = match peak > 1.0 {
+    true => 1.0 / peak,
+    false => 1.0,
+  };
+  mix.into_iter().map(|sample| (sample * scale) as f32).collect()
+}
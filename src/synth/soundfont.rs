@@ -0,0 +1,505 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use super::SynthNote;
+
+/// 打击乐使用的 bank
+pub const PERCUSSION_BANK: u16 = 128;
+
+/// 生成器的个数
+const GENERATORS: usize = 61;
+
+// 用到的生成器编号，见 SoundFont 2.04 规范 8.1.2
+const START_OFFSET: usize = 0;
+const END_OFFSET: usize = 1;
+const LOOP_START_OFFSET: usize = 2;
+const LOOP_END_OFFSET: usize = 3;
+const START_COARSE_OFFSET: usize = 4;
+const END_COARSE_OFFSET: usize = 12;
+const PAN: usize = 17;
+const DELAY_VOL_ENV: usize = 33;
+const ATTACK_VOL_ENV: usize = 34;
+const HOLD_VOL_ENV: usize = 35;
+const DECAY_VOL_ENV: usize = 36;
+const SUSTAIN_VOL_ENV: usize = 37;
+const RELEASE_VOL_ENV: usize = 38;
+const INSTRUMENT: usize = 41;
+const KEY_RANGE: usize = 43;
+const VEL_RANGE: usize = 44;
+const LOOP_START_COARSE_OFFSET: usize = 45;
+const INITIAL_ATTENUATION: usize = 48;
+const LOOP_END_COARSE_OFFSET: usize = 50;
+const COARSE_TUNE: usize = 51;
+const FINE_TUNE: usize = 52;
+const SAMPLE_ID: usize = 53;
+const SAMPLE_MODES: usize = 54;
+const SCALE_TUNING: usize = 56;
+const OVERRIDING_ROOT_KEY: usize = 58;
+
+/// 包络时间的默认值(timecent)，约为 1 毫秒
+const DEFAULT_TIMECENTS: i32 = -12000;
+
+/// 衰减到 100 dB 即视为静音(centibel)
+const SILENCE_CENTIBELS: f64 = 1000.0;
+
+/// 采样的循环方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopMode {
+  /// 不循环，播放到采样结束
+  None,
+
+  /// 一直循环
+  Continuous,
+
+  /// 按住时循环，松开后播放到采样结束
+  UntilRelease,
+}
+
+/// SoundFont 的音量包络，时间以秒为单位，sustain 为持续阶段的衰减(centibel)
+#[derive(Debug, Clone, Copy)]
+struct VolumeEnvelope {
+  delay: f64,
+  attack: f64,
+  hold: f64,
+  decay: f64,
+  sustain: f64,
+  release: f64,
+}
+
+/// 一个音符所播放的一段采样及其参数
+#[derive(Debug, Clone)]
+pub struct Region {
+  /// 整个 SoundFont 的采样数据
+  data: Rc<[i16]>,
+
+  start: usize,
+  end: usize,
+  loop_start: usize,
+  loop_end: usize,
+  loop_mode: LoopMode,
+
+  /// 采样的采样率
+  sample_rate: f64,
+
+  /// 采样原本的音高
+  root_key: f64,
+
+  /// 额外的音高偏移(音分)
+  tune: f64,
+
+  /// 每个半音对应的音分，通常为 100
+  scale_tuning: f64,
+
+  /// 由初始衰减得到的增益
+  gain: f64,
+
+  /// 声像，-1 为最左，1 为最右
+  pub pan: f64,
+
+  envelope: VolumeEnvelope,
+}
+
+/// 预设中的一个区域，在键和力度的范围内发声
+#[derive(Debug, Clone)]
+struct Zone {
+  keys: (u8, u8),
+  velocities: (u8, u8),
+  region: Region,
+}
+
+/// 已解析的 SoundFont 2 音色库
+#[derive(Debug)]
+pub struct SoundFont {
+  /// 以 (bank, program) 索引的预设
+  presets: BTreeMap<(u16, u8), Vec<Zone>>,
+}
+
+/// 按小端序读取 chunk 内容
+struct Bytes<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    Self{data, pos: 0}
+  }
+
+  fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+    let bytes = self.data.get(self.pos..self.pos + len)
+      .ok_or("unexpected end of soundfont file".to_string())?;
+    self.pos += len;
+    Ok(bytes)
+  }
+
+  fn u8(&mut self) -> Result<u8, String> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16, String> {
+    let bytes = self.take(2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+  }
+
+  fn u32(&mut self) -> Result<u32, String> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  fn tag(&mut self) -> Result<&'a [u8], String> {
+    self.take(4)
+  }
+
+  fn is_empty(&self) -> bool {
+    self.pos >= self.data.len()
+  }
+}
+
+/// RIFF 中的一个 chunk，(id, 内容)
+type Chunk<'a> = (&'a [u8], &'a [u8]);
+
+/// 读取 RIFF 中一个 LIST chunk 包含的子 chunk
+fn sub_chunks(list: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+  let mut bytes = Bytes::new(list);
+  let mut chunks = vec![];
+  while !bytes.is_empty() {
+    let id = bytes.tag()?;
+    let len = bytes.u32()? as usize;
+    chunks.push((id, bytes.take(len)?));
+    // chunk 按偶数字节对齐
+    if len % 2 == 1 && !bytes.is_empty() {
+      bytes.take(1)?;
+    }
+  }
+  Ok(chunks)
+}
+
+/// pdta 中的一个 sample header
+struct SampleHeader {
+  start: u32,
+  end: u32,
+  loop_start: u32,
+  loop_end: u32,
+  sample_rate: u32,
+  original_pitch: u8,
+  pitch_correction: i8,
+}
+
+/// 一个区域的生成器，未设置的为 None
+type Generators = [Option<i32>; GENERATORS];
+
+/// 将 pbag/ibag 和 pgen/igen 展开为每个区域的生成器
+fn zones(bags: &[u8], gens: &[u8], first: usize, last: usize) -> Result<Vec<Generators>, String> {
+  let bag = |index: usize| -> Result<usize, String> {
+    Ok(Bytes::new(bags.get(index * 4..index * 4 + 2).ok_or("invalid soundfont bag index".to_string())?).u16()? as usize)
+  };
+  let mut zones = vec![];
+  for index in first..last {
+    let mut generators = [None; GENERATORS];
+    for gen_index in bag(index)?..bag(index + 1)? {
+      let mut bytes = Bytes::new(gens.get(gen_index * 4..gen_index * 4 + 4).ok_or("invalid soundfont generator index".to_string())?);
+      let oper = bytes.u16()? as usize;
+      let amount = bytes.u16()?;
+      if oper < GENERATORS {
+        // 范围的 amount 为两个字节 (lo, hi)，其余为有符号数
+        generators[oper] = Some(match oper {
+          KEY_RANGE | VEL_RANGE => amount as i32,
+          _ => amount as i16 as i32,
+        });
+      }
+    }
+    zones.push(generators);
+  }
+  Ok(zones)
+}
+
+/// 将全局区域的生成器作为默认值合并到局部区域
+fn with_global(global: Option<&Generators>, local: &Generators) -> Generators {
+  let mut merged = *local;
+  if let Some( global ) = global {
+    for (merged, global) in merged.iter_mut().zip(global) {
+      if merged.is_none() {
+        *merged = *global;
+      }
+    }
+  }
+  merged
+}
+
+/// 键或力度的范围
+fn range(generators: &Generators, oper: usize) -> (u8, u8) {
+  match generators[oper] {
+    Some( amount ) => ((amount & 0xff) as u8, (amount >> 8) as u8),
+    None => (0, 127),
+  }
+}
+
+/// 两个范围的交集
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+  let lo = a.0.max(b.0);
+  let hi = a.1.min(b.1);
+  (lo <= hi).then_some((lo, hi))
+}
+
+/// timecent 表示的时间(秒)
+fn seconds(timecents: i32) -> f64 {
+  2_f64.powf(timecents as f64 / 1200.0)
+}
+
+impl SoundFont {
+  /// 解析 .sf2 文件，只读取 16 位采样、预设、乐器和生成器，忽略调制器
+  pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+    let mut riff = Bytes::new(bytes);
+    if riff.tag()? != b"RIFF" {
+      return Err("not a RIFF file".to_string());
+    }
+    let len = riff.u32()? as usize;
+    let body = riff.take(len.min(bytes.len() - 8))?;
+    let mut body = Bytes::new(body);
+    if body.tag()? != b"sfbk" {
+      return Err("not a soundfont file".to_string());
+    }
+
+    let mut smpl: &[u8] = &[];
+    let mut pdta = BTreeMap::new();
+    for (id, list) in sub_chunks(&body.data[body.pos..])? {
+      if id != b"LIST" || list.len() < 4 {
+        continue;
+      }
+      for (sub_id, chunk) in sub_chunks(&list[4..])? {
+        match (&list[..4], sub_id) {
+          (b"sdta", b"smpl") => smpl = chunk,
+          (b"pdta", _) => { pdta.insert(sub_id, chunk); },
+          _ => {},
+        }
+      }
+    }
+    let chunk = |id: &[u8]| pdta.get(id).copied()
+      .ok_or(format!("missing '{}' chunk in soundfont file", String::from_utf8_lossy(id)));
+    let (phdr, pbag, pgen) = (chunk(b"phdr")?, chunk(b"pbag")?, chunk(b"pgen")?);
+    let (inst, ibag, igen, shdr) = (chunk(b"inst")?, chunk(b"ibag")?, chunk(b"igen")?, chunk(b"shdr")?);
+
+    let data: Rc<[i16]> = smpl.chunks_exact(2)
+      .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+      .collect();
+
+    let mut samples = vec![];
+    let mut bytes = Bytes::new(shdr);
+    while shdr.len() - bytes.pos >= 46 {
+      bytes.take(20)?;
+      let header = SampleHeader {
+        start: bytes.u32()?,
+        end: bytes.u32()?,
+        loop_start: bytes.u32()?,
+        loop_end: bytes.u32()?,
+        sample_rate: bytes.u32()?,
+        original_pitch: bytes.u8()?,
+        pitch_correction: bytes.u8()? as i8,
+      };
+      bytes.take(4)?;
+      samples.push(header);
+    }
+
+    // 每个乐器的区域，第一个区域没有 sampleID 时为全局区域
+    let mut bag_indices = vec![];
+    let mut bytes = Bytes::new(inst);
+    while inst.len() - bytes.pos >= 22 {
+      bytes.take(20)?;
+      bag_indices.push(bytes.u16()? as usize);
+    }
+    let mut instruments = vec![];
+    for pair in bag_indices.windows(2) {
+      let zones = zones(ibag, igen, pair[0], pair[1])?;
+      let (global, locals) = match zones.first() {
+        Some( first ) if first[SAMPLE_ID].is_none() => (Some(first), &zones[1..]),
+        _ => (None, &zones[..]),
+      };
+      let locals: Vec<Generators> = locals.iter()
+        .filter(|local| local[SAMPLE_ID].is_some())
+        .map(|local| with_global(global, local))
+        .collect();
+      instruments.push(locals);
+    }
+
+    // 每个预设的区域，预设的生成器是对乐器生成器的偏移
+    let mut headers = vec![];
+    let mut bytes = Bytes::new(phdr);
+    while phdr.len() - bytes.pos >= 38 {
+      bytes.take(20)?;
+      let program = bytes.u16()?;
+      let bank = bytes.u16()?;
+      let bag_index = bytes.u16()? as usize;
+      bytes.take(12)?;
+      headers.push((bank, program, bag_index));
+    }
+    let mut presets = BTreeMap::new();
+    for pair in headers.windows(2) {
+      let (bank, program, first) = pair[0];
+      let Ok( program ) = u8::try_from(program) else { continue };
+      let zones = zones(pbag, pgen, first, pair[1].2)?;
+      let (global, locals) = match zones.first() {
+        Some( first ) if first[INSTRUMENT].is_none() => (Some(first), &zones[1..]),
+        _ => (None, &zones[..]),
+      };
+
+      let mut preset_zones = vec![];
+      for local in locals {
+        let preset = with_global(global, local);
+        let Some( instrument ) = preset[INSTRUMENT].and_then(|index| instruments.get(index as u16 as usize)) else { continue };
+        for generators in instrument {
+          let Some( keys ) = intersect(range(&preset, KEY_RANGE), range(generators, KEY_RANGE)) else { continue };
+          let Some( velocities ) = intersect(range(&preset, VEL_RANGE), range(generators, VEL_RANGE)) else { continue };
+          let Some( header ) = generators[SAMPLE_ID].and_then(|index| samples.get(index as u16 as usize)) else { continue };
+          if let Some( region ) = Region::new(&data, header, generators, &preset) {
+            preset_zones.push(Zone{keys, velocities, region});
+          }
+        }
+      }
+      presets.insert((bank, program), preset_zones);
+    }
+
+    Ok(Self{presets})
+  }
+
+  /// 某个 bank 和 program 的预设在给定的键和力度上发声的所有采样。
+  /// 预设不存在时依次尝试 bank 0 和 program 0，与常见的 General MIDI 音源相同；都不存在时返回 None
+  pub fn regions(&self, bank: u16, program: u8, key: u8, velocity: u8) -> Option<Vec<Region>> {
+    let zones = self.presets.get(&(bank, program))
+      .or_else(|| self.presets.get(&(0, program)).filter(|_| bank != PERCUSSION_BANK))
+      .or_else(|| self.presets.get(&(bank, 0)))?;
+    Some(zones.iter()
+      .filter(|zone| (zone.keys.0..=zone.keys.1).contains(&key))
+      .filter(|zone| (zone.velocities.0..=zone.velocities.1).contains(&velocity))
+      .map(|zone| zone.region.clone())
+      .collect())
+  }
+}
+
+impl Region {
+  /// 由乐器区域的生成器和预设区域的偏移得到采样的参数，采样超出数据范围时返回 None
+  fn new(data: &Rc<[i16]>, header: &SampleHeader, generators: &Generators, preset: &Generators) -> Option<Self> {
+    let instrument = |oper: usize, default: i32| generators[oper].unwrap_or(default);
+    // 地址偏移只能出现在乐器中，其余生成器加上预设中的偏移
+    let value = |oper: usize, default: i32| instrument(oper, default) + preset[oper].unwrap_or(0);
+    let offset = |fine: usize, coarse: usize| instrument(fine, 0) as i64 + instrument(coarse, 0) as i64 * 32768;
+
+    let address = |base: u32, offset: i64| usize::try_from(base as i64 + offset).ok();
+    let start = address(header.start, offset(START_OFFSET, START_COARSE_OFFSET))?;
+    let end = address(header.end, offset(END_OFFSET, END_COARSE_OFFSET))?.min(data.len());
+    let loop_start = address(header.loop_start, offset(LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET))?;
+    let loop_end = address(header.loop_end, offset(LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET))?;
+    if start >= end {
+      return None;
+    }
+
+    let loop_mode = match instrument(SAMPLE_MODES, 0) & 3 {
+      1 if start <= loop_start && loop_start < loop_end && loop_end <= end => LoopMode::Continuous,
+      3 if start <= loop_start && loop_start < loop_end && loop_end <= end => LoopMode::UntilRelease,
+      _ => LoopMode::None,
+    };
+    let root_key = match instrument(OVERRIDING_ROOT_KEY, -1) {
+      key @ 0..=127 => key,
+      _ if header.original_pitch <= 127 => header.original_pitch as i32,
+      _ => 60,
+    };
+
+    Some(Self {
+      data: data.clone(),
+      start,
+      end,
+      loop_start,
+      loop_end,
+      loop_mode,
+      sample_rate: header.sample_rate.max(1) as f64,
+      root_key: root_key as f64,
+      tune: (value(COARSE_TUNE, 0) * 100 + value(FINE_TUNE, 0) + header.pitch_correction as i32) as f64,
+      scale_tuning: value(SCALE_TUNING, 100) as f64,
+      gain: 10_f64.powf(-value(INITIAL_ATTENUATION, 0).clamp(0, 1440) as f64 / 200.0),
+      pan: value(PAN, 0).clamp(-500, 500) as f64 / 500.0,
+      envelope: VolumeEnvelope {
+        delay: seconds(value(DELAY_VOL_ENV, DEFAULT_TIMECENTS)),
+        attack: seconds(value(ATTACK_VOL_ENV, DEFAULT_TIMECENTS)),
+        hold: seconds(value(HOLD_VOL_ENV, DEFAULT_TIMECENTS)),
+        decay: seconds(value(DECAY_VOL_ENV, DEFAULT_TIMECENTS)),
+        sustain: value(SUSTAIN_VOL_ENV, 0).clamp(0, 1440) as f64,
+        release: seconds(value(RELEASE_VOL_ENV, DEFAULT_TIMECENTS)),
+      },
+    })
+  }
+
+  /// 松开后释放阶段的时间(秒)
+  pub fn release(&self) -> f64 {
+    self.envelope.release
+  }
+
+  /// 以 sample_rate 播放一个音符，返回从音符开始的单声道采样。
+  /// 力度按 SoundFont 默认的调制器取平方，音高由音符的频率相对于采样原本的音高计算
+  pub fn play(&self, note: &SynthNote, sample_rate: f64) -> Vec<f64> {
+    let cents = 1200.0 * (note.frequency / 440.0).log2() + 6900.0;
+    let cents = (cents - self.root_key * 100.0) * self.scale_tuning / 100.0 + self.tune;
+    let step = 2_f64.powf(cents / 1200.0) * self.sample_rate / sample_rate;
+    let gain = self.gain * note.velocity * note.velocity / 32768.0;
+
+    let held = (note.end - note.start).max(0.0);
+    let frames = ((held + self.envelope.release) * sample_rate).ceil() as usize;
+    let release_attenuation = self.envelope.attenuation(held);
+    let mut out = Vec::with_capacity(frames);
+    let mut position = self.start as f64;
+    for frame in 0..frames {
+      let t = frame as f64 / sample_rate;
+      let looping = match self.loop_mode {
+        LoopMode::None => false,
+        LoopMode::Continuous => true,
+        LoopMode::UntilRelease => t < held,
+      };
+      if looping {
+        let length = (self.loop_end - self.loop_start) as f64;
+        while position >= self.loop_end as f64 {
+          position -= length;
+        }
+      } else if position >= (self.end - 1) as f64 {
+        break;
+      }
+
+      let attenuation = match t < held {
+        true => self.envelope.attenuation(t),
+        false => release_attenuation + SILENCE_CENTIBELS * (t - held) / self.envelope.release,
+      };
+      if t >= held && attenuation >= SILENCE_CENTIBELS {
+        break;
+      }
+
+      // 线性插值，循环时最后一个采样之后接循环的开头
+      let index = position as usize;
+      let fraction = position - index as f64;
+      let next = match looping && index + 1 >= self.loop_end {
+        true => self.loop_start,
+        false => (index + 1).min(self.end - 1),
+      };
+      let sample = self.data[index] as f64 * (1.0 - fraction) + self.data[next] as f64 * fraction;
+      out.push(sample * gain * 10_f64.powf(-attenuation / 200.0));
+      position += step;
+    }
+    out
+  }
+}
+
+impl VolumeEnvelope {
+  /// 音符开始 t 秒后、松开之前的衰减(centibel)。
+  /// 起音阶段的振幅线性上升，衰减阶段按分贝线性下降到持续的电平
+  fn attenuation(&self, t: f64) -> f64 {
+    let t = t - self.delay;
+    if t < 0.0 {
+      SILENCE_CENTIBELS
+    } else if t < self.attack {
+      match t > 0.0 {
+        true => (-200.0 * (t / self.attack).log10()).min(SILENCE_CENTIBELS),
+        false => SILENCE_CENTIBELS,
+      }
+    } else if t < self.attack + self.hold {
+      0.0
+    } else {
+      (SILENCE_CENTIBELS * (t - self.attack - self.hold) / self.decay).min(self.sustain)
+    }
+  }
+}
@@ -0,0 +1,87 @@
+/// 立体声
+const CHANNELS: u16 = 2;
+
+/// WAV 的 fmt chunk 中的格式代码：整数 PCM
+const WAVE_FORMAT_PCM: u16 = 1;
+
+/// WAV 的 fmt chunk 中的格式代码：IEEE 浮点数
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// 默认采样率
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// 采样格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+  /// 16 位整数
+  Int16,
+
+  /// 32 位浮点数
+  Float32,
+}
+
+/// 输出 WAV 的采样率和采样格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+  pub sample_rate: u32,
+  pub sample_format: SampleFormat,
+}
+
+impl Default for WavFormat {
+  fn default() -> Self {
+    Self {
+      sample_rate: DEFAULT_SAMPLE_RATE,
+      sample_format: SampleFormat::Int16,
+    }
+  }
+}
+
+/// 将交错的立体声采样编码为 WAV 文件
+pub fn encode(samples: &[f32], format: WavFormat) -> Vec<u8> {
+  let (format_tag, bytes_per_sample) = match format.sample_format {
+    SampleFormat::Int16 => (WAVE_FORMAT_PCM, 2),
+    SampleFormat::Float32 => (WAVE_FORMAT_IEEE_FLOAT, 4),
+  };
+  let data_len = (samples.len() * bytes_per_sample as usize) as u32;
+  let block_align = CHANNELS * bytes_per_sample;
+
+  // 浮点格式的 fmt chunk 带有 cbSize 字段，并且需要 fact chunk
+  let fmt_len: u32 = match format.sample_format {
+    SampleFormat::Int16 => 16,
+    SampleFormat::Float32 => 18,
+  };
+  let fact_len = match format.sample_format {
+    SampleFormat::Int16 => 0,
+    SampleFormat::Float32 => 12,
+  };
+
+  let mut wav = Vec::with_capacity(data_len as usize + 64);
+  wav.extend(b"RIFF");
+  wav.extend((4 + 8 + fmt_len + fact_len + 8 + data_len).to_le_bytes());
+  wav.extend(b"WAVE");
+
+  wav.extend(b"fmt ");
+  wav.extend(fmt_len.to_le_bytes());
+  wav.extend(format_tag.to_le_bytes());
+  wav.extend(CHANNELS.to_le_bytes());
+  wav.extend(format.sample_rate.to_le_bytes());
+  wav.extend((format.sample_rate * block_align as u32).to_le_bytes());
+  wav.extend(block_align.to_le_bytes());
+  wav.extend((bytes_per_sample * 8).to_le_bytes());
+  if format.sample_format == SampleFormat::Float32 {
+    wav.extend(0_u16.to_le_bytes());
+    wav.extend(b"fact");
+    wav.extend(4_u32.to_le_bytes());
+    wav.extend((samples.len() as u32 / CHANNELS as u32).to_le_bytes());
+  }
+
+  wav.extend(b"data");
+  wav.extend(data_len.to_le_bytes());
+  for sample in samples {
+    match format.sample_format {
+      SampleFormat::Int16 => wav.extend(((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16).to_le_bytes()),
+      SampleFormat::Float32 => wav.extend(sample.to_le_bytes()),
+    }
+  }
+  wav
+}
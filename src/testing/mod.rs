@@ -0,0 +1,71 @@
+use crate::error::Error;
+use crate::interpret::Interpreter;
+use crate::interpret::source_map::LineIndex;
+use crate::semantic::Analyzer as SemanticAnalyzer;
+use crate::syntactic::Analyzer as SyntacticAnalyzer;
+
+/// 错误及其在源文件中的位置，行号和列号都从 1 开始，列号按字符计
+#[derive(Debug)]
+pub struct Located {
+  pub line: usize,
+  pub column: usize,
+  pub error: Error,
+}
+
+/// 一个测试的结果
+#[derive(Debug)]
+pub struct TestResult {
+  pub name: String,
+
+  /// 测试定义所在的行号
+  pub line: usize,
+
+  /// 测试失败时断言失败或执行出错的语句的位置及错误，通过时为 None
+  pub failure: Option<Located>,
+}
+
+/// 执行 yam 源文件中以 `test "name" { ... }` 定义的测试。
+/// 正常编译时测试会被跳过，只有这里会检查并执行测试和其中的断言
+pub struct TestRunner {
+  parser: SyntacticAnalyzer,
+}
+
+impl Default for TestRunner {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl TestRunner {
+  pub fn new() -> Self {
+    Self {
+      parser: SyntacticAnalyzer::new(),
+    }
+  }
+
+  /// 检查 source 并依次执行其中的所有测试，返回各个测试的结果。
+  /// 语法错误或语义错误时不执行任何测试，返回该错误及其位置
+  pub fn run(&self, source: &str) -> Result<Vec<TestResult>, Located> {
+    let lines = LineIndex::new(source);
+    let locate = |offset: usize, error: Error| {
+      let (line, column) = lines.position(offset);
+      Located{line, column, error}
+    };
+
+    let comp_unit = self.parser.parse_with_span(source).map_err(|(err, span)| locate(span.start, err))?;
+    let mut semantic_analyzer = SemanticAnalyzer::new();
+    semantic_analyzer.check(&comp_unit)
+      .and_then(|_| semantic_analyzer.tests_check(&comp_unit))
+      .map_err(|err| locate(semantic_analyzer.get_current_span().start, err))?;
+
+    let mut interpreter = Interpreter::new();
+    let results = interpreter.interpret_tests(&comp_unit).into_iter()
+      .map(|outcome| TestResult{
+        name: outcome.name,
+        line: lines.position(outcome.span.start).0,
+        failure: outcome.failure.map(|(err, span)| locate(span.start, err)),
+      })
+      .collect();
+    Ok(results)
+  }
+}
@@ -0,0 +1,121 @@
+pub mod scala;
+
+/// 十二平均律中一个八度的音分数
+pub const OCTAVE_CENTS: f64 = 1200.0;
+
+/// 五限纯律各音级相对主音的频率比
+const JUST_RATIOS: [(f64, f64); 12] = [
+  (1.0, 1.0), (16.0, 15.0), (9.0, 8.0), (6.0, 5.0), (5.0, 4.0), (4.0, 3.0),
+  (45.0, 32.0), (3.0, 2.0), (8.0, 5.0), (5.0, 3.0), (9.0, 5.0), (15.0, 8.0),
+];
+
+/// MIDI Tuning Standard 中 bulk tuning dump 的音律名称长度
+const MTS_NAME_LEN: usize = 16;
+
+/// 频率比对应的音分
+pub fn ratio_to_cents(ratio: f64) -> f64 {
+  OCTAVE_CENTS * ratio.log2()
+}
+
+/// 音律：从 root 开始按音级依次对应到 MIDI 音符，每经过一个周期音高升高 period 音分。
+/// root 的音高与十二平均律相同。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+  /// 各音级相对 root 的音分，第一个总是 0
+  pub degrees: Vec<f64>,
+
+  /// 周期的音分，通常为一个八度 1200
+  pub period: f64,
+
+  /// 音级从这个 MIDI 音符开始计算
+  pub root: i32,
+}
+
+impl Tuning {
+  /// 十二平均律
+  pub fn equal(root: i32) -> Self {
+    Tuning {
+      degrees: (0..12).map(|i| i as f64 * 100.0).collect(),
+      period: OCTAVE_CENTS,
+      root,
+    }
+  }
+
+  /// 以 root 为主音的五限纯律
+  pub fn just(root: i32) -> Self {
+    Tuning {
+      degrees: JUST_RATIOS.iter().map(|(num, den)| ratio_to_cents(num / den)).collect(),
+      period: OCTAVE_CENTS,
+      root,
+    }
+  }
+
+  /// 以 root 为 C 的四分之一音差中全音律，五度链从降 E 到升 G
+  pub fn meantone(root: i32) -> Self {
+    let fifth = ratio_to_cents(1.5) - ratio_to_cents(81.0 / 80.0) / 4.0;
+    let mut degrees = vec![0.0; 12];
+    for k in -3..=8 {
+      let degree = (k * 7i32).rem_euclid(12) as usize;
+      degrees[degree] = (k as f64 * fifth).rem_euclid(OCTAVE_CENTS);
+    }
+    Tuning {
+      degrees,
+      period: OCTAVE_CENTS,
+      root,
+    }
+  }
+
+  /// 与 Scala 的约定相同，scale 为除主音外各音级的音分，最后一个为周期
+  pub fn from_scale(scale: &[f64], root: i32) -> Result<Self, String> {
+    let Some((&period, degrees)) = scale.split_last() else {
+      return Err("scale must have at least one pitch".to_string());
+    };
+    if period <= 0.0 {
+      return Err(format!("period of scale must be positive, but found {period} cents"));
+    }
+    let mut all_degrees = vec![0.0];
+    all_degrees.extend_from_slice(degrees);
+    Ok(Tuning {
+      degrees: all_degrees,
+      period,
+      root,
+    })
+  }
+
+  /// MIDI 音符 note 的音高，以音分为单位，MIDI 音符 0 为 0 音分
+  pub fn pitch(&self, note: i32) -> f64 {
+    let steps = note - self.root;
+    let len = self.degrees.len() as i32;
+    let octave = steps.div_euclid(len);
+    let degree = steps.rem_euclid(len) as usize;
+    self.root as f64 * 100.0 + octave as f64 * self.period + self.degrees[degree]
+  }
+
+  /// 是否与十二平均律完全相同
+  pub fn is_equal(&self) -> bool {
+    (0..128).all(|note| self.pitch(note) == note as f64 * 100.0)
+  }
+
+  /// 生成 MIDI Tuning Standard 的 bulk tuning dump(非实时，tuning program 0)，
+  /// 为 128 个音符分别指定音高
+  pub fn bulk_tuning_dump(&self, name: &str) -> Vec<u8> {
+    let mut data = vec![0x7e, 0x7f, 0x08, 0x01, 0x00];
+    let mut name_bytes: Vec<u8> = name.bytes().filter(|b| (0x20..0x7f).contains(b)).take(MTS_NAME_LEN).collect();
+    name_bytes.resize(MTS_NAME_LEN, b' ');
+    data.extend(name_bytes);
+
+    for note in 0..128 {
+      // 音高以半音及其 1/16384 的小数部分表示
+      let semitones = (self.pitch(note) / 100.0).clamp(0.0, 127.0 + 16383.0 / 16384.0);
+      let semitone = semitones.floor();
+      let fraction = (((semitones - semitone) * 16384.0).round() as u32).min(16383);
+      data.extend([semitone as u8, (fraction >> 7) as u8, (fraction & 0x7f) as u8]);
+    }
+
+    let checksum = data.iter().fold(0, |acc, b| acc ^ b) & 0x7f;
+    let mut dump = vec![0xf0];
+    dump.extend(data);
+    dump.extend([checksum, 0xf7]);
+    dump
+  }
+}
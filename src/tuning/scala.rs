@@ -0,0 +1,45 @@
+/// 解析 Scala 的 .scl 音阶文件，返回除主音外各音级的音分，最后一个为周期。
+/// 以 '!' 开头的行为注释；第一行为描述，第二行为音级个数，之后每行一个音高：
+/// 含小数点的为音分，否则为 3/2 或 2 形式的频率比。
+pub fn parse(text: &str) -> Result<Vec<f64>, String> {
+  let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+
+  // 描述可以为空行
+  lines.next().ok_or("missing description line in scala file".to_string())?;
+
+  let count_line = lines.next().ok_or("missing note count in scala file".to_string())?;
+  let count: usize = first_token(count_line).parse()
+    .map_err(|_| format!("invalid note count '{}' in scala file", count_line.trim()))?;
+
+  let mut pitches = vec![];
+  for _ in 0..count {
+    let line = lines.next().ok_or(format!(
+      "scala file declares {count} notes, but only found {}", pitches.len()
+    ))?;
+    pitches.push(parse_pitch(first_token(line))?);
+  }
+  Ok(pitches)
+}
+
+fn first_token(line: &str) -> &str {
+  line.split_whitespace().next().unwrap_or("")
+}
+
+/// 解析一个音高，返回音分
+fn parse_pitch(token: &str) -> Result<f64, String> {
+  let invalid = || format!("invalid pitch '{token}' in scala file");
+  if token.contains('.') {
+    return token.parse().map_err(|_| invalid());
+  }
+
+  let (num, den) = match token.split_once('/') {
+    Some((num, den)) => (num, den),
+    None => (token, "1"),
+  };
+  let num: f64 = num.parse().map_err(|_| invalid())?;
+  let den: f64 = den.parse().map_err(|_| invalid())?;
+  if num <= 0.0 || den <= 0.0 {
+    return Err(invalid());
+  }
+  Ok(super::ratio_to_cents(num / den))
+}